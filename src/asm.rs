@@ -0,0 +1,633 @@
+//! Assembler for Cowgod's mnemonics, the syntax the disassembler writes.
+//!
+//! ```text
+//! SPEED EQU 2              ; constants
+//! start:                   ; labels
+//!     LD V0, SPEED
+//!     LD I, sprite
+//!     DRW V0, V1, 3
+//!     JP start
+//! sprite:
+//!     db 0x80, 0xc0, 0x80  ; data, dw for 16-bit words
+//! include "more.asm"       ; relative to the including file
+//! ```
+//!
+//! Numbers are decimal or hexadecimal (`0x2a`, `#2a`) or binary (`0b101`);
+//! operands can add and subtract numbers, labels and constants. The program is
+//! assembled for address `0x200`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::instruction::{encode, Instruction};
+use crate::processor::PC_START;
+
+// How deeply includes and constants may nest.
+const MAX_DEPTH: usize = 16;
+
+/// Where and why assembling failed. Lines and columns are counted from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// The source file, if the source was read from one.
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assembles `source` into a ROM. Included files are read relative to the
+/// working directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler::new();
+    assembler.parse(source, None, Path::new(""), 0)?;
+    assembler.emit()
+}
+
+/// Assembles the file at `path` into a ROM.
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Vec<u8>, AsmError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|error| AsmError{
+        file: Some(path.display().to_string()),
+        line: 0,
+        column: 0,
+        message: error.to_string()
+    })?;
+    let mut assembler = Assembler::new();
+    assembler.parse(&source, Some(path), path.parent().unwrap_or(Path::new("")), 0)?;
+    assembler.emit()
+}
+
+#[derive(Clone, Debug)]
+struct Location {
+    file: Option<String>,
+    line: usize,
+    column: usize
+}
+
+impl Location {
+
+    fn at(&self, column: usize) -> Location {
+        Location{ column, ..self.clone() }
+    }
+
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError{ file: self.file.clone(), line: self.line, column: self.column, message: message.into() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Comma,
+    Colon,
+    Plus,
+    Minus,
+    Open,
+    Close
+}
+
+// A token and the column it starts at.
+type Spanned = (Token, usize);
+
+fn tokenize(text: &str, location: &Location) -> Result<Vec<Spanned>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let column = index + 1;
+        let token = match c {
+            ';' => break,
+            c if c.is_whitespace() => {
+                index += 1;
+                continue;
+            },
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '[' => Token::Open,
+            ']' => Token::Close,
+            '"' => {
+                let end = chars[index + 1..].iter().position(|&c| c == '"')
+                    .ok_or_else(|| location.at(column).error("unterminated string"))?;
+                let text = chars[index + 1..index + 1 + end].iter().collect();
+                index += end + 2;
+                tokens.push((Token::Text(text), column));
+                continue;
+            },
+            c if is_word_char(c) => {
+                let end = chars[index..].iter().position(|&c| !is_word_char(c)).unwrap_or(chars.len() - index);
+                let word = chars[index..index + end].iter().collect();
+                index += end;
+                tokens.push((Token::Word(word), column));
+                continue;
+            },
+            c => return Err(location.at(column).error(format!("unexpected character '{}'", c)))
+        };
+        tokens.push((token, column));
+        index += 1;
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '#'
+}
+
+// A sum of numbers and symbols.
+#[derive(Clone, Debug)]
+struct Expr {
+    terms: Vec<(i64, Term)>,
+    column: usize
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Number(i64),
+    Symbol(String, usize)
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Register(u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Value(Expr)
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Instruction { mnemonic: String, operands: Vec<(Operand, usize)> },
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>)
+}
+
+#[derive(Clone, Debug)]
+struct Statement {
+    location: Location,
+    kind: Kind
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    symbols: HashMap<String, (Symbol, Location)>,
+    address: usize
+}
+
+#[derive(Clone, Debug)]
+enum Symbol {
+    Label(usize),
+    Constant(Expr)
+}
+
+impl Assembler {
+
+    fn new() -> Self {
+        Assembler{ statements: Vec::new(), symbols: HashMap::new(), address: PC_START }
+    }
+
+    // First pass: collects the statements, their addresses and the symbols.
+    fn parse(&mut self, source: &str, file: Option<&Path>, directory: &Path, depth: usize) -> Result<(), AsmError> {
+        for (index, text) in source.lines().enumerate() {
+            let location = Location{ file: file.map(|file| file.display().to_string()), line: index + 1, column: 1 };
+            let mut tokens = tokenize(text, &location)?;
+
+            if let [(Token::Word(name), column), (Token::Colon, _), ..] = tokens.as_slice() {
+                let (name, column) = (name.clone(), *column);
+                self.define(name, Symbol::Label(self.address), location.at(column))?;
+                tokens.drain(..2);
+            }
+            let ((first, column), rest) = match tokens.split_first() {
+                Some(((Token::Word(first), column), rest)) => ((first.clone(), *column), rest),
+                Some(((_, column), _)) => return Err(location.at(*column).error("expected an instruction")),
+                None => continue
+            };
+            let location = location.at(column);
+
+            if let Some((Token::Word(equ), _)) = rest.first() {
+                if equ.eq_ignore_ascii_case("equ") {
+                    let expr = parse_expr(&rest[1..], &location.at(column + first.len()))?;
+                    self.define(first, Symbol::Constant(expr), location)?;
+                    continue;
+                }
+            }
+
+            let mnemonic = first.to_ascii_uppercase();
+            let kind = match mnemonic.as_str() {
+                "INCLUDE" => {
+                    let path = match rest {
+                        [(Token::Text(path), _)] => directory.join(path),
+                        _ => return Err(location.error("include expects a quoted file name"))
+                    };
+                    self.include(&path, depth, &location)?;
+                    continue;
+                },
+                "DB" => Kind::Bytes(parse_list(rest, &location)?),
+                "DW" => Kind::Words(parse_list(rest, &location)?),
+                _ => Kind::Instruction{ operands: parse_operands(rest, &location)?, mnemonic }
+            };
+            let size = match &kind {
+                Kind::Bytes(values) => values.len(),
+                Kind::Words(values) => values.len() * 2,
+                Kind::Instruction{ operands, .. } => match operands.as_slice() {
+                    [_, (Operand::Long(_), _)] => 4,
+                    _ => 2
+                }
+            };
+            self.statements.push(Statement{ location, kind });
+            self.address += size;
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, path: &Path, depth: usize, location: &Location) -> Result<(), AsmError> {
+        if depth >= MAX_DEPTH {
+            return Err(location.error("includes are nested too deeply"));
+        }
+        let source = fs::read_to_string(path)
+            .map_err(|error| location.error(format!("{}: {}", path.display(), error)))?;
+        let directory = path.parent().map(PathBuf::from).unwrap_or_default();
+        self.parse(&source, Some(path), &directory, depth + 1)
+    }
+
+    fn define(&mut self, name: String, symbol: Symbol, location: Location) -> Result<(), AsmError> {
+        if register(&name).is_some() || keyword(&name).is_some() {
+            return Err(location.error(format!("'{}' is reserved", name)));
+        }
+        if let Some((_, previous)) = self.symbols.get(&name) {
+            return Err(location.error(format!("'{}' is already defined at line {}", name, previous.line)));
+        }
+        self.symbols.insert(name, (symbol, location));
+        Ok(())
+    }
+
+    // Second pass: resolves the symbols and encodes the statements.
+    fn emit(&self) -> Result<Vec<u8>, AsmError> {
+        let mut rom = Vec::new();
+        for statement in &self.statements {
+            let location = &statement.location;
+            match &statement.kind {
+                Kind::Bytes(values) => {
+                    for value in values {
+                        rom.push(self.value(value, location, -0x80, 0xff)? as u8);
+                    }
+                },
+                Kind::Words(values) => {
+                    for value in values {
+                        rom.extend_from_slice(&(self.value(value, location, -0x8000, 0xffff)? as u16).to_be_bytes());
+                    }
+                },
+                Kind::Instruction{ mnemonic, operands } => {
+                    let (instruction, long) = self.instruction(mnemonic, operands, location)?;
+                    rom.extend_from_slice(&encode(instruction).to_be_bytes());
+                    if let Some(long) = long {
+                        rom.extend_from_slice(&long.to_be_bytes());
+                    }
+                }
+            }
+        }
+        Ok(rom)
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[(Operand, usize)], location: &Location)
+        -> Result<(Instruction, Option<u16>), AsmError>
+    {
+        use Instruction::*;
+        use Operand::*;
+
+        let nibble = |expr: &Expr| self.value(expr, location, 0, 0xf).map(|value| value as u8);
+        let byte = |expr: &Expr| self.value(expr, location, -0x80, 0xff).map(|value| value as u8);
+        let address = |expr: &Expr| self.value(expr, location, 0, 0xfff).map(|value| value as u16);
+
+        let kinds: Vec<&Operand> = operands.iter().map(|(operand, _)| operand).collect();
+        let instruction = match (mnemonic, kinds.as_slice()) {
+            ("CLS", []) => Clear,
+            ("RET", []) => Return,
+            ("SCD", [Value(n)]) => ScrollDown(nibble(n)?),
+            ("SCU", [Value(n)]) => ScrollUp(nibble(n)?),
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => LowRes,
+            ("HIGH", []) => HighRes,
+            ("JP", [Value(nnn)]) => Jump(address(nnn)?),
+            ("JP", [Register(0), Value(nnn)]) => JumpOffset(address(nnn)?),
+            ("CALL", [Value(nnn)]) => Call(address(nnn)?),
+            ("SE", [Register(x), Register(y)]) => SkipIfRegistersEqual{ x: *x, y: *y },
+            ("SE", [Register(x), Value(nn)]) => SkipIfEqual{ x: *x, nn: byte(nn)? },
+            ("SNE", [Register(x), Register(y)]) => SkipIfRegistersNotEqual{ x: *x, y: *y },
+            ("SNE", [Register(x), Value(nn)]) => SkipIfNotEqual{ x: *x, nn: byte(nn)? },
+            ("SAVE", [Register(x), Register(y)]) => SaveRange{ x: *x, y: *y },
+            ("LOAD", [Register(x), Register(y)]) => LoadRange{ x: *x, y: *y },
+            ("LD", [Register(x), Register(y)]) => Move{ x: *x, y: *y },
+            ("LD", [Register(x), Value(nn)]) => Set{ x: *x, nn: byte(nn)? },
+            ("LD", [I, Long(nnnn)]) => {
+                let nnnn = self.value(nnnn, location, 0, 0xffff)? as u16;
+                return Ok((SetILong, Some(nnnn)));
+            },
+            ("LD", [I, Value(nnn)]) => SetI(address(nnn)?),
+            ("LD", [Register(x), Dt]) => GetDelay(*x),
+            ("LD", [Register(x), K]) => WaitKey(*x),
+            ("LD", [Dt, Register(x)]) => SetDelay(*x),
+            ("LD", [St, Register(x)]) => SetSound(*x),
+            ("LD", [F, Register(x)]) => Font(*x),
+            ("LD", [Hf, Register(x)]) => BigFont(*x),
+            ("LD", [B, Register(x)]) => Bcd(*x),
+            ("LD", [IndirectI, Register(x)]) => Store(*x),
+            ("LD", [Register(x), IndirectI]) => Load(*x),
+            ("LD", [R, Register(x)]) => SaveFlags(*x),
+            ("LD", [Register(x), R]) => LoadFlags(*x),
+            ("ADD", [Register(x), Register(y)]) => AddRegisters{ x: *x, y: *y },
+            ("ADD", [Register(x), Value(nn)]) => Add{ x: *x, nn: byte(nn)? },
+            ("ADD", [I, Register(x)]) => AddI(*x),
+            ("OR", [Register(x), Register(y)]) => Or{ x: *x, y: *y },
+            ("AND", [Register(x), Register(y)]) => And{ x: *x, y: *y },
+            ("XOR", [Register(x), Register(y)]) => Xor{ x: *x, y: *y },
+            ("SUB", [Register(x), Register(y)]) => Subtract{ x: *x, y: *y },
+            ("SUBN", [Register(x), Register(y)]) => SubtractReversed{ x: *x, y: *y },
+            ("SHR", [Register(x)]) => ShiftRight{ x: *x, y: *x },
+            ("SHR", [Register(x), Register(y)]) => ShiftRight{ x: *x, y: *y },
+            ("SHL", [Register(x)]) => ShiftLeft{ x: *x, y: *x },
+            ("SHL", [Register(x), Register(y)]) => ShiftLeft{ x: *x, y: *y },
+            ("RND", [Register(x), Value(nn)]) => Random{ x: *x, nn: byte(nn)? },
+            ("DRW", [Register(x), Register(y), Value(n)]) => Draw{ x: *x, y: *y, n: nibble(n)? },
+            ("SKP", [Register(x)]) => SkipIfKey(*x),
+            ("SKNP", [Register(x)]) => SkipIfNotKey(*x),
+            ("PLANE", [Value(n)]) => Plane(nibble(n)?),
+            ("AUDIO", []) => Audio,
+            ("PITCH", [Register(x)]) => Pitch(*x),
+            _ if is_mnemonic(mnemonic) => return Err(location.error(format!("invalid operands for {}", mnemonic))),
+            _ => return Err(location.error(format!("unknown instruction '{}'", mnemonic)))
+        };
+        Ok((instruction, None))
+    }
+
+    // Evaluates `expr` and checks that it lies within `min..=max`.
+    fn value(&self, expr: &Expr, location: &Location, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.evaluate(expr, location, 0)?;
+        if value < min || value > max {
+            return Err(location.at(expr.column).error(format!("value {} is out of range {}..={}", value, min, max)));
+        }
+        Ok(value)
+    }
+
+    fn evaluate(&self, expr: &Expr, location: &Location, depth: usize) -> Result<i64, AsmError> {
+        let mut sum = 0i64;
+        for (sign, term) in &expr.terms {
+            let value = match term {
+                Term::Number(value) => *value,
+                Term::Symbol(name, column) => match self.symbols.get(name) {
+                    Some((Symbol::Label(address), _)) => *address as i64,
+                    Some((Symbol::Constant(_), _)) if depth >= MAX_DEPTH => {
+                        return Err(location.at(*column).error(format!("constant '{}' refers to itself", name)));
+                    },
+                    Some((Symbol::Constant(expr), defined)) => self.evaluate(expr, defined, depth + 1)?,
+                    None => return Err(location.at(*column).error(format!("undefined symbol '{}'", name)))
+                }
+            };
+            sum = sum.saturating_add(sign * value);
+        }
+        Ok(sum)
+    }
+}
+
+fn is_mnemonic(mnemonic: &str) -> bool {
+    [
+        "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE",
+        "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW",
+        "SKP", "SKNP", "PLANE", "AUDIO", "PITCH"
+    ].contains(&mnemonic)
+}
+
+fn register(word: &str) -> Option<u8> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v' | 'V'), Some(digit), None) => digit.to_digit(16).map(|digit| digit as u8),
+        _ => None
+    }
+}
+
+fn keyword(word: &str) -> Option<Operand> {
+    match word.to_ascii_uppercase().as_str() {
+        "I" => Some(Operand::I),
+        "DT" => Some(Operand::Dt),
+        "ST" => Some(Operand::St),
+        "K" => Some(Operand::K),
+        "F" => Some(Operand::F),
+        "HF" => Some(Operand::Hf),
+        "B" => Some(Operand::B),
+        "R" => Some(Operand::R),
+        _ => None
+    }
+}
+
+fn parse_number(word: &str) -> Option<i64> {
+    let lower = word.to_ascii_lowercase();
+    let (digits, radix) = if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('#')) {
+        (hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        (binary, 2)
+    } else {
+        (lower.as_str(), 10)
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
+
+// Splits the tokens at commas, keeping the column each part starts at.
+fn split_commas<'a>(tokens: &'a [Spanned], location: &Location) -> Result<Vec<(&'a [Spanned], usize)>, AsmError> {
+    if tokens.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut column = tokens[0].1;
+    for (index, (token, token_column)) in tokens.iter().enumerate() {
+        if *token == Token::Comma {
+            if index == start {
+                return Err(location.at(*token_column).error("expected an operand"));
+            }
+            parts.push((&tokens[start..index], column));
+            start = index + 1;
+            column = token_column + 1;
+        }
+    }
+    if start == tokens.len() {
+        return Err(location.at(column).error("expected an operand"));
+    }
+    parts.push((&tokens[start..], tokens[start].1));
+    Ok(parts)
+}
+
+fn parse_list(tokens: &[Spanned], location: &Location) -> Result<Vec<Expr>, AsmError> {
+    split_commas(tokens, location)?
+        .into_iter()
+        .map(|(part, column)| parse_expr(part, &location.at(column)))
+        .collect()
+}
+
+fn parse_operands(tokens: &[Spanned], location: &Location) -> Result<Vec<(Operand, usize)>, AsmError> {
+    split_commas(tokens, location)?
+        .into_iter()
+        .map(|(part, column)| {
+            let location = location.at(column);
+            let operand = match part {
+                [(Token::Word(word), _)] if register(word).is_some() => Operand::Register(register(word).unwrap_or(0)),
+                [(Token::Word(word), _)] if keyword(word).is_some() => keyword(word).unwrap_or(Operand::I),
+                [(Token::Open, _), (Token::Word(word), _), (Token::Close, _)] if word.eq_ignore_ascii_case("i") => Operand::IndirectI,
+                [(Token::Word(word), _), rest @ ..] if word.eq_ignore_ascii_case("long") && !rest.is_empty() => {
+                    Operand::Long(parse_expr(rest, &location.at(rest[0].1))?)
+                },
+                _ => Operand::Value(parse_expr(part, &location)?)
+            };
+            Ok((operand, column))
+        })
+        .collect()
+}
+
+fn parse_expr(tokens: &[Spanned], location: &Location) -> Result<Expr, AsmError> {
+    let mut terms = Vec::new();
+    let mut sign = 1;
+    let mut expect_term = true;
+    for (token, column) in tokens {
+        match (token, expect_term) {
+            (Token::Minus, true) => sign = -sign,
+            (Token::Word(word), true) => {
+                let term = match parse_number(word) {
+                    Some(value) => Term::Number(value),
+                    None if word.starts_with(|c: char| c.is_ascii_digit() || c == '#') => {
+                        return Err(location.at(*column).error(format!("invalid number '{}'", word)));
+                    },
+                    None if register(word).is_some() || keyword(word).is_some() => {
+                        return Err(location.at(*column).error(format!("unexpected '{}'", word)));
+                    },
+                    None => Term::Symbol(word.clone(), *column)
+                };
+                terms.push((sign, term));
+                sign = 1;
+                expect_term = false;
+            },
+            (Token::Plus, false) => expect_term = true,
+            (Token::Minus, false) => {
+                sign = -1;
+                expect_term = true;
+            },
+            _ => return Err(location.at(*column).error("unexpected token"))
+        }
+    }
+    if expect_term {
+        let column = tokens.last().map_or(location.column, |(_, column)| column + 1);
+        return Err(location.at(column).error("expected a value"));
+    }
+    Ok(Expr{ terms, column: location.column })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{disassemble, Syntax};
+    use crate::platform::Platform;
+
+    #[test]
+    fn assemble_instructions() {
+        // arrange
+        let source = "
+            CLS
+            LD V5, 0x34     ; comment
+            DRW V0, V1, 5
+            ld i, #2a0
+            LD [I], VA
+            SHR V3
+            SE V1, -1
+            LD I, LONG 0x1234
+        ";
+
+        // act
+        let rom = assemble(source).unwrap();
+
+        // assert
+        assert_eq!(rom, [
+            0x00, 0xe0, 0x65, 0x34, 0xd0, 0x15, 0xa2, 0xa0, 0xfa, 0x55, 0x83, 0x36, 0x31, 0xff,
+            0xf0, 0x00, 0x12, 0x34
+        ]);
+    }
+
+    #[test]
+    fn assemble_labels_constants_and_data() {
+        // arrange
+        let source = "
+            HEIGHT EQU 3
+            OFFSET EQU sprite - start
+            start: LD I, sprite
+            DRW V0, V1, HEIGHT
+            JP start
+            LD V2, OFFSET + 1
+            sprite:
+            db 0b10000000, 0xc0, 128
+            dw start, 0xbeef
+        ";
+
+        // act
+        let rom = assemble(source).unwrap();
+
+        // assert
+        assert_eq!(rom, [
+            0xa2, 0x08, 0xd0, 0x13, 0x12, 0x00, 0x62, 0x09,
+            0x80, 0xc0, 0x80, 0x02, 0x00, 0xbe, 0xef
+        ]);
+    }
+
+    #[test]
+    fn errors_have_line_and_column() {
+        let error = |source: &str| assemble(source).unwrap_err();
+
+        assert_eq!(error("CLS\n  LD V0, missing").to_string(), "2:10: undefined symbol 'missing'");
+        assert_eq!(error("FOO V1").to_string(), "1:1: unknown instruction 'FOO'");
+        assert_eq!(error("  LD DT, 5").to_string(), "1:3: invalid operands for LD");
+        assert_eq!(error("DRW V0, V1, 16"), AsmError{ file: None, line: 1, column: 13, message: "value 16 is out of range 0..=15".to_string() });
+        assert_eq!(error("a:\na: CLS").to_string(), "2:1: 'a' is already defined at line 1");
+        assert_eq!(error("JP 0x12g").to_string(), "1:4: invalid number '0x12g'");
+        assert_eq!(error("LD V0,").to_string(), "1:7: expected an operand");
+    }
+
+    #[test]
+    fn listing_reassembles_to_same_rom() {
+        // arrange
+        // 200: jump over data, 202: data, 204: A202, 206: 220B call, 208: 1208 halt,
+        // 20A: data, 20B: F000 0300 at an odd address on XO-CHIP, 20F: 00EE
+        let rom = [
+            0x12, 0x04, 0xf0, 0x90, 0xa2, 0x02, 0x22, 0x0b, 0x12, 0x08, 0xff,
+            0xf0, 0x00, 0x03, 0x00, 0x00, 0xee
+        ];
+        let listing = disassemble(&rom, Platform::XoChip).listing(Syntax::Cowgod);
+
+        // act
+        let assembled = assemble(&listing).unwrap();
+
+        // assert
+        assert_eq!(assembled, rom);
+    }
+}
@@ -0,0 +1,269 @@
+//! Audio output for the buzzer.
+//!
+//! A [`SquareWave`] turns the processor's [`SoundState`] into PCM samples,
+//! which are handed to an [`AudioSink`] such as a [`WavWriter`]. XO-CHIP
+//! audio patterns are played back instead of the square wave once loaded.
+
+use crate::processor::{SoundState, DEFAULT_PITCH};
+
+/// Default sample rate in Hz.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+/// Default buzzer frequency in Hz.
+pub const DEFAULT_FREQUENCY: f32 = 440.0;
+const FRAMES_PER_SECOND: f64 = 60.0;
+
+/// Receives mono PCM samples in the range -1.0 to 1.0.
+pub trait AudioSink {
+    fn write(&mut self, samples: &[f32]);
+}
+
+impl AudioSink for Vec<f32> {
+    fn write(&mut self, samples: &[f32]) {
+        self.extend_from_slice(samples);
+    }
+}
+
+/// Square wave generator for the buzzer.
+pub struct SquareWave {
+    sample_rate: u32,
+    frequency: f32,
+    amplitude: f32,
+    phase: f32,
+    // Fraction of a sample carried over between frames, so that sample
+    // rates not divisible by 60 produce the right number of samples.
+    frame_remainder: f64
+}
+
+impl SquareWave {
+
+    pub fn new(sample_rate: u32) -> Self {
+        SquareWave{
+            sample_rate,
+            frequency: DEFAULT_FREQUENCY,
+            amplitude: 0.25,
+            phase: 0.0,
+            frame_remainder: 0.0
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+    }
+
+    /// Sets the peak amplitude, between 0.0 and 1.0.
+    pub fn set_amplitude(&mut self, amplitude: f32) {
+        self.amplitude = amplitude.clamp(0.0, 1.0);
+    }
+
+    /// Fills `out` with the wave while `active`, and with silence otherwise.
+    pub fn fill(&mut self, active: bool, out: &mut [f32]) {
+        if !active {
+            self.phase = 0.0;
+            out.iter_mut().for_each(|sample| *sample = 0.0);
+            return;
+        }
+
+        let step = self.frequency / self.sample_rate as f32;
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 { self.amplitude } else { -self.amplitude };
+            self.phase = (self.phase + step).fract();
+        }
+    }
+
+    /// Fills `out` for the given sound state: the XO-CHIP audio pattern at its
+    /// pitch if one was loaded, and the square wave otherwise.
+    pub fn fill_sound(&mut self, sound: &SoundState, out: &mut [f32]) {
+        let pattern = match sound.pattern {
+            Some(pattern) if sound.active => pattern,
+            _ => return self.fill(sound.active, out)
+        };
+
+        // The phase runs once through all 128 bits of the pattern.
+        let step = pattern_rate(sound.pitch) / 128.0 / self.sample_rate as f32;
+        for sample in out.iter_mut() {
+            let bit = ((self.phase * 128.0) as usize).min(127);
+            let set = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            *sample = if set { self.amplitude } else { -self.amplitude };
+            self.phase = (self.phase + step).fract();
+        }
+    }
+
+    /// Writes the samples for one 60 Hz frame of the given sound state to the sink.
+    pub fn render_frame(&mut self, sound: SoundState, sink: &mut dyn AudioSink) {
+        let samples = self.sample_rate as f64 / FRAMES_PER_SECOND + self.frame_remainder;
+        let count = samples.floor();
+        self.frame_remainder = samples - count;
+
+        let mut buffer = vec![0.0; count as usize];
+        self.fill_sound(&sound, &mut buffer);
+        sink.write(&buffer);
+    }
+}
+
+/// Playback rate of an XO-CHIP audio pattern in bits per second: 4000 at the
+/// default pitch of 64, doubling every 48 steps.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+}
+
+impl Default for SquareWave {
+    fn default() -> Self {
+        SquareWave::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+/// Collects samples and encodes them as a 16-bit mono PCM WAV file.
+pub struct WavWriter {
+    sample_rate: u32,
+    samples: Vec<i16>
+}
+
+impl WavWriter {
+
+    pub fn new(sample_rate: u32) -> Self {
+        WavWriter{ sample_rate, samples: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Encodes the collected samples as a RIFF WAVE file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let data_len = (self.samples.len() * 2) as u32;
+        let mut out = Vec::with_capacity(44 + data_len as usize);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        out.extend_from_slice(&1u16.to_le_bytes()); // mono
+        out.extend_from_slice(&self.sample_rate.to_le_bytes());
+        out.extend_from_slice(&(self.sample_rate * 2).to_le_bytes()); // byte rate
+        out.extend_from_slice(&2u16.to_le_bytes()); // block align
+        out.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for sample in &self.samples {
+            out.extend_from_slice(&sample.to_le_bytes());
+        }
+        out
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn write(&mut self, samples: &[f32]) {
+        self.samples.extend(samples.iter().map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACTIVE: SoundState = SoundState{ active: true, remaining_frames: 1, pattern: None, pitch: DEFAULT_PITCH };
+    const SILENT: SoundState = SoundState{ active: false, remaining_frames: 0, pattern: None, pitch: DEFAULT_PITCH };
+
+    #[test]
+    fn fill_generates_square_wave() {
+        // arrange
+        let mut wave = SquareWave::new(8);
+        wave.set_frequency(2.0);
+        wave.set_amplitude(0.5);
+        let mut out = [0.0; 8];
+
+        // act
+        wave.fill(true, &mut out);
+
+        // assert
+        assert_eq!(out, [0.5, 0.5, -0.5, -0.5, 0.5, 0.5, -0.5, -0.5]);
+    }
+
+    #[test]
+    fn fill_inactive_is_silent() {
+        // arrange
+        let mut wave = SquareWave::new(8);
+        let mut out = [1.0; 4];
+
+        // act
+        wave.fill(false, &mut out);
+
+        // assert
+        assert_eq!(out, [0.0; 4]);
+    }
+
+    #[test]
+    fn fill_sound_plays_pattern() {
+        // arrange
+        let mut wave = SquareWave::new(4000);
+        wave.set_amplitude(0.5);
+        let mut pattern = [0; 16];
+        pattern[0] = 0xf0;
+        let sound = SoundState{ pattern: Some(pattern), ..ACTIVE };
+        let mut out = [0.0; 10];
+
+        // act
+        wave.fill_sound(&sound, &mut out);
+
+        // assert
+        assert_eq!(out, [0.5, 0.5, 0.5, 0.5, -0.5, -0.5, -0.5, -0.5, -0.5, -0.5]);
+    }
+
+    #[test]
+    fn pattern_rate_doubles_every_48_steps() {
+        assert_eq!(pattern_rate(DEFAULT_PITCH), 4000.0);
+        assert_eq!(pattern_rate(DEFAULT_PITCH + 48), 8000.0);
+        assert_eq!(pattern_rate(DEFAULT_PITCH - 48), 2000.0);
+    }
+
+    #[test]
+    fn render_frame_carries_fractional_samples() {
+        // arrange
+        let mut wave = SquareWave::new(100);
+        let mut samples = Vec::new();
+
+        // act
+        for _ in 0..3 {
+            wave.render_frame(ACTIVE, &mut samples);
+        }
+
+        // assert
+        assert_eq!(samples.len(), 5);
+    }
+
+    #[test]
+    fn wav_writer_encodes_header_and_samples() {
+        // arrange
+        let mut wav = WavWriter::new(DEFAULT_SAMPLE_RATE);
+        let mut wave = SquareWave::default();
+        wave.set_amplitude(1.0);
+
+        // act
+        wave.render_frame(ACTIVE, &mut wav);
+        wave.render_frame(SILENT, &mut wav);
+        let bytes = wav.to_bytes();
+
+        // assert
+        assert_eq!(wav.len(), 2 * 735);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[24..28], &DEFAULT_SAMPLE_RATE.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(bytes.len(), 44 + 2 * 2 * 735);
+        assert_eq!(&bytes[44..46], &i16::MAX.to_le_bytes());
+        assert_eq!(&bytes[bytes.len() - 2..], &[0, 0]);
+    }
+}
@@ -0,0 +1,579 @@
+//! Native frontend for the emulator.
+//!
+//! `chip8 run <rom>` runs a ROM in the terminal and renders the display with
+//! half-block characters, two pixel rows per text line. With `--persistence`
+//! pixels stay lit while the renderer's fading image is bright enough.
+//!
+//! `chip8 headless <rom>` runs a ROM for a fixed number of frames without a
+//! display and writes the final screen as ASCII art or PBM. With `--trace <file>`
+//! it also writes a line per executed instruction, with `--profile <file>` a
+//! listing annotated with execution counts and with `--flamegraph <file>` the
+//! cycles per call stack.
+//!
+//! `chip8 disasm <rom>` prints a listing of the ROM in Cowgod's or Octo's syntax,
+//! which `chip8 asm <source>` assembles back into a ROM.
+//!
+//! `chip8 gdb <rom>` waits for a GDB connection on a local port and lets the
+//! debugger run the ROM without a display.
+//!
+//! `chip8 run <rom> --record <movie>` records the key presses to a movie file
+//! that `chip8 headless <rom> --movie <movie>` replays.
+
+use std::error::Error;
+use std::io::{self, BufWriter, Stdout, Write};
+#[cfg(feature = "gdb")]
+use std::net::TcpListener;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{env, fs, process, thread};
+
+use chip_8_wasm::asm;
+#[cfg(feature = "gdb")]
+use chip_8_wasm::debugger::Debugger;
+use chip_8_wasm::disasm::{self, Syntax};
+#[cfg(feature = "gdb")]
+use chip_8_wasm::gdb::GdbStub;
+use chip_8_wasm::headless::{self, ScreenFormat, ScriptedKey};
+use chip_8_wasm::movie::{Movie, Recorder};
+use chip_8_wasm::platform::Platform;
+use chip_8_wasm::processor::Processor;
+use chip_8_wasm::profiler::Profiler;
+use chip_8_wasm::quirks::Quirks;
+use chip_8_wasm::render::{Palette, Persistence, Renderer};
+use chip_8_wasm::timing::Timing;
+use chip_8_wasm::trace::{TraceFilter, Tracer, WriteSink};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, queue, style, terminal};
+
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 15;
+#[cfg(feature = "gdb")]
+const DEFAULT_GDB_PORT: u16 = 1234;
+// Most terminals only report key presses. Without release events a key is
+// treated as held until no press or auto-repeat arrived for this long.
+const KEY_HOLD_DURATION: Duration = Duration::from_millis(150);
+// Half-block characters are either lit or not, so a rendered pixel is drawn
+// while it is at least this bright.
+const LIT_THRESHOLD: u8 = 0x40;
+
+const USAGE: &str = "\
+Usage:
+  chip8 run <rom> [--ipf <instructions per frame>] [--platform <platform>] [--quirks <preset>]
+                 [--timing instructions|vip] [--persistence off|decay:<f>|max:<n>]
+                 [--seed <n>] [--record <movie>]
+  chip8 headless <rom> --frames <n> [--ipf <instructions per frame>]
+                 [--platform <platform>] [--quirks <preset>] [--timing instructions|vip] [--seed <n>]
+                 [--key <frame>:<key><+|->]... [--format ascii|pbm] [--output <file>]
+                 [--trace <file> [--trace-range <start>-<end>] [--trace-class <digit>,...]]
+                 [--profile <listing>] [--flamegraph <folded stacks>]
+  chip8 headless <rom> --movie <movie> [--frames <n>] [--format ascii|pbm] [--output <file>]
+  chip8 disasm <rom> [--platform <platform>] [--syntax cowgod|octo]
+  chip8 asm <source> [--output <rom>]
+  chip8 gdb <rom> [--port <port>] [--ipf <instructions per frame>] [--platform <platform>]
+                 [--quirks <preset>] [--seed <n>]
+
+Platforms: chip8, schip, xochip
+Quirks presets: default, vip, chip48, schip, xochip (default: the platform's)
+Timing: instructions runs --ipf instructions per frame, vip as many as the COSMAC VIP
+        would (default: instructions)
+Persistence: off, decay:<f> fades switched off pixels by the factor f per frame,
+             max:<n> keeps them lit for n frames (default: off)
+
+Keys:
+  1 2 3 4      1 2 3 C
+  q w e r  ->  4 5 6 D
+  a s d f      7 8 9 E
+  y x c v      A 0 B F
+  Esc quits.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("run") => run(&args[1..]),
+        Some("headless") => run_headless(&args[1..]),
+        Some("disasm") => run_disasm(&args[1..]),
+        Some("asm") => run_asm(&args[1..]),
+        #[cfg(feature = "gdb")]
+        Some("gdb") => run_gdb(&args[1..]),
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        _ => Err(USAGE.into())
+    };
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut rom_path = None;
+    let mut instructions_per_frame = DEFAULT_INSTRUCTIONS_PER_FRAME;
+    let mut platform = Platform::default();
+    let mut quirks: Option<Quirks> = None;
+    let mut timing = Timing::default();
+    let mut persistence = Persistence::default();
+    let mut seed = None;
+    let mut record = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ipf" => {
+                let value = args.next().ok_or("--ipf expects a value")?;
+                instructions_per_frame = value.parse()
+                    .map_err(|_| format!("invalid instructions per frame: {}", value))?;
+            },
+            "--platform" => platform = args.next().ok_or("--platform expects a value")?.parse()?,
+            "--quirks" => quirks = Some(args.next().ok_or("--quirks expects a value")?.parse()?),
+            "--timing" => timing = args.next().ok_or("--timing expects a value")?.parse()?,
+            "--persistence" => persistence = args.next().ok_or("--persistence expects a value")?.parse()?,
+            "--seed" => seed = Some(parse_seed(args.next().ok_or("--seed expects a value")?)?),
+            "--record" => record = Some(args.next().ok_or("--record expects a value")?),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(format!("unexpected argument: {}\n\n{}", arg, USAGE).into())
+        }
+    }
+
+    let rom_path = rom_path.ok_or(USAGE)?;
+    // Movies replay a fixed number of instructions per frame.
+    if record.is_some() && timing != Timing::Instructions {
+        return Err("--record needs --timing instructions".into());
+    }
+    let rom = fs::read(rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+    let quirks = quirks.unwrap_or_else(|| platform.default_quirks());
+    let mut processor = Processor::with_platform(&rom, platform, quirks);
+    processor.set_timing(timing);
+    // A movie needs a known seed to replay the random numbers.
+    let mut recorder = record.map(|_| {
+        Recorder::start(&mut processor, &rom, seed.unwrap_or_else(rand::random), instructions_per_frame)
+    });
+    if let (Some(seed), None) = (seed, &recorder) {
+        processor.seed_random(seed);
+    }
+
+    let mut terminal = Terminal::open()?;
+    let mut keypad = Keypad::new(terminal.reports_key_release);
+    let mut renderer = Renderer::new(Palette::MONOCHROME, 1);
+    renderer.set_persistence(persistence);
+    let mut screen = read_screen(&mut renderer, &processor);
+    let mut status = status_line(&processor);
+    terminal.draw(&screen, &status)?;
+
+    'running: loop {
+        let frame_start = Instant::now();
+
+        {
+            let mut set_key = |key, pressed| match recorder.as_mut() {
+                Some(recorder) => recorder.set_key(&mut processor, key, pressed),
+                None => processor.set_key(key, pressed)
+            };
+            while event::poll(Duration::ZERO)? {
+                if let Event::Key(key) = event::read()? {
+                    if is_quit(&key) {
+                        break 'running;
+                    }
+                    keypad.handle(&key, &mut set_key);
+                }
+            }
+            keypad.release_expired(&mut set_key);
+        }
+
+        // A failing instruction halts the processor, the status line shows why.
+        let _ = match recorder.as_mut() {
+            Some(recorder) => recorder.run_frame(&mut processor),
+            None => processor.run_frame(instructions_per_frame)
+        };
+
+        let current = read_screen(&mut renderer, &processor);
+        let current_status = status_line(&processor);
+        if current != screen || current_status != status {
+            if current.len() != screen.len() {
+                terminal.clear()?;
+            }
+            screen = current;
+            status = current_status;
+            terminal.draw(&screen, &status)?;
+        }
+
+        if let Some(remaining) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+
+    drop(terminal);
+    if let (Some(path), Some(recorder)) = (record, recorder) {
+        fs::write(path, recorder.finish().to_string()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn run_headless(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut rom_path = None;
+    let mut frames = None;
+    let mut instructions_per_frame = DEFAULT_INSTRUCTIONS_PER_FRAME;
+    let mut script = Vec::new();
+    let mut platform = Platform::default();
+    let mut quirks: Option<Quirks> = None;
+    let mut timing = None;
+    let mut format = ScreenFormat::Ascii;
+    let mut output = None;
+    let mut seed = None;
+    let mut movie = None;
+    let mut trace = None;
+    let mut trace_filter = TraceFilter::ALL;
+    let mut profile = None;
+    let mut flamegraph = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} expects a value", arg));
+        match arg.as_str() {
+            "--frames" => {
+                let value = value()?;
+                frames = Some(value.parse().map_err(|_| format!("invalid frame count: {}", value))?);
+            },
+            "--ipf" => {
+                let value = value()?;
+                instructions_per_frame = value.parse()
+                    .map_err(|_| format!("invalid instructions per frame: {}", value))?;
+            },
+            "--platform" => platform = value()?.parse()?,
+            "--quirks" => quirks = Some(value()?.parse()?),
+            "--timing" => timing = Some(value()?.parse::<Timing>()?),
+            "--seed" => seed = Some(parse_seed(value()?)?),
+            "--key" => script.push(value()?.parse::<ScriptedKey>()?),
+            "--movie" => movie = Some(value()?),
+            "--format" => format = value()?.parse()?,
+            "--output" => output = Some(value()?),
+            "--trace" => trace = Some(value()?),
+            "--trace-range" => trace_filter.addresses = parse_address_range(value()?)?,
+            "--trace-class" => trace_filter.classes = parse_opcode_classes(value()?)?,
+            "--profile" => profile = Some(value()?),
+            "--flamegraph" => flamegraph = Some(value()?),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(format!("unexpected argument: {}\n\n{}", arg, USAGE).into())
+        }
+    }
+
+    let rom_path = rom_path.ok_or(USAGE)?;
+    let rom = fs::read(rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+
+    let mut processor = match movie {
+        // The movie's settings replace --ipf, --platform, --quirks, --seed and --key.
+        Some(_) if timing.is_some() => return Err("--timing cannot be used with --movie".into()),
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let movie: Movie = text.parse()?;
+            frames = frames.or(Some(movie.frames));
+            instructions_per_frame = movie.instructions_per_frame;
+            script = movie.events.clone();
+            movie.start(&rom)?
+        },
+        None => {
+            let quirks = quirks.unwrap_or_else(|| platform.default_quirks());
+            let mut processor = Processor::with_platform(&rom, platform, quirks);
+            processor.set_timing(timing.unwrap_or_default());
+            if let Some(seed) = seed {
+                processor.seed_random(seed);
+            }
+            processor
+        }
+    };
+    let frames = frames.ok_or("--frames is required")?;
+    let profiling = profile.is_some() || flamegraph.is_some();
+    match trace {
+        Some(_) if profiling => return Err("--trace cannot be combined with --profile or --flamegraph".into()),
+        Some(path) => {
+            let file = fs::File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+            let mut tracer = Tracer::new(WriteSink::new(BufWriter::new(file)), trace_filter);
+            let result = headless::run_with(&mut processor, frames, &script, |processor| {
+                tracer.run_frame(processor, instructions_per_frame)
+            });
+            // Keep the trace up to a failing instruction.
+            tracer.into_sink().finish().map_err(|e| format!("{}: {}", path, e))?;
+            result?;
+        },
+        None if profiling => {
+            let mut profiler = Profiler::new();
+            let result = headless::run_with(&mut processor, frames, &script, |processor| {
+                profiler.run_frame(processor, instructions_per_frame)
+            });
+            if let Some(path) = profile {
+                let disassembly = disasm::disassemble(&rom, processor.platform());
+                fs::write(path, profiler.annotated_listing(&disassembly, Syntax::Cowgod))
+                    .map_err(|e| format!("{}: {}", path, e))?;
+            }
+            if let Some(path) = flamegraph {
+                fs::write(path, profiler.folded()).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            result?;
+        },
+        None => headless::run(&mut processor, frames, instructions_per_frame, &script)?
+    }
+    let screen = headless::dump_screen(&processor, format);
+    match output {
+        Some(path) => fs::write(path, screen).map_err(|e| format!("{}: {}", path, e))?,
+        None => print!("{}", screen)
+    }
+    Ok(())
+}
+
+fn run_disasm(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut rom_path = None;
+    let mut platform = Platform::default();
+    let mut syntax = Syntax::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} expects a value", arg));
+        match arg.as_str() {
+            "--platform" => platform = value()?.parse()?,
+            "--syntax" => syntax = value()?.parse()?,
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(format!("unexpected argument: {}\n\n{}", arg, USAGE).into())
+        }
+    }
+
+    let rom_path = rom_path.ok_or(USAGE)?;
+    let rom = fs::read(rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+    print!("{}", disasm::disassemble(&rom, platform).listing(syntax));
+    Ok(())
+}
+
+fn run_asm(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut source_path = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = Some(PathBuf::from(args.next().ok_or("--output expects a value")?)),
+            _ if source_path.is_none() => source_path = Some(Path::new(arg)),
+            _ => return Err(format!("unexpected argument: {}\n\n{}", arg, USAGE).into())
+        }
+    }
+
+    let source_path = source_path.ok_or(USAGE)?;
+    let rom = asm::assemble_file(source_path)?;
+    // Writes next to the source by default, e.g. game.asm to game.ch8.
+    let output = output.unwrap_or_else(|| source_path.with_extension("ch8"));
+    fs::write(&output, rom).map_err(|e| format!("{}: {}", output.display(), e))?;
+    Ok(())
+}
+
+#[cfg(feature = "gdb")]
+fn run_gdb(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut rom_path = None;
+    let mut port = DEFAULT_GDB_PORT;
+    let mut instructions_per_frame = DEFAULT_INSTRUCTIONS_PER_FRAME;
+    let mut platform = Platform::default();
+    let mut quirks: Option<Quirks> = None;
+    let mut seed = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} expects a value", arg));
+        match arg.as_str() {
+            "--port" => {
+                let value = value()?;
+                port = value.parse().map_err(|_| format!("invalid port: {}", value))?;
+            },
+            "--ipf" => {
+                let value = value()?;
+                instructions_per_frame = value.parse()
+                    .map_err(|_| format!("invalid instructions per frame: {}", value))?;
+            },
+            "--platform" => platform = value()?.parse()?,
+            "--quirks" => quirks = Some(value()?.parse()?),
+            "--seed" => seed = Some(parse_seed(value()?)?),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(format!("unexpected argument: {}\n\n{}", arg, USAGE).into())
+        }
+    }
+
+    let rom_path = rom_path.ok_or(USAGE)?;
+    let rom = fs::read(rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+    let quirks = quirks.unwrap_or_else(|| platform.default_quirks());
+    let mut processor = Processor::with_platform(&rom, platform, quirks);
+    if let Some(seed) = seed {
+        processor.seed_random(seed);
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Waiting for GDB on 127.0.0.1:{}, e.g. `target remote :{}`", port, port);
+    let (stream, address) = listener.accept()?;
+    eprintln!("GDB connected from {}", address);
+    GdbStub::new(Debugger::new(processor, instructions_per_frame)).serve(stream)?;
+    Ok(())
+}
+
+// `200-2ff`, both ends included.
+fn parse_address_range(value: &str) -> Result<Range<usize>, String> {
+    let invalid = || format!("invalid address range: {}, expected e.g. 200-2ff", value);
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+    let start = usize::from_str_radix(start, 16).map_err(|_| invalid())?;
+    let end = usize::from_str_radix(end, 16).map_err(|_| invalid())?;
+    Ok(start..end + 1)
+}
+
+// The first hex digits of the traced opcodes, e.g. `2,d`.
+fn parse_opcode_classes(value: &str) -> Result<u16, String> {
+    value.split(',').try_fold(0, |classes, digit| match u8::from_str_radix(digit.trim(), 16) {
+        Ok(class) if digit.trim().len() == 1 => Ok(classes | 1 << class),
+        _ => Err(format!("invalid opcode class: {}, expected a hex digit", digit))
+    })
+}
+
+fn parse_seed(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("invalid seed: {}", value))
+}
+
+fn is_quit(key: &KeyEvent) -> bool {
+    key.kind != KeyEventKind::Release && (key.code == KeyCode::Esc
+        || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)))
+}
+
+fn status_line(processor: &Processor) -> String {
+    match processor.error() {
+        Some(error) => format!("Halted: {}. Esc quits.", error),
+        None if processor.is_halted() => "Halted. Esc quits.".to_string(),
+        None => "Running. Esc quits.".to_string()
+    }
+}
+
+// Renders the display as the next frame and reads it as rows of lit pixels.
+fn read_screen(renderer: &mut Renderer, processor: &Processor) -> Vec<Vec<bool>> {
+    renderer.render(processor.display())
+        .chunks_exact(processor.screen_width() * 4)
+        .map(|row| row.chunks_exact(4).map(|pixel| pixel[..3].iter().any(|&c| c >= LIT_THRESHOLD)).collect())
+        .collect()
+}
+
+// Maps the keyboard to the hex keypad, same layout as `key_map` in web/index.js.
+fn key_map(c: char) -> Option<usize> {
+    match c.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xc),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xd),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xe),
+        'y' => Some(0xa),
+        'x' => Some(0x0),
+        'c' => Some(0xb),
+        'v' => Some(0xf),
+        _ => None
+    }
+}
+
+struct Keypad {
+    reports_key_release: bool,
+    last_pressed: [Option<Instant>; 16]
+}
+
+impl Keypad {
+
+    fn new(reports_key_release: bool) -> Self {
+        Keypad{ reports_key_release, last_pressed: [None; 16] }
+    }
+
+    fn handle(&mut self, event: &KeyEvent, set_key: &mut impl FnMut(usize, bool)) {
+        let key = match event.code {
+            KeyCode::Char(c) => key_map(c),
+            _ => None
+        };
+        if let Some(key) = key {
+            if event.kind == KeyEventKind::Release {
+                self.last_pressed[key] = None;
+                set_key(key, false);
+            } else {
+                self.last_pressed[key] = Some(Instant::now());
+                set_key(key, true);
+            }
+        }
+    }
+
+    fn release_expired(&mut self, set_key: &mut impl FnMut(usize, bool)) {
+        if self.reports_key_release {
+            return;
+        }
+        for (key, pressed) in self.last_pressed.iter_mut().enumerate() {
+            if pressed.is_some_and(|at| at.elapsed() >= KEY_HOLD_DURATION) {
+                *pressed = None;
+                set_key(key, false);
+            }
+        }
+    }
+}
+
+// Puts the terminal into raw mode on an alternate screen and restores it on drop.
+struct Terminal {
+    stdout: Stdout,
+    reports_key_release: bool
+}
+
+impl Terminal {
+
+    fn open() -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        queue!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let reports_key_release = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_key_release {
+            queue!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+        stdout.flush()?;
+
+        Ok(Terminal{ stdout, reports_key_release })
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        queue!(self.stdout, terminal::Clear(terminal::ClearType::All))
+    }
+
+    fn draw(&mut self, screen: &[Vec<bool>], status: &str) -> io::Result<()> {
+        for (line, rows) in screen.chunks(2).enumerate() {
+            let text: String = (0..rows[0].len())
+                .map(|x| match (rows[0][x], rows[1][x]) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' '
+                })
+                .collect();
+            queue!(self.stdout, cursor::MoveTo(0, line as u16), style::Print(text))?;
+        }
+
+        queue!(
+            self.stdout,
+            cursor::MoveTo(0, (screen.len() / 2) as u16),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            style::Print(status))?;
+        self.stdout.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.reports_key_release {
+            let _ = queue!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = self.stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
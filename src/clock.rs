@@ -0,0 +1,70 @@
+//! Wall-clock adapter for hosts that do not drive the processor frame by frame.
+//!
+//! Prefer [`Processor::run_frame`], which is deterministic. [`WallClock`]
+//! ticks the timers whenever a 60 Hz period of real time has passed, so the
+//! emulation speed depends on how often the host calls it.
+
+use std::time::Duration;
+
+use instant::Instant;
+
+use crate::error::Chip8Error;
+use crate::processor::{Processor, StepOutcome};
+
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+
+pub struct WallClock {
+    last_frame: Instant
+}
+
+impl WallClock {
+
+    pub fn new() -> Self {
+        WallClock{ last_frame: Instant::now() }
+    }
+
+    /// Ticks the processor's timers once for every 60 Hz period elapsed since
+    /// the last update and returns the number of ticks.
+    pub fn update(&mut self, processor: &mut Processor) -> u32 {
+        let mut ticks = 0;
+        while self.last_frame.elapsed() >= FRAME_DURATION {
+            processor.timer_tick();
+            self.last_frame += FRAME_DURATION;
+            ticks += 1;
+        }
+        ticks
+    }
+
+    /// Updates the timers and executes one instruction.
+    pub fn tick(&mut self, processor: &mut Processor) -> Result<StepOutcome, Chip8Error> {
+        self.update(processor);
+        processor.step()
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        WallClock::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn update_ticks_once_per_elapsed_frame() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        let mut clock = WallClock{ last_frame: Instant::now() - FRAME_DURATION * 3 };
+
+        // act
+        let ticks = clock.update(&mut processor);
+
+        // assert
+        assert_eq!(ticks, 3);
+        assert_eq!(processor.frames(), 3);
+        assert_eq!(clock.update(&mut processor), 0);
+    }
+}
@@ -0,0 +1,631 @@
+//! Breakpoints, watchpoints and single-stepping on top of a [`Processor`].
+//!
+//! The [`Debugger`] owns the processor and runs it in frames like
+//! [`Processor::run_frame`], so timers keep ticking while stepping. Running
+//! stops before an instruction at a breakpoint or one that accesses watched
+//! memory, and after an instruction that makes a register condition true.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use crate::processor::{Processor, StepOutcome};
+
+/// Stepping over or out of a subroutine gives up after this many instructions.
+pub const STEP_LIMIT: usize = 1_000_000;
+
+/// How an instruction accesses memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Watches both, never reported for an access.
+    ReadWrite
+}
+
+impl Access {
+
+    fn matches(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+impl FromStr for Access {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Access::Read),
+            "write" => Ok(Access::Write),
+            "readwrite" | "access" => Ok(Access::ReadWrite),
+            _ => Err(format!("unknown access '{}', expected read, write or readwrite", s))
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::ReadWrite => write!(f, "readwrite")
+        }
+    }
+}
+
+/// Stops when an instruction accesses memory in `range`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: Range<usize>,
+    pub access: Access
+}
+
+/// A register a [`Condition`] can test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    DelayTimer,
+    SoundTimer
+}
+
+impl Register {
+
+    fn value(self, processor: &Processor) -> usize {
+        match self {
+            Register::V(x) => processor.registers()[x as usize] as usize,
+            Register::I => processor.i(),
+            Register::Pc => processor.pc(),
+            Register::Sp => processor.sp(),
+            Register::DelayTimer => processor.delay_timer() as usize,
+            Register::SoundTimer => processor.sound_timer() as usize
+        }
+    }
+}
+
+/// Parses `v0` to `vf`, `i`, `pc`, `sp`, `dt` and `st`, ignoring case.
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        match lower.as_str() {
+            "i" => Ok(Register::I),
+            "pc" => Ok(Register::Pc),
+            "sp" => Ok(Register::Sp),
+            "dt" => Ok(Register::DelayTimer),
+            "st" => Ok(Register::SoundTimer),
+            _ => lower.strip_prefix('v')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+                .map(Register::V)
+                .ok_or_else(|| format!("unknown register '{}'", s))
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::DelayTimer => write!(f, "DT"),
+            Register::SoundTimer => write!(f, "ST")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual
+}
+
+impl Comparison {
+
+    // Longer operators first, so that `<=` is not taken for `<`.
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater)
+    ];
+
+    fn holds(self, left: usize, right: usize) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right
+        }
+    }
+}
+
+/// A register condition like `V3 == 0x10`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: usize
+}
+
+impl Condition {
+
+    pub fn holds(&self, processor: &Processor) -> bool {
+        self.comparison.holds(self.register.value(processor), self.value)
+    }
+}
+
+/// Parses `<register> <operator> <value>`, e.g. `V3 == 0x10` or `i >= 768`.
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (register, comparison, value) = Comparison::OPERATORS.iter()
+            .find_map(|(operator, comparison)| {
+                s.split_once(operator).map(|(register, value)| (register, *comparison, value))
+            })
+            .ok_or_else(|| format!("invalid condition '{}', expected e.g. V3 == 0x10", s))?;
+        let value = value.trim();
+        let value = match value.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => value.parse()
+        }.map_err(|_| format!("invalid value '{}'", value))?;
+        Ok(Condition{ register: register.trim().parse()?, comparison, value })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = Comparison::OPERATORS.iter()
+            .find(|(_, comparison)| *comparison == self.comparison)
+            .map_or("==", |(operator, _)| operator);
+        write!(f, "{} {} 0x{:x}", self.register, operator, self.value)
+    }
+}
+
+/// Why the debugger stopped running the processor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested step is done.
+    Step,
+    /// The requested frame was reached.
+    Frame,
+    /// The next instruction is at a breakpoint.
+    Breakpoint { pc: usize },
+    /// The next instruction accesses watched memory.
+    Watchpoint { address: usize, access: Access },
+    /// The last instruction made the condition true.
+    Condition(Condition),
+    /// The processor halted, see [`Processor::error`] for a failed instruction.
+    Halted,
+    /// Stepping over or out did not finish within [`STEP_LIMIT`] instructions.
+    StepLimit
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "step"),
+            StopReason::Frame => write!(f, "frame"),
+            StopReason::Breakpoint{ pc } => write!(f, "breakpoint at 0x{:03x}", pc),
+            StopReason::Watchpoint{ address, access } => write!(f, "{} watchpoint at 0x{:03x}", access, address),
+            StopReason::Condition(condition) => write!(f, "condition {}", condition),
+            StopReason::Halted => write!(f, "halted"),
+            StopReason::StepLimit => write!(f, "step limit reached")
+        }
+    }
+}
+
+/// A [`Processor`] with breakpoints, watchpoints and register conditions.
+pub struct Debugger {
+    processor: Processor,
+    cycles_per_frame: usize,
+    // Instructions executed in the current frame.
+    cycle: usize,
+    // The instruction whose breakpoints and watchpoints were already checked,
+    // so that resuming or waiting for a key does not stop at it again.
+    passed: Option<usize>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    // Each condition with whether it held after the previous instruction.
+    conditions: Vec<(Condition, bool)>
+}
+
+impl Debugger {
+
+    pub fn new(processor: Processor, cycles_per_frame: usize) -> Self {
+        Debugger{
+            processor,
+            cycles_per_frame,
+            cycle: 0,
+            passed: None,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            conditions: Vec::new()
+        }
+    }
+
+    pub fn processor(&self) -> &Processor {
+        &self.processor
+    }
+
+    /// The processor, e.g. to press keys or load a state. Loading a state
+    /// should be followed by [`Debugger::reset_frame`].
+    pub fn processor_mut(&mut self) -> &mut Processor {
+        &mut self.processor
+    }
+
+    pub fn into_processor(self) -> Processor {
+        self.processor
+    }
+
+    pub fn cycles_per_frame(&self) -> usize {
+        self.cycles_per_frame
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles_per_frame: usize) {
+        self.cycles_per_frame = cycles_per_frame;
+    }
+
+    /// Returns whether running stopped in the middle of a frame.
+    pub fn is_mid_frame(&self) -> bool {
+        self.cycle != 0
+    }
+
+    /// Starts counting the instructions of a new frame.
+    pub fn reset_frame(&mut self) {
+        self.cycle = 0;
+        self.passed = None;
+    }
+
+    /// Returns whether any breakpoint, watchpoint or condition is set.
+    pub fn has_stops(&self) -> bool {
+        !self.breakpoints.is_empty() || !self.watchpoints.is_empty() || !self.conditions.is_empty()
+    }
+
+    /// Adds a breakpoint. Returns false if there already is one at `pc`.
+    pub fn add_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.insert(pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, range: Range<usize>, access: Access) {
+        self.watchpoints.push(Watchpoint{ range, access });
+    }
+
+    /// Removes the watchpoints on exactly `range`. Returns false if there were none.
+    pub fn remove_watchpoint(&mut self, range: Range<usize>) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.range != range);
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_condition(&mut self, condition: Condition) {
+        let holds = condition.holds(&self.processor);
+        self.conditions.push((condition, holds));
+    }
+
+    pub fn remove_condition(&mut self, condition: &Condition) -> bool {
+        let len = self.conditions.len();
+        self.conditions.retain(|(existing, _)| existing != condition);
+        self.conditions.len() != len
+    }
+
+    pub fn conditions(&self) -> impl Iterator<Item = &Condition> + '_ {
+        self.conditions.iter().map(|(condition, _)| condition)
+    }
+
+    /// Removes all breakpoints, watchpoints and conditions.
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.conditions.clear();
+    }
+
+    /// Executes one instruction, ignoring a breakpoint at the current one.
+    pub fn step_into(&mut self) -> StopReason {
+        self.execute(false).unwrap_or(StopReason::Step)
+    }
+
+    /// Executes one instruction, or a whole subroutine if it is a `2NNN` call.
+    pub fn step_over(&mut self) -> StopReason {
+        match self.processor.current_instruction() {
+            Ok(Instruction::Call(_)) => {
+                let (sp, return_pc) = (self.processor.sp(), self.processor.pc() + 2);
+                self.run_until(|processor| processor.sp() == sp && processor.pc() == return_pc)
+            },
+            _ => self.step_into()
+        }
+    }
+
+    /// Runs until the current subroutine returns with `00EE`. Steps a single
+    /// instruction if the processor is not in a subroutine.
+    pub fn step_out(&mut self) -> StopReason {
+        let sp = self.processor.sp();
+        if sp == 0 {
+            return self.step_into();
+        }
+        self.run_until(|processor| processor.sp() < sp)
+    }
+
+    /// Runs the rest of the current frame. Returns why it stopped early, if it did.
+    pub fn run_frame(&mut self) -> Option<StopReason> {
+        self.run_frames(1).err()
+    }
+
+    /// Runs frames until [`Processor::frames`] reaches `frame`.
+    pub fn run_until_frame(&mut self, frame: u64) -> StopReason {
+        let frames = frame.saturating_sub(self.processor.frames());
+        self.run_frames(frames).err().unwrap_or(StopReason::Frame)
+    }
+
+    fn run_frames(&mut self, frames: u64) -> Result<(), StopReason> {
+        for _ in 0..frames {
+            loop {
+                if let Some(reason) = self.execute(true) {
+                    return Err(reason);
+                }
+                if self.cycle == 0 {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn run_until(&mut self, done: impl Fn(&Processor) -> bool) -> StopReason {
+        for _ in 0..STEP_LIMIT {
+            if let Some(reason) = self.execute(true) {
+                return reason;
+            }
+            if done(&self.processor) {
+                return StopReason::Step;
+            }
+        }
+        StopReason::StepLimit
+    }
+
+    // Executes one instruction and ticks the timers at the end of a frame.
+    // `check` stops before the instruction at breakpoints and watchpoints.
+    fn execute(&mut self, check: bool) -> Option<StopReason> {
+        if self.processor.is_halted() {
+            return Some(StopReason::Halted);
+        }
+        let pc = self.processor.pc();
+        if check && self.passed != Some(pc) {
+            self.passed = Some(pc);
+            if let Some(reason) = self.check_before() {
+                return Some(reason);
+            }
+        }
+        self.passed = Some(pc);
+
+        let result = self.processor.step();
+        if let Ok(StepOutcome::Executed) = result {
+            self.passed = None;
+        }
+        self.cycle += 1;
+        if self.processor.end_step(self.cycle, self.cycles_per_frame) {
+            self.cycle = 0;
+        }
+        if result.is_err() || self.processor.is_halted() {
+            return Some(StopReason::Halted);
+        }
+
+        let mut stop = None;
+        for (condition, held) in self.conditions.iter_mut() {
+            let holds = condition.holds(&self.processor);
+            if holds && !*held && stop.is_none() {
+                stop = Some(StopReason::Condition(*condition));
+            }
+            *held = holds;
+        }
+        stop
+    }
+
+    fn check_before(&self) -> Option<StopReason> {
+        let pc = self.processor.pc();
+        if self.breakpoints.contains(&pc) {
+            return Some(StopReason::Breakpoint{ pc });
+        }
+        let (range, access) = self.processor.current_instruction().ok()
+            .and_then(|instruction| memory_access(&self.processor, instruction))?;
+        self.watchpoints.iter()
+            .filter(|watchpoint| watchpoint.access.matches(access))
+            .find_map(|watchpoint| {
+                let start = range.start.max(watchpoint.range.start);
+                (start < range.end.min(watchpoint.range.end)).then_some(StopReason::Watchpoint{ address: start, access })
+            })
+    }
+}
+
+// The memory `instruction` reads or writes when executed next.
+fn memory_access(processor: &Processor, instruction: Instruction) -> Option<(Range<usize>, Access)> {
+    use Instruction::*;
+
+    let i = processor.i();
+    let (len, access) = match instruction {
+        Draw{ n, .. } => {
+            let bytes = match n {
+                0 if processor.platform() != Platform::Chip8 => 32,
+                n => n as usize
+            };
+            (bytes * processor.selected_planes().count_ones() as usize, Access::Read)
+        },
+        Bcd(_) => (3, Access::Write),
+        Store(x) => (x as usize + 1, Access::Write),
+        Load(x) => (x as usize + 1, Access::Read),
+        SaveRange{ x, y } => ((x as usize).abs_diff(y as usize) + 1, Access::Write),
+        LoadRange{ x, y } => ((x as usize).abs_diff(y as usize) + 1, Access::Read),
+        Audio => (16, Access::Read),
+        _ => return None
+    };
+    Some((i..i + len, access))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    // 200: 6005 V0 = 5, 202: 2208 call, 204: 7001 V0 += 1, 206: 1206 halt,
+    // 208: A300 I = 0x300, 20A: F033 BCD, 20C: 00EE
+    const CALL: [u8; 14] = [0x60, 0x05, 0x22, 0x08, 0x70, 0x01, 0x12, 0x06, 0xa3, 0x00, 0xf0, 0x33, 0x00, 0xee];
+
+    fn debugger(rom: &[u8]) -> Debugger {
+        Debugger::new(Processor::new(rom, Quirks::default()), 10)
+    }
+
+    #[test]
+    fn breakpoint_stops_before_instruction() {
+        // arrange
+        let mut debugger = debugger(&CALL);
+        debugger.add_breakpoint(0x20a);
+
+        // act
+        let stop = debugger.run_frame();
+        let before = debugger.processor().memory()[0x300..0x303].to_vec();
+        let resumed = debugger.run_frame();
+
+        // assert
+        assert_eq!(stop, Some(StopReason::Breakpoint{ pc: 0x20a }));
+        assert_eq!(before, [0, 0, 0]);
+        assert_eq!(resumed, Some(StopReason::Halted));
+        assert_eq!(debugger.processor().memory()[0x300..0x303], [0, 0, 5]);
+    }
+
+    #[test]
+    fn breakpoint_stops_again_after_resuming() {
+        // arrange
+        // 200: 7001 V0 += 1, 202: 1200 loop
+        let mut debugger = debugger(&[0x70, 0x01, 0x12, 0x00]);
+        debugger.add_breakpoint(0x200);
+
+        // act
+        let first = debugger.run_frame();
+        let second = debugger.run_frame();
+
+        // assert
+        assert_eq!(first, Some(StopReason::Breakpoint{ pc: 0x200 }));
+        assert_eq!(second, Some(StopReason::Breakpoint{ pc: 0x200 }));
+        assert_eq!(debugger.processor().registers()[0], 1);
+    }
+
+    #[test]
+    fn step_over_and_out_of_call() {
+        // arrange
+        let mut debugger = debugger(&CALL);
+        debugger.step_into();
+
+        // act
+        let over = debugger.step_over();
+        let pc_after_over = debugger.processor().pc();
+        let mut nested = self::debugger(&CALL);
+        nested.step_into();
+        nested.step_into();
+        nested.step_into();
+        let out = nested.step_out();
+
+        // assert
+        assert_eq!(over, StopReason::Step);
+        assert_eq!(pc_after_over, 0x204);
+        assert_eq!(out, StopReason::Step);
+        assert_eq!(nested.processor().pc(), 0x204);
+        assert_eq!(nested.processor().sp(), 0);
+    }
+
+    #[test]
+    fn step_over_stops_at_breakpoint_in_subroutine() {
+        // arrange
+        let mut debugger = debugger(&CALL);
+        debugger.step_into();
+        debugger.add_breakpoint(0x20c);
+
+        // act
+        let stop = debugger.step_over();
+
+        // assert
+        assert_eq!(stop, StopReason::Breakpoint{ pc: 0x20c });
+    }
+
+    #[test]
+    fn watchpoint_stops_on_access() {
+        // arrange
+        let mut debugger = debugger(&CALL);
+        debugger.add_watchpoint(0x302..0x303, Access::Write);
+        debugger.add_watchpoint(0x300..0x310, Access::Read);
+
+        // act
+        let stop = debugger.run_frame();
+
+        // assert
+        assert_eq!(stop, Some(StopReason::Watchpoint{ address: 0x302, access: Access::Write }));
+        assert_eq!(debugger.processor().pc(), 0x20a);
+    }
+
+    #[test]
+    fn condition_stops_when_it_becomes_true() {
+        // arrange
+        let mut debugger = debugger(&CALL);
+        let condition: Condition = "v0 >= 6".parse().unwrap();
+        debugger.add_condition(condition);
+
+        // act
+        let stop = debugger.run_frame();
+
+        // assert
+        assert_eq!(stop, Some(StopReason::Condition(condition)));
+        assert_eq!(debugger.processor().pc(), 0x206);
+        assert_eq!(condition.to_string(), "V0 >= 0x6");
+    }
+
+    #[test]
+    fn run_until_frame_ticks_timers() {
+        // arrange
+        // 6020: V0 = 0x20, F015: delay = V0, F107: V1 = delay, 1204: loop
+        let mut debugger = debugger(&[0x60, 0x20, 0xf0, 0x15, 0xf1, 0x07, 0x12, 0x04]);
+
+        // act
+        let stop = debugger.run_until_frame(5);
+
+        // assert
+        assert_eq!(stop, StopReason::Frame);
+        assert_eq!(debugger.processor().frames(), 5);
+        assert_eq!(debugger.processor().delay_timer(), 0x20 - 5);
+    }
+
+    #[test]
+    fn parse_condition() {
+        assert_eq!("V3 == 0x10".parse(), Ok(Condition{ register: Register::V(3), comparison: Comparison::Equal, value: 0x10 }));
+        assert_eq!("i<=768".parse(), Ok(Condition{ register: Register::I, comparison: Comparison::LessOrEqual, value: 768 }));
+        assert!("V3 = 1".parse::<Condition>().is_err());
+        assert!("vg == 1".parse::<Condition>().is_err());
+    }
+}
@@ -0,0 +1,466 @@
+//! Disassembler for CHIP-8, SUPER-CHIP and XO-CHIP programs.
+//!
+//! [`decode`] turns an opcode into an [`Instruction`], which formats itself in
+//! Cowgod's mnemonics (`LD V5, 0x34`) or in Octo's syntax (`v5 := 0x34`).
+//! [`disassemble`] walks a whole ROM: it follows every path the program can
+//! take from the start address to tell code from data, and labels the targets
+//! of jumps and calls.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+pub use crate::instruction::{decode, Instruction};
+use crate::instruction::encode;
+use crate::platform::Platform;
+use crate::processor::PC_START;
+
+/// Mnemonics to format instructions with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// Cowgod's technical reference, e.g. `LD V5, 0x34`.
+    #[default]
+    Cowgod,
+    /// Octo's assembly language, e.g. `v5 := 0x34`.
+    Octo
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cowgod" => Ok(Syntax::Cowgod),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!("unknown syntax '{}', expected cowgod or octo", s))
+        }
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Syntax::Cowgod => write!(f, "cowgod"),
+            Syntax::Octo => write!(f, "octo")
+        }
+    }
+}
+
+impl Instruction {
+
+    /// Size of the instruction in bytes, including the address of `F000 NNNN`.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::SetILong => 4,
+            _ => 2
+        }
+    }
+
+    /// Formats the instruction with addresses as hex numbers.
+    ///
+    /// `SetILong` is formatted without its address, which is not part of the opcode.
+    pub fn format(&self, syntax: Syntax) -> String {
+        self.format_with(syntax, &|address| format!("0x{:03x}", address))
+    }
+
+    /// Formats the instruction, writing the addresses of jumps and calls with `address`.
+    pub fn format_with(&self, syntax: Syntax, address: &dyn Fn(u16) -> String) -> String {
+        match syntax {
+            Syntax::Cowgod => self.cowgod(address),
+            Syntax::Octo => self.octo(address)
+        }
+    }
+
+    fn cowgod(&self, address: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+
+        match *self {
+            ScrollDown(n) => format!("SCD {}", n),
+            ScrollUp(n) => format!("SCU {}", n),
+            Clear => "CLS".to_string(),
+            Return => "RET".to_string(),
+            ScrollRight => "SCR".to_string(),
+            ScrollLeft => "SCL".to_string(),
+            Exit => "EXIT".to_string(),
+            LowRes => "LOW".to_string(),
+            HighRes => "HIGH".to_string(),
+            Jump(nnn) => format!("JP {}", address(nnn)),
+            Call(nnn) => format!("CALL {}", address(nnn)),
+            SkipIfEqual{ x, nn } => format!("SE V{:X}, 0x{:02x}", x, nn),
+            SkipIfNotEqual{ x, nn } => format!("SNE V{:X}, 0x{:02x}", x, nn),
+            SkipIfRegistersEqual{ x, y } => format!("SE V{:X}, V{:X}", x, y),
+            SaveRange{ x, y } => format!("SAVE V{:X}, V{:X}", x, y),
+            LoadRange{ x, y } => format!("LOAD V{:X}, V{:X}", x, y),
+            Set{ x, nn } => format!("LD V{:X}, 0x{:02x}", x, nn),
+            Add{ x, nn } => format!("ADD V{:X}, 0x{:02x}", x, nn),
+            Move{ x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Or{ x, y } => format!("OR V{:X}, V{:X}", x, y),
+            And{ x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Xor{ x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            AddRegisters{ x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Subtract{ x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            ShiftRight{ x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            SubtractReversed{ x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft{ x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            SkipIfRegistersNotEqual{ x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            SetI(nnn) => format!("LD I, 0x{:03x}", nnn),
+            JumpOffset(nnn) => format!("JP V0, {}", address(nnn)),
+            Random{ x, nn } => format!("RND V{:X}, 0x{:02x}", x, nn),
+            Draw{ x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipIfKey(x) => format!("SKP V{:X}", x),
+            SkipIfNotKey(x) => format!("SKNP V{:X}", x),
+            SetILong => "LD I, LONG".to_string(),
+            Plane(n) => format!("PLANE {}", n),
+            Audio => "AUDIO".to_string(),
+            GetDelay(x) => format!("LD V{:X}, DT", x),
+            WaitKey(x) => format!("LD V{:X}, K", x),
+            SetDelay(x) => format!("LD DT, V{:X}", x),
+            SetSound(x) => format!("LD ST, V{:X}", x),
+            AddI(x) => format!("ADD I, V{:X}", x),
+            Font(x) => format!("LD F, V{:X}", x),
+            BigFont(x) => format!("LD HF, V{:X}", x),
+            Bcd(x) => format!("LD B, V{:X}", x),
+            Pitch(x) => format!("PITCH V{:X}", x),
+            Store(x) => format!("LD [I], V{:X}", x),
+            Load(x) => format!("LD V{:X}, [I]", x),
+            SaveFlags(x) => format!("LD R, V{:X}", x),
+            LoadFlags(x) => format!("LD V{:X}, R", x)
+        }
+    }
+
+    fn octo(&self, address: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+
+        // Octo's skips are written as the condition under which the next instruction runs.
+        match *self {
+            ScrollDown(n) => format!("scroll-down {}", n),
+            ScrollUp(n) => format!("scroll-up {}", n),
+            Clear => "clear".to_string(),
+            Return => "return".to_string(),
+            ScrollRight => "scroll-right".to_string(),
+            ScrollLeft => "scroll-left".to_string(),
+            Exit => "exit".to_string(),
+            LowRes => "lores".to_string(),
+            HighRes => "hires".to_string(),
+            Jump(nnn) => format!("jump {}", address(nnn)),
+            Call(nnn) => format!(":call {}", address(nnn)),
+            SkipIfEqual{ x, nn } => format!("if v{:x} != 0x{:02x} then", x, nn),
+            SkipIfNotEqual{ x, nn } => format!("if v{:x} == 0x{:02x} then", x, nn),
+            SkipIfRegistersEqual{ x, y } => format!("if v{:x} != v{:x} then", x, y),
+            SaveRange{ x, y } => format!("save v{:x} - v{:x}", x, y),
+            LoadRange{ x, y } => format!("load v{:x} - v{:x}", x, y),
+            Set{ x, nn } => format!("v{:x} := 0x{:02x}", x, nn),
+            Add{ x, nn } => format!("v{:x} += 0x{:02x}", x, nn),
+            Move{ x, y } => format!("v{:x} := v{:x}", x, y),
+            Or{ x, y } => format!("v{:x} |= v{:x}", x, y),
+            And{ x, y } => format!("v{:x} &= v{:x}", x, y),
+            Xor{ x, y } => format!("v{:x} ^= v{:x}", x, y),
+            AddRegisters{ x, y } => format!("v{:x} += v{:x}", x, y),
+            Subtract{ x, y } => format!("v{:x} -= v{:x}", x, y),
+            ShiftRight{ x, y } => format!("v{:x} >>= v{:x}", x, y),
+            SubtractReversed{ x, y } => format!("v{:x} =- v{:x}", x, y),
+            ShiftLeft{ x, y } => format!("v{:x} <<= v{:x}", x, y),
+            SkipIfRegistersNotEqual{ x, y } => format!("if v{:x} == v{:x} then", x, y),
+            SetI(nnn) => format!("i := 0x{:03x}", nnn),
+            JumpOffset(nnn) => format!("jump0 {}", address(nnn)),
+            Random{ x, nn } => format!("v{:x} := random 0x{:02x}", x, nn),
+            Draw{ x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            SkipIfKey(x) => format!("if v{:x} -key then", x),
+            SkipIfNotKey(x) => format!("if v{:x} key then", x),
+            SetILong => "i := long".to_string(),
+            Plane(n) => format!("plane {}", n),
+            Audio => "audio".to_string(),
+            GetDelay(x) => format!("v{:x} := delay", x),
+            WaitKey(x) => format!("v{:x} := key", x),
+            SetDelay(x) => format!("delay := v{:x}", x),
+            SetSound(x) => format!("buzzer := v{:x}", x),
+            AddI(x) => format!("i += v{:x}", x),
+            Font(x) => format!("i := hex v{:x}", x),
+            BigFont(x) => format!("i := bighex v{:x}", x),
+            Bcd(x) => format!("bcd v{:x}", x),
+            Pitch(x) => format!("pitch := v{:x}", x),
+            Store(x) => format!("save v{:x}", x),
+            Load(x) => format!("load v{:x}", x),
+            SaveFlags(x) => format!("saveflags v{:x}", x),
+            LoadFlags(x) => format!("loadflags v{:x}", x)
+        }
+    }
+
+    // The addresses execution can continue at after this instruction at `address`.
+    fn successors(&self, address: usize, next_is_long: bool) -> Vec<usize> {
+        use Instruction::*;
+
+        let next = address + self.size();
+        match *self {
+            Return | Exit => vec![],
+            // A jump to itself halts the processor.
+            Jump(nnn) if nnn as usize == address => vec![],
+            Jump(nnn) => vec![nnn as usize],
+            Call(nnn) => vec![nnn as usize, next],
+            // The offset is only known at run time, assume the base is a jump table.
+            JumpOffset(nnn) => vec![nnn as usize],
+            SkipIfEqual{ .. } | SkipIfNotEqual{ .. } | SkipIfRegistersEqual{ .. }
+            | SkipIfRegistersNotEqual{ .. } | SkipIfKey(_) | SkipIfNotKey(_) => {
+                vec![next, next + if next_is_long { 4 } else { 2 }]
+            },
+            _ => vec![next]
+        }
+    }
+
+    // The address this instruction jumps to or calls, if it is known.
+    fn target(&self) -> Option<usize> {
+        match *self {
+            Instruction::Jump(nnn) | Instruction::Call(nnn) | Instruction::JumpOffset(nnn) => Some(nnn as usize),
+            _ => None
+        }
+    }
+}
+
+/// Formats in Cowgod's mnemonics.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(Syntax::Cowgod))
+    }
+}
+
+/// A part of a disassembled ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Item {
+    /// An instruction reachable from the start address. `long` holds the
+    /// address of `F000 NNNN`.
+    Code { address: usize, instruction: Instruction, long: Option<u16> },
+    /// Bytes that are never executed.
+    Data { address: usize, bytes: Vec<u8> }
+}
+
+impl Item {
+
+    pub fn address(&self) -> usize {
+        match self {
+            Item::Code{ address, .. } | Item::Data{ address, .. } => *address
+        }
+    }
+}
+
+/// A disassembled ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Disassembly {
+    pub items: Vec<Item>,
+    /// The jump and call targets inside the ROM.
+    pub labels: BTreeSet<usize>
+}
+
+impl Disassembly {
+
+    /// The name of the label at `address`.
+    pub fn label(address: usize) -> String {
+        format!("L{:03X}", address)
+    }
+
+    /// Formats the disassembly as a listing with labels. The address and
+    /// opcode of every line are added as a comment.
+    pub fn listing(&self, syntax: Syntax) -> String {
+        self.annotated_listing(syntax, &|_| None)
+    }
+
+    /// Like [`Disassembly::listing`], with the text `annotation` returns for
+    /// an item appended to its comment.
+    pub fn annotated_listing(&self, syntax: Syntax, annotation: &dyn Fn(&Item) -> Option<String>) -> String {
+        let (comment, data) = match syntax {
+            Syntax::Cowgod => (';', "db "),
+            Syntax::Octo => ('#', "")
+        };
+        let address = |address: u16| if self.labels.contains(&(address as usize)) {
+            Disassembly::label(address as usize)
+        } else {
+            format!("0x{:03x}", address)
+        };
+
+        let mut out = String::new();
+        for item in &self.items {
+            if self.labels.contains(&item.address()) {
+                let label = Disassembly::label(item.address());
+                match syntax {
+                    Syntax::Cowgod => out.push_str(&format!("{}:\n", label)),
+                    Syntax::Octo => out.push_str(&format!(": {}\n", label))
+                }
+            }
+            let (text, bytes) = match item {
+                Item::Code{ instruction, long, .. } => {
+                    let mut text = instruction.format_with(syntax, &address);
+                    let mut bytes = encode(*instruction).to_be_bytes().to_vec();
+                    if let Some(long) = long {
+                        text.push_str(&format!(" 0x{:04x}", long));
+                        bytes.extend_from_slice(&long.to_be_bytes());
+                    }
+                    (text, bytes)
+                },
+                Item::Data{ bytes, .. } => {
+                    let values: Vec<String> = bytes.iter().map(|byte| format!("0x{:02x}", byte)).collect();
+                    (format!("{}{}", data, values.join(if data.is_empty() { " " } else { ", " })), bytes.clone())
+                }
+            };
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            out.push_str(&format!("    {:<24} {} {:03x}: {}", text, comment, item.address(), hex));
+            if let Some(annotation) = annotation(item) {
+                out.push_str(&format!(" {}", annotation));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Disassembles a ROM loaded at `0x200`.
+///
+/// Every instruction reachable from the start address through jumps, calls,
+/// skips and `BNNN` jump tables is code, everything else is data. Jumps whose
+/// target depends on data, like self-modifying code, are not followed.
+pub fn disassemble(rom: &[u8], platform: Platform) -> Disassembly {
+    let end = PC_START + rom.len();
+    let word = |address: usize| {
+        if address < PC_START || address + 2 > end {
+            return None;
+        }
+        let offset = address - PC_START;
+        Some(u16::from_be_bytes([rom[offset], rom[offset + 1]]))
+    };
+    let decode_at = |address: usize| {
+        let instruction = decode(word(address)?, platform).ok()?;
+        let long = match instruction {
+            Instruction::SetILong => Some(word(address + 2)?),
+            _ => None
+        };
+        Some((instruction, long))
+    };
+
+    // Follow all paths from the start address.
+    let mut code = vec![None; rom.len()];
+    let mut labels = BTreeSet::new();
+    let mut pending = vec![PC_START];
+    while let Some(address) = pending.pop() {
+        if address < PC_START || address >= end || code[address - PC_START].is_some() {
+            continue;
+        }
+        let (instruction, long) = match decode_at(address) {
+            Some(decoded) => decoded,
+            None => continue
+        };
+        code[address - PC_START] = Some((instruction, long));
+        if let Some(target) = instruction.target() {
+            if (PC_START..end).contains(&target) {
+                labels.insert(target);
+            }
+        }
+        let next_is_long = decode_at(address + instruction.size())
+            .is_some_and(|(next, _)| next == Instruction::SetILong);
+        pending.extend(instruction.successors(address, next_is_long));
+    }
+
+    // Bytes covered by an instruction that is not reached itself, e.g. when
+    // code jumps into the middle of another instruction, stay data.
+    let mut items = Vec::new();
+    let mut address = PC_START;
+    while address < end {
+        let offset = address - PC_START;
+        if let Some((instruction, long)) = code[offset] {
+            let len = instruction.size();
+            let overlaps = (1..len).any(|i| code.get(offset + i).is_some_and(Option::is_some));
+            if !overlaps {
+                items.push(Item::Code{ address, instruction, long });
+                address += len;
+                continue;
+            }
+        }
+        let byte = rom[offset];
+        match items.last_mut() {
+            Some(Item::Data{ address: start, bytes })
+                if bytes.len() < 8 && !labels.contains(&address) && *start + bytes.len() == address => bytes.push(byte),
+            _ => items.push(Item::Data{ address, bytes: vec![byte] })
+        }
+        address += 1;
+    }
+
+    Disassembly{ items, labels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_cowgod_and_octo() {
+        let cases = [
+            (0x6534, "LD V5, 0x34", "v5 := 0x34"),
+            (0xd015, "DRW V0, V1, 5", "sprite v0 v1 5"),
+            (0x3a07, "SE VA, 0x07", "if va != 0x07 then"),
+            (0xe1a1, "SKNP V1", "if v1 key then"),
+            (0x22a0, "CALL 0x2a0", ":call 0x2a0"),
+            (0xf355, "LD [I], V3", "save v3"),
+            (0x8127, "SUBN V1, V2", "v1 =- v2")
+        ];
+        for (opcode, cowgod, octo) in cases {
+            // act
+            let instruction = decode(opcode, Platform::Chip8).unwrap();
+
+            // assert
+            assert_eq!(instruction.format(Syntax::Cowgod), cowgod);
+            assert_eq!(instruction.format(Syntax::Octo), octo);
+        }
+    }
+
+    #[test]
+    fn disassemble_separates_code_and_data() {
+        // arrange
+        // 200: 1204 jump over data, 202: sprite data, 204: A202, 206: 2209 call,
+        // 208: data, 209: 00EE (odd address), 20B: data
+        let rom = [0x12, 0x04, 0xf0, 0x90, 0xa2, 0x02, 0x22, 0x09, 0xff, 0x00, 0xee, 0x12];
+
+        // act
+        let disassembly = disassemble(&rom, Platform::Chip8);
+
+        // assert
+        assert_eq!(disassembly.labels, BTreeSet::from([0x204, 0x209]));
+        assert_eq!(disassembly.items, vec![
+            Item::Code{ address: 0x200, instruction: Instruction::Jump(0x204), long: None },
+            Item::Data{ address: 0x202, bytes: vec![0xf0, 0x90] },
+            Item::Code{ address: 0x204, instruction: Instruction::SetI(0x202), long: None },
+            Item::Code{ address: 0x206, instruction: Instruction::Call(0x209), long: None },
+            Item::Data{ address: 0x208, bytes: vec![0xff] },
+            Item::Code{ address: 0x209, instruction: Instruction::Return, long: None },
+            Item::Data{ address: 0x20b, bytes: vec![0x12] }
+        ]);
+    }
+
+    #[test]
+    fn listing_uses_labels() {
+        // arrange
+        // 200: 6000, 202: 7001, 204: 3005 skip, 206: 1202 loop, 208: 1208 halt
+        let rom = [0x60, 0x00, 0x70, 0x01, 0x30, 0x05, 0x12, 0x02, 0x12, 0x08];
+        let disassembly = disassemble(&rom, Platform::Chip8);
+
+        // act
+        let cowgod = disassembly.listing(Syntax::Cowgod);
+        let octo = disassembly.listing(Syntax::Octo);
+
+        // assert
+        assert!(cowgod.contains("L202:\n    ADD V0, 0x01"));
+        assert!(cowgod.contains("JP L202                  ; 206: 1202\n"));
+        assert!(octo.contains(": L208\n    jump L208                # 208: 1208\n"));
+    }
+
+    #[test]
+    fn listing_formats_long_load() {
+        // arrange
+        // 200: 3000 skip over the four byte F000 1234, 206: 1206 halt
+        let rom = [0x30, 0x00, 0xf0, 0x00, 0x12, 0x34, 0x12, 0x06];
+
+        // act
+        let disassembly = disassemble(&rom, Platform::XoChip);
+
+        // assert
+        assert_eq!(disassembly.items.len(), 3);
+        assert!(disassembly.listing(Syntax::Octo).contains("i := long 0x1234"));
+        assert!(disassembly.listing(Syntax::Cowgod).contains("LD I, LONG 0x1234"));
+    }
+}
@@ -0,0 +1,438 @@
+//! The display: a framebuffer with CHIP-8 sprite drawing and SUPER-CHIP and
+//! XO-CHIP scrolling, resolutions and bit-planes.
+
+/// Height of the display in pixels.
+pub const SCREEN_HEIGHT: usize = 32;
+/// Width of the display in pixels.
+pub const SCREEN_WIDTH: usize = 64;
+/// Height of the SUPER-CHIP high resolution display in pixels.
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+/// Width of the SUPER-CHIP high resolution display in pixels.
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+
+// More dirty rectangles than this are merged into their bounding box.
+const MAX_DIRTY_RECTS: usize = 16;
+
+/// A rectangle of display pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl Rect {
+
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect{ x, y, width, height }
+    }
+
+    /// Returns whether `other` lies within this rectangle.
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x && other.y >= self.y
+            && other.x + other.width <= self.x + self.width
+            && other.y + other.height <= self.y + self.height
+    }
+
+    /// The smallest rectangle containing both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Rect{ x, y, width: right - x, height: bottom - y }
+    }
+}
+
+/// A framebuffer of one byte per pixel, row by row.
+///
+/// Bit 0 of a pixel is plane 1 and bit 1 is plane 2, so XO-CHIP pixels range
+/// from 0 to 3 while CHIP-8 and SUPER-CHIP pixels are 0 or 1. Clearing,
+/// drawing and scrolling only affect the selected planes.
+///
+/// The display also records the rectangles changed since the frontend last
+/// took them with [`Display::take_dirty_rects`], so that it only has to
+/// redraw those.
+#[derive(Clone, Debug)]
+pub struct Display {
+    hires: bool,
+    pixels: Vec<u8>,
+    planes: u8,
+    dirty: Vec<Rect>
+}
+
+impl Display {
+
+    /// Creates a cleared low resolution display with plane 1 selected.
+    pub fn new() -> Self {
+        Display{
+            hires: false,
+            pixels: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            planes: 1,
+            dirty: vec![Rect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT)]
+        }
+    }
+
+    // Restores a display from a save state, which checked the values.
+    pub(crate) fn from_parts(hires: bool, pixels: Vec<u8>, planes: u8) -> Self {
+        let mut display = Display{ hires, pixels, planes, dirty: Vec::new() };
+        display.mark_dirty();
+        display
+    }
+
+    /// Current width in pixels.
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_SCREEN_WIDTH } else { SCREEN_WIDTH }
+    }
+
+    /// Current height in pixels.
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_SCREEN_HEIGHT } else { SCREEN_HEIGHT }
+    }
+
+    /// Returns whether the SUPER-CHIP high resolution mode is active.
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches between 64x32 and 128x64 pixels and clears the display.
+    pub fn set_resolution(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = vec![0; self.width() * self.height()];
+        self.mark_dirty();
+    }
+
+    /// The XO-CHIP bit-planes drawn to, cleared and scrolled, bit 0 being plane 1.
+    pub fn selected_planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+
+    /// The pixels, `width * height` bytes row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Returns whether the pixel at (x, y) is set in any plane.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width() + x] != 0
+    }
+
+    /// Returns whether any pixels changed since the dirty rectangles were last
+    /// taken, i.e. whether the frame has to be presented again.
+    pub fn frame_changed(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Returns the rectangles changed since the last call and forgets them.
+    /// They may overlap; a change of resolution, clearing or scrolling dirties
+    /// the whole display.
+    pub fn take_dirty_rects(&mut self) -> Vec<Rect> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Forgets the changes, e.g. after the frontend presented the display
+    /// without looking at them.
+    pub fn mark_clean(&mut self) {
+        self.dirty.clear();
+    }
+
+    // Marks the whole display as changed, e.g. after rewinding.
+    pub(crate) fn mark_dirty(&mut self) {
+        self.dirty.clear();
+        self.dirty.push(Rect::new(0, 0, self.width(), self.height()));
+    }
+
+    fn add_dirty_rect(&mut self, rect: Rect) {
+        if self.dirty.iter().any(|dirty| dirty.contains(&rect)) {
+            return;
+        }
+        self.dirty.retain(|dirty| !rect.contains(dirty));
+        if self.dirty.len() == MAX_DIRTY_RECTS {
+            let bounds = self.dirty.iter().fold(rect, |bounds, dirty| bounds.union(dirty));
+            self.dirty.clear();
+            self.dirty.push(bounds);
+        } else {
+            self.dirty.push(rect);
+        }
+    }
+
+    /// Clears the selected planes.
+    pub fn clear(&mut self) {
+        let planes = self.planes;
+        self.pixels.iter_mut().for_each(|pixel| *pixel &= !planes);
+        self.mark_dirty();
+    }
+
+    /// XORs a sprite `width` pixels wide (8 or 16) onto the selected planes
+    /// at (x, y) and returns whether a set pixel was cleared.
+    ///
+    /// `sprite` holds the rows of the first selected plane followed by those
+    /// of the second one, if both are selected. The position wraps around the
+    /// display, and so does the sprite unless `clip` is set.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], width: usize, clip: bool) -> bool {
+        let (screen_width, screen_height) = (self.width(), self.height());
+        let bytes_per_row = width / 8;
+        let planes = self.planes.count_ones() as usize;
+        let height = sprite.len().checked_div(planes).unwrap_or(0) / bytes_per_row;
+        if height == 0 {
+            return false;
+        }
+        let (x_start, y_start) = (x % screen_width, y % screen_height);
+
+        let mut collided = false;
+        let mut rows = sprite.chunks(height * bytes_per_row);
+        for plane in [0b01, 0b10] {
+            if self.planes & plane == 0 {
+                continue;
+            }
+            let data = rows.next().unwrap_or_default();
+            for row in 0..height {
+                let y = y_start + row;
+                if y >= screen_height && clip {
+                    break;
+                }
+                for column in 0..width {
+                    let x = x_start + column;
+                    if x >= screen_width && clip {
+                        break;
+                    }
+                    let byte = data[row * bytes_per_row + column / 8];
+                    if byte & (0x80 >> (column % 8)) != 0 {
+                        collided |= self.xor_pixel(x % screen_width, y % screen_height, plane);
+                    }
+                }
+            }
+        }
+        // The sprite's rectangle, split where it wraps around.
+        let right = if clip { (x_start + width).min(screen_width) } else { x_start + width };
+        let bottom = if clip { (y_start + height).min(screen_height) } else { y_start + height };
+        for (x, width) in split(x_start, right, screen_width) {
+            for &(y, height) in &split(y_start, bottom, screen_height) {
+                self.add_dirty_rect(Rect::new(x, y, width, height));
+            }
+        }
+        collided
+    }
+
+    /// XORs the pixel with the `planes` bits and returns whether a set pixel was cleared.
+    pub fn flip_pixel(&mut self, x: usize, y: usize, planes: u8) -> bool {
+        self.add_dirty_rect(Rect::new(x, y, 1, 1));
+        self.xor_pixel(x, y, planes)
+    }
+
+    // Like `flip_pixel` but leaves the dirty rectangles to the caller.
+    fn xor_pixel(&mut self, x: usize, y: usize, planes: u8) -> bool {
+        let address = y * self.width() + x;
+        let data = self.pixels[address];
+        self.pixels[address] = data ^ planes;
+        data & planes != 0
+    }
+
+    pub fn scroll_down(&mut self, pixels: usize) {
+        self.scroll(0, pixels as isize);
+    }
+
+    pub fn scroll_up(&mut self, pixels: usize) {
+        self.scroll(0, -(pixels as isize));
+    }
+
+    pub fn scroll_right(&mut self, pixels: usize) {
+        self.scroll(pixels as isize, 0);
+    }
+
+    pub fn scroll_left(&mut self, pixels: usize) {
+        self.scroll(-(pixels as isize), 0);
+    }
+
+    // Moves the selected planes right by `dx` and down by `dy` pixels.
+    // Pixels scrolled in from outside the display are cleared.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let planes = self.planes;
+        let source = self.pixels.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                    source[(from_y * width + from_x) as usize] & planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                *pixel = *pixel & !planes | moved;
+            }
+        }
+        self.mark_dirty();
+    }
+}
+
+// The start and length of the parts of `start..end` before and after it wraps at `size`.
+fn split(start: usize, end: usize, size: usize) -> Vec<(usize, usize)> {
+    if end <= size {
+        vec![(start, end - start)]
+    } else {
+        vec![(start, size - start), (0, (end - size).min(start))]
+    }
+}
+
+/// Displays are equal if they show the same pixels, whatever has been redrawn.
+impl PartialEq for Display {
+    fn eq(&self, other: &Self) -> bool {
+        self.hires == other.hires && self.pixels == other.pixels && self.planes == other.planes
+    }
+}
+
+impl Eq for Display {}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 4x2 box outline, one byte per row.
+    const BOX: [u8; 2] = [0xf0, 0x90];
+
+    fn set_pixels(display: &Display) -> Vec<(usize, usize)> {
+        (0..display.height())
+            .flat_map(|y| (0..display.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| display.pixel(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn draw_sprite_reports_collision() {
+        // arrange
+        let mut display = Display::new();
+
+        // act
+        let first = display.draw_sprite(2, 3, &BOX, 8, false);
+        let second = display.draw_sprite(2, 4, &[0x80], 8, false);
+
+        // assert
+        assert!(!first);
+        assert!(second);
+        assert_eq!(set_pixels(&display), [(2, 3), (3, 3), (4, 3), (5, 3), (5, 4)]);
+    }
+
+    #[test]
+    fn draw_sprite_wraps_or_clips() {
+        // arrange
+        let mut wrapped = Display::new();
+        let mut clipped = Display::new();
+
+        // act
+        wrapped.draw_sprite(62 + SCREEN_WIDTH, 31, &BOX, 8, false);
+        clipped.draw_sprite(62, 31, &BOX, 8, true);
+
+        // assert
+        assert_eq!(set_pixels(&wrapped), [(1, 0), (62, 0), (0, 31), (1, 31), (62, 31), (63, 31)]);
+        assert_eq!(set_pixels(&clipped), [(62, 31), (63, 31)]);
+    }
+
+    #[test]
+    fn planes_are_drawn_and_cleared_separately() {
+        // arrange
+        let mut display = Display::new();
+        display.select_planes(0b11);
+        display.draw_sprite(0, 0, &[0x80, 0xc0], 8, false);
+
+        // act
+        display.select_planes(0b01);
+        display.clear();
+
+        // assert
+        assert_eq!(display.pixels()[..2], [0b10, 0b10]);
+    }
+
+    #[test]
+    fn scroll_moves_selected_planes() {
+        // arrange
+        let mut display = Display::new();
+        display.set_resolution(true);
+        display.flip_pixel(10, 10, 1);
+
+        // act
+        display.scroll_down(3);
+        display.scroll_left(4);
+
+        // assert
+        assert_eq!(set_pixels(&display), [(6, 13)]);
+    }
+
+    #[test]
+    fn dirty_rects_cover_drawn_sprites() {
+        // arrange
+        let mut display = Display::new();
+        display.take_dirty_rects();
+        let unchanged = display.frame_changed();
+
+        // act
+        display.draw_sprite(2, 3, &BOX, 8, false);
+        display.draw_sprite(4, 3, &[0x80], 8, false);
+        let changed = display.frame_changed();
+        let rects = display.take_dirty_rects();
+
+        // assert
+        assert!(!unchanged);
+        assert!(changed);
+        assert_eq!(rects, [Rect::new(2, 3, 8, 2), Rect::new(4, 3, 8, 1)]);
+        assert!(!display.frame_changed());
+    }
+
+    #[test]
+    fn dirty_rects_of_large_and_small_sprites_stay_separate() {
+        // arrange
+        let mut display = Display::new();
+        display.take_dirty_rects();
+
+        // act
+        display.draw_sprite(50, 16, &[0xff; 15], 8, false);
+        display.draw_sprite(2, 3, &BOX, 8, false);
+        let rects = display.take_dirty_rects();
+
+        // assert
+        assert_eq!(rects, [Rect::new(50, 16, 8, 15), Rect::new(2, 3, 8, 2)]);
+    }
+
+    #[test]
+    fn dirty_rects_split_where_sprites_wrap() {
+        // arrange
+        let mut wrapped = Display::new();
+        let mut clipped = Display::new();
+        wrapped.mark_clean();
+        clipped.mark_clean();
+
+        // act
+        wrapped.draw_sprite(60, 31, &BOX, 8, false);
+        clipped.draw_sprite(60, 31, &BOX, 8, true);
+
+        // assert
+        assert_eq!(wrapped.take_dirty_rects(), [
+            Rect::new(60, 31, 4, 1), Rect::new(60, 0, 4, 1), Rect::new(0, 31, 4, 1), Rect::new(0, 0, 4, 1)
+        ]);
+        assert_eq!(clipped.take_dirty_rects(), [Rect::new(60, 31, 4, 1)]);
+    }
+
+    #[test]
+    fn clear_and_scroll_dirty_everything() {
+        // arrange
+        let mut display = Display::new();
+        display.set_resolution(true);
+        display.take_dirty_rects();
+
+        // act
+        display.scroll_left(4);
+
+        // assert
+        assert_eq!(display.take_dirty_rects(), [Rect::new(0, 0, HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT)]);
+    }
+}
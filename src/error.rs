@@ -0,0 +1,45 @@
+//! Errors that stop the processor.
+
+use std::error::Error;
+use std::fmt;
+
+/// Why the processor could not execute an instruction.
+///
+/// The processor halts when an instruction fails; the error stays available
+/// through [`Processor::error`](crate::processor::Processor::error) until the
+/// next ROM is loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The opcode at `pc` is not part of the platform's instruction set.
+    UnknownOpcode { pc: usize, opcode: u16 },
+    /// `2NNN` was called with all stack entries in use.
+    StackOverflow { pc: usize },
+    /// `00EE` returned with an empty stack.
+    StackUnderflow { pc: usize },
+    /// An instruction accessed memory outside of RAM.
+    MemoryOutOfBounds { addr: usize }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode{ pc, opcode } => write!(f, "unknown opcode 0x{:04x} at 0x{:03x}", opcode, pc),
+            Chip8Error::StackOverflow{ pc } => write!(f, "stack overflow at 0x{:03x}", pc),
+            Chip8Error::StackUnderflow{ pc } => write!(f, "return with empty stack at 0x{:03x}", pc),
+            Chip8Error::MemoryOutOfBounds{ addr } => write!(f, "memory access out of bounds at 0x{:x}", addr)
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_is_readable() {
+        assert_eq!(Chip8Error::UnknownOpcode{ pc: 0x200, opcode: 0x00ff }.to_string(), "unknown opcode 0x00ff at 0x200");
+        assert_eq!(Chip8Error::MemoryOutOfBounds{ addr: 0x1000 }.to_string(), "memory access out of bounds at 0x1000");
+    }
+}
@@ -0,0 +1,403 @@
+//! A GDB remote serial protocol stub, so that GDB and other tools speaking the
+//! protocol can debug a ROM over TCP.
+//!
+//! The stub runs a [`Debugger`] for one connection at a time. Its register file
+//! is V0 to VF, I, PC, SP, DT and ST, described to the client as
+//! `target.xml`, and its memory is the processor's RAM. It supports software
+//! breakpoints (`Z0`), write, read and access watchpoints (`Z2` to `Z4`),
+//! single-stepping and continuing, which runs frames as fast as possible until
+//! a stop or an interrupt from the client. Writing registers or memory is not
+//! supported.
+//!
+//! ```no_run
+//! use std::net::TcpListener;
+//! use chip_8_wasm::debugger::Debugger;
+//! use chip_8_wasm::gdb::GdbStub;
+//! use chip_8_wasm::{Processor, Quirks};
+//!
+//! let rom = std::fs::read("game.ch8").unwrap();
+//! let mut stub = GdbStub::new(Debugger::new(Processor::new(&rom, Quirks::default()), 15));
+//! let (stream, _) = TcpListener::bind("127.0.0.1:1234").unwrap().accept().unwrap();
+//! stub.serve(stream).unwrap();
+//! ```
+
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use crate::debugger::{Access, Debugger, StopReason};
+
+const INTERRUPT: u8 = 0x03;
+// Register sizes in bytes, in the order of `target.xml`: V0 to VF, I, PC, SP, DT, ST.
+const REGISTER_SIZES: [usize; 21] = [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 1, 1, 1];
+
+/// Serves the GDB remote serial protocol for a [`Debugger`].
+pub struct GdbStub {
+    debugger: Debugger,
+    no_ack: bool
+}
+
+impl GdbStub {
+
+    pub fn new(debugger: Debugger) -> Self {
+        GdbStub{ debugger, no_ack: false }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn into_debugger(self) -> Debugger {
+        self.debugger
+    }
+
+    /// Talks to one client until it detaches, kills the target or disconnects.
+    /// Breakpoints set by the client stay set afterwards.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        // Packets are small and answered one at a time.
+        stream.set_nodelay(true)?;
+        let mut connection = Connection{ stream, buffer: Vec::new() };
+        self.no_ack = false;
+        while let Some(packet) = connection.read_packet(self.no_ack)? {
+            match self.handle(&packet) {
+                Reply::Packet(reply) => connection.write_packet(&reply)?,
+                Reply::Run(run) => {
+                    let reply = self.run(run, &mut connection)?;
+                    connection.write_packet(&reply)?;
+                },
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        connection.write_packet(reply)?;
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &[u8]) -> Reply {
+        let packet = String::from_utf8_lossy(packet);
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match command {
+            "?" => stop_reply(StopReason::Step),
+            "g" => (0..REGISTER_SIZES.len()).map(|n| self.register(n)).collect(),
+            "p" => usize::from_str_radix(arguments, 16).ok()
+                .filter(|&n| n < REGISTER_SIZES.len())
+                .map_or_else(|| "E01".to_string(), |n| self.register(n)),
+            "m" => self.read_memory(arguments).unwrap_or_else(|| "E01".to_string()),
+            "Z" | "z" => self.set_breakpoint(command == "Z", arguments).unwrap_or_else(|| "E01".to_string()),
+            "s" if arguments.is_empty() => return Reply::Run(Run::Step),
+            "c" if arguments.is_empty() => return Reply::Run(Run::Continue),
+            "H" => "OK".to_string(),
+            "q" => self.query(arguments),
+            "Q" if arguments == "StartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            },
+            "D" => return Reply::Close(Some("OK")),
+            "k" => return Reply::Close(None),
+            _ => String::new()
+        };
+        Reply::Packet(reply)
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return "PacketSize=1000;QStartNoAckMode+;qXfer:features:read+;swbreak+".to_string();
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let xml = target_xml();
+            return match parse_range(range) {
+                Some((offset, _)) if offset >= xml.len() => "l".to_string(),
+                Some((offset, length)) => {
+                    let end = (offset + length).min(xml.len());
+                    format!("{}{}", if end == xml.len() { 'l' } else { 'm' }, &xml[offset..end])
+                },
+                None => "E01".to_string()
+            };
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new()
+        }
+    }
+
+    // Register `n` as hex, least significant byte first.
+    fn register(&self, n: usize) -> String {
+        let processor = self.debugger.processor();
+        let value = match n {
+            0..=15 => processor.registers()[n] as usize,
+            16 => processor.i(),
+            17 => processor.pc(),
+            18 => processor.sp(),
+            19 => processor.delay_timer() as usize,
+            _ => processor.sound_timer() as usize
+        };
+        (0..REGISTER_SIZES[n]).fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", (value >> (8 * byte)) as u8);
+            hex
+        })
+    }
+
+    // `addr,length`, truncated at the end of memory.
+    fn read_memory(&self, arguments: &str) -> Option<String> {
+        let (address, length) = parse_range(arguments)?;
+        let memory = self.debugger.processor().memory();
+        if address >= memory.len() {
+            return None;
+        }
+        let end = address.saturating_add(length).min(memory.len());
+        Some(memory[address..end].iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        }))
+    }
+
+    // `type,addr,kind`, where kind is the length for watchpoints.
+    fn set_breakpoint(&mut self, insert: bool, arguments: &str) -> Option<String> {
+        let (kind, range) = arguments.split_once(',')?;
+        let (address, length) = parse_range(range)?;
+        let access = match kind {
+            "0" => {
+                if insert {
+                    self.debugger.add_breakpoint(address);
+                } else {
+                    self.debugger.remove_breakpoint(address);
+                }
+                return Some("OK".to_string());
+            },
+            "2" => Access::Write,
+            "3" => Access::Read,
+            "4" => Access::ReadWrite,
+            _ => return Some(String::new())
+        };
+        let range = address..address + length.max(1);
+        if insert {
+            self.debugger.add_watchpoint(range, access);
+        } else {
+            self.debugger.remove_watchpoint(range);
+        }
+        Some("OK".to_string())
+    }
+
+    fn run(&mut self, run: Run, connection: &mut Connection) -> io::Result<String> {
+        let reason = match run {
+            Run::Step => self.debugger.step_into(),
+            Run::Continue => loop {
+                if let Some(reason) = self.debugger.run_frame() {
+                    break reason;
+                }
+                if connection.interrupted()? {
+                    // SIGINT
+                    return Ok("S02".to_string());
+                }
+            }
+        };
+        if reason == StopReason::Halted {
+            // SIGILL for a failing instruction, otherwise the ROM exited.
+            return Ok(if self.debugger.processor().error().is_some() { "S04" } else { "W00" }.to_string());
+        }
+        Ok(stop_reply(reason))
+    }
+}
+
+enum Run {
+    Step,
+    Continue
+}
+
+enum Reply {
+    Packet(String),
+    Run(Run),
+    /// Ends the session after sending the reply, if any.
+    Close(Option<&'static str>)
+}
+
+// The reply for a stop with SIGTRAP.
+fn stop_reply(reason: StopReason) -> String {
+    match reason {
+        StopReason::Breakpoint{ .. } => "T05swbreak:;".to_string(),
+        StopReason::Watchpoint{ address, access } => {
+            let kind = match access {
+                Access::Read => "rwatch",
+                Access::Write => "watch",
+                Access::ReadWrite => "awatch"
+            };
+            format!("T05{}:{:x};", kind, address)
+        },
+        _ => "S05".to_string()
+    }
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let (start, length) = range.split_once(',')?;
+    Some((usize::from_str_radix(start, 16).ok()?, usize::from_str_radix(length, 16).ok()?))
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+        r#"<target version="1.0"><feature name="org.chip8.core">"#
+    ));
+    for x in 0..16 {
+        let _ = write!(xml, r#"<reg name="v{:x}" bitsize="8" type="uint8"/>"#, x);
+    }
+    xml.push_str(concat!(
+        r#"<reg name="i" bitsize="16" type="data_ptr"/>"#,
+        r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#,
+        r#"<reg name="sp" bitsize="8" type="uint8"/>"#,
+        r#"<reg name="dt" bitsize="8" type="uint8"/>"#,
+        r#"<reg name="st" bitsize="8" type="uint8"/>"#,
+        "</feature></target>"
+    ));
+    xml
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, &byte| sum.wrapping_add(byte))
+}
+
+// Escapes the characters with a meaning in packets.
+fn escape(data: &str) -> Vec<u8> {
+    data.bytes().fold(Vec::new(), |mut escaped, byte| {
+        match byte {
+            b'$' | b'#' | b'}' | b'*' => escaped.extend([b'}', byte ^ 0x20]),
+            _ => escaped.push(byte)
+        }
+        escaped
+    })
+}
+
+struct Connection {
+    stream: TcpStream,
+    // Bytes received but not consumed yet.
+    buffer: Vec<u8>
+}
+
+impl Connection {
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if !self.buffer.is_empty() {
+            return Ok(Some(self.buffer.remove(0)));
+        }
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0]))
+        }
+    }
+
+    // Reads the next valid packet without `$`, `#` and checksum, acknowledging
+    // it unless acknowledgements are off. Returns `None` once the client
+    // disconnected.
+    fn read_packet(&mut self, no_ack: bool) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => (),
+                // Acknowledgements and stray interrupts.
+                Some(_) => continue
+            }
+            let mut packet = Vec::new();
+            let mut escaped = false;
+            let mut sum = 0u8;
+            loop {
+                let byte = match self.read_byte()? {
+                    Some(byte) => byte,
+                    None => return Ok(None)
+                };
+                if byte == b'#' && !escaped {
+                    break;
+                }
+                sum = sum.wrapping_add(byte);
+                match (escaped, byte) {
+                    (false, b'}') => escaped = true,
+                    (true, _) => {
+                        packet.push(byte ^ 0x20);
+                        escaped = false;
+                    },
+                    (false, _) => packet.push(byte)
+                }
+            }
+            let high = self.read_byte()?;
+            let low = self.read_byte()?;
+            let expected = match (high, low) {
+                (Some(high), Some(low)) => u8::from_str_radix(&String::from_utf8_lossy(&[high, low]), 16).ok(),
+                _ => return Ok(None)
+            };
+            if no_ack {
+                return Ok(Some(packet));
+            }
+            if expected == Some(sum) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(packet));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let escaped = escape(data);
+        let mut packet = Vec::with_capacity(escaped.len() + 4);
+        packet.push(b'$');
+        packet.extend(&escaped);
+        packet.extend(format!("#{:02x}", checksum(&escaped)).bytes());
+        self.stream.write_all(&packet)?;
+        self.stream.flush()
+    }
+
+    // Returns whether the client sent an interrupt while the target runs,
+    // without waiting for one.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut bytes = [0; 64];
+        let result = self.stream.read(&mut bytes);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                let interrupted = bytes[..n].contains(&INTERRUPT);
+                self.buffer.extend(bytes[..n].iter().filter(|&&byte| byte != INTERRUPT));
+                Ok(interrupted)
+            },
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_and_checksum() {
+        // arrange
+        let data = "a$b}c";
+
+        // act
+        let escaped = escape(data);
+
+        // assert
+        assert_eq!(escaped, b"a}\x04b}]c");
+        assert_eq!(checksum(b"OK"), 0x9a);
+    }
+
+    #[test]
+    fn target_xml_describes_register_file() {
+        // arrange
+        let xml = target_xml();
+
+        // act
+        let registers = xml.matches("<reg ").count();
+
+        // assert
+        assert_eq!(registers, REGISTER_SIZES.len());
+        assert!(xml.contains(r#"<reg name="vf" bitsize="8""#));
+        assert!(xml.contains(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#));
+    }
+}
@@ -0,0 +1,177 @@
+//! Runs ROMs without a display, e.g. to golden-test them in CI.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::Chip8Error;
+use crate::processor::Processor;
+
+/// A key press or release applied at the start of a frame.
+///
+/// Parsed from `<frame>:<key><+|->`, e.g. `10:a+` presses key A at frame 10.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScriptedKey {
+    pub frame: usize,
+    pub key: usize,
+    pub pressed: bool
+}
+
+impl FromStr for ScriptedKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid key event '{}', expected <frame>:<key><+|->", s);
+        let (frame, key) = s.split_once(':').ok_or_else(invalid)?;
+        let pressed = match key.chars().last() {
+            Some('+') => true,
+            Some('-') => false,
+            _ => return Err(invalid())
+        };
+        let frame = frame.parse().map_err(|_| invalid())?;
+        let key = usize::from_str_radix(&key[..key.len() - 1], 16)
+            .ok()
+            .filter(|&key| key < 16)
+            .ok_or_else(invalid)?;
+        Ok(ScriptedKey{ frame, key, pressed })
+    }
+}
+
+impl fmt::Display for ScriptedKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{:x}{}", self.frame, self.key, if self.pressed { '+' } else { '-' })
+    }
+}
+
+/// Output format of [`dump_screen`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenFormat {
+    /// One line per row, `#` for set and `.` for unset pixels.
+    Ascii,
+    /// Plain (`P1`) portable bitmap.
+    Pbm
+}
+
+impl FromStr for ScreenFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(ScreenFormat::Ascii),
+            "pbm" => Ok(ScreenFormat::Pbm),
+            _ => Err(format!("unknown screen format '{}', expected ascii or pbm", s))
+        }
+    }
+}
+
+impl fmt::Display for ScreenFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScreenFormat::Ascii => write!(f, "ascii"),
+            ScreenFormat::Pbm => write!(f, "pbm")
+        }
+    }
+}
+
+/// Runs the processor for `frames` frames of `instructions_per_frame`
+/// instructions, applying the scripted key events at the start of their frame.
+/// Frames are counted from the start of this run.
+///
+/// Stops at the first instruction that fails and returns its error.
+pub fn run(processor: &mut Processor, frames: usize, instructions_per_frame: usize, script: &[ScriptedKey]) -> Result<(), Chip8Error> {
+    run_with(processor, frames, script, |processor| processor.run_frame(instructions_per_frame))
+}
+
+/// Like [`run`], but emulates each frame with `run_frame`, e.g. a
+/// [`Tracer`](crate::trace::Tracer)'s.
+pub fn run_with(
+    processor: &mut Processor,
+    frames: usize,
+    script: &[ScriptedKey],
+    mut run_frame: impl FnMut(&mut Processor) -> Result<(), Chip8Error>
+) -> Result<(), Chip8Error> {
+    for frame in 0..frames {
+        for event in script.iter().filter(|event| event.frame == frame) {
+            processor.set_key(event.key, event.pressed);
+        }
+        run_frame(processor)?;
+    }
+    Ok(())
+}
+
+/// Renders the current screen of the processor in the given format.
+pub fn dump_screen(processor: &Processor, format: ScreenFormat) -> String {
+    let (width, height) = (processor.screen_width(), processor.screen_height());
+    let mut out = String::new();
+    if format == ScreenFormat::Pbm {
+        out.push_str(&format!("P1\n{} {}\n", width, height));
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = processor.pixel(x, y);
+            out.push(match (format, pixel) {
+                (ScreenFormat::Ascii, true) => '#',
+                (ScreenFormat::Ascii, false) => '.',
+                (ScreenFormat::Pbm, true) => '1',
+                (ScreenFormat::Pbm, false) => '0'
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::SCREEN_HEIGHT;
+    use crate::quirks::Quirks;
+
+    fn run_rom(rom: &[u8], frames: usize, instructions_per_frame: usize, script: &[ScriptedKey]) -> Processor {
+        let mut processor = Processor::new(rom, Quirks::default());
+        run(&mut processor, frames, instructions_per_frame, script).unwrap();
+        processor
+    }
+
+    #[test]
+    fn parse_scripted_key() {
+        assert_eq!("10:a+".parse(), Ok(ScriptedKey{ frame: 10, key: 0xa, pressed: true }));
+        assert_eq!("0:F-".parse(), Ok(ScriptedKey{ frame: 0, key: 0xf, pressed: false }));
+        assert!("10:10+".parse::<ScriptedKey>().is_err());
+        assert!("10:a".parse::<ScriptedKey>().is_err());
+        assert!("a+".parse::<ScriptedKey>().is_err());
+    }
+
+    #[test]
+    fn run_applies_script_at_frame() {
+        // arrange
+        // F30A: wait for key into V3
+        let rom = [0xf3, 0x0a, 0x12, 0x02];
+        let script = [ScriptedKey{ frame: 3, key: 0x7, pressed: true }];
+
+        // act
+        let early = run_rom(&rom, 3, 10, &script);
+        let late = run_rom(&rom, 4, 10, &script);
+
+        // assert
+        assert_eq!(early.registers()[3], 0);
+        assert_eq!(late.registers()[3], 0x7);
+        assert!(late.is_halted());
+    }
+
+    #[test]
+    fn dump_screen_pbm() {
+        // arrange
+        // 6000: V0 = 0, A050: I = font '0', D001: draw one row at (0, 0)
+        let processor = run_rom(&[0x60, 0x00, 0xa0, 0x50, 0xd0, 0x01], 1, 3, &[]);
+
+        // act
+        let pbm = dump_screen(&processor, ScreenFormat::Pbm);
+
+        // assert
+        let mut lines = pbm.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("64 32"));
+        assert_eq!(lines.next(), Some(format!("11110000{}", "0".repeat(56)).as_str()));
+        assert_eq!(lines.count(), SCREEN_HEIGHT - 1);
+    }
+}
@@ -0,0 +1,322 @@
+//! Decoded instructions, shared by the interpreter, the disassembler and tools.
+//!
+//! [`decode`] splits an opcode into its nibbles once and names the
+//! instruction; [`encode`] turns an [`Instruction`] back into its opcode.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::platform::Platform;
+
+/// A decoded instruction. `x` and `y` are register numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// `00CN`, SUPER-CHIP.
+    ScrollDown(u8),
+    /// `00DN`, XO-CHIP.
+    ScrollUp(u8),
+    /// `00E0`
+    Clear,
+    /// `00EE`
+    Return,
+    /// `00FB`, SUPER-CHIP.
+    ScrollRight,
+    /// `00FC`, SUPER-CHIP.
+    ScrollLeft,
+    /// `00FD`, SUPER-CHIP.
+    Exit,
+    /// `00FE`, SUPER-CHIP.
+    LowRes,
+    /// `00FF`, SUPER-CHIP.
+    HighRes,
+    /// `1NNN`
+    Jump(u16),
+    /// `2NNN`
+    Call(u16),
+    /// `3XNN`
+    SkipIfEqual { x: u8, nn: u8 },
+    /// `4XNN`
+    SkipIfNotEqual { x: u8, nn: u8 },
+    /// `5XY0`
+    SkipIfRegistersEqual { x: u8, y: u8 },
+    /// `5XY2`, XO-CHIP.
+    SaveRange { x: u8, y: u8 },
+    /// `5XY3`, XO-CHIP.
+    LoadRange { x: u8, y: u8 },
+    /// `6XNN`
+    Set { x: u8, nn: u8 },
+    /// `7XNN`
+    Add { x: u8, nn: u8 },
+    /// `8XY0`
+    Move { x: u8, y: u8 },
+    /// `8XY1`
+    Or { x: u8, y: u8 },
+    /// `8XY2`
+    And { x: u8, y: u8 },
+    /// `8XY3`
+    Xor { x: u8, y: u8 },
+    /// `8XY4`
+    AddRegisters { x: u8, y: u8 },
+    /// `8XY5`
+    Subtract { x: u8, y: u8 },
+    /// `8XY6`
+    ShiftRight { x: u8, y: u8 },
+    /// `8XY7`
+    SubtractReversed { x: u8, y: u8 },
+    /// `8XYE`
+    ShiftLeft { x: u8, y: u8 },
+    /// `9XY0`
+    SkipIfRegistersNotEqual { x: u8, y: u8 },
+    /// `ANNN`
+    SetI(u16),
+    /// `BNNN`
+    JumpOffset(u16),
+    /// `CXNN`
+    Random { x: u8, nn: u8 },
+    /// `DXYN`
+    Draw { x: u8, y: u8, n: u8 },
+    /// `EX9E`
+    SkipIfKey(u8),
+    /// `EXA1`
+    SkipIfNotKey(u8),
+    /// `F000 NNNN`, XO-CHIP. The address is the word following the opcode.
+    SetILong,
+    /// `FN01`, XO-CHIP.
+    Plane(u8),
+    /// `F002`, XO-CHIP.
+    Audio,
+    /// `FX07`
+    GetDelay(u8),
+    /// `FX0A`
+    WaitKey(u8),
+    /// `FX15`
+    SetDelay(u8),
+    /// `FX18`
+    SetSound(u8),
+    /// `FX1E`
+    AddI(u8),
+    /// `FX29`
+    Font(u8),
+    /// `FX30`, SUPER-CHIP.
+    BigFont(u8),
+    /// `FX33`
+    Bcd(u8),
+    /// `FX3A`, XO-CHIP.
+    Pitch(u8),
+    /// `FX55`
+    Store(u8),
+    /// `FX65`
+    Load(u8),
+    /// `FX75`, SUPER-CHIP.
+    SaveFlags(u8),
+    /// `FX85`, SUPER-CHIP.
+    LoadFlags(u8)
+}
+
+/// Decodes `opcode` as an instruction of `platform`.
+pub fn decode(opcode: u16, platform: Platform) -> Result<Instruction, DecodeError> {
+    use Instruction::*;
+
+    let schip = platform != Platform::Chip8;
+    let xo = platform == Platform::XoChip;
+    let x = ((opcode & 0x0f00) >> 8) as u8;
+    let y = ((opcode & 0x00f0) >> 4) as u8;
+    let n = (opcode & 0x000f) as u8;
+    let nn = (opcode & 0x00ff) as u8;
+    let nnn = opcode & 0x0fff;
+    let instruction = match opcode >> 12 {
+        0x0 => match nnn {
+            0x0c0..=0x0cf if schip => ScrollDown(n),
+            0x0d0..=0x0df if xo => ScrollUp(n),
+            0x0e0 => Clear,
+            0x0ee => Return,
+            0x0fb if schip => ScrollRight,
+            0x0fc if schip => ScrollLeft,
+            0x0fd if schip => Exit,
+            0x0fe if schip => LowRes,
+            0x0ff if schip => HighRes,
+            _ => return Err(DecodeError{ opcode, platform })
+        },
+        0x1 => Jump(nnn),
+        0x2 => Call(nnn),
+        0x3 => SkipIfEqual{ x, nn },
+        0x4 => SkipIfNotEqual{ x, nn },
+        0x5 => match n {
+            0x0 => SkipIfRegistersEqual{ x, y },
+            0x2 if xo => SaveRange{ x, y },
+            0x3 if xo => LoadRange{ x, y },
+            _ => return Err(DecodeError{ opcode, platform })
+        },
+        0x6 => Set{ x, nn },
+        0x7 => Add{ x, nn },
+        0x8 => match n {
+            0x0 => Move{ x, y },
+            0x1 => Or{ x, y },
+            0x2 => And{ x, y },
+            0x3 => Xor{ x, y },
+            0x4 => AddRegisters{ x, y },
+            0x5 => Subtract{ x, y },
+            0x6 => ShiftRight{ x, y },
+            0x7 => SubtractReversed{ x, y },
+            0xe => ShiftLeft{ x, y },
+            _ => return Err(DecodeError{ opcode, platform })
+        },
+        0x9 if n == 0 => SkipIfRegistersNotEqual{ x, y },
+        0xa => SetI(nnn),
+        0xb => JumpOffset(nnn),
+        0xc => Random{ x, nn },
+        0xd => Draw{ x, y, n },
+        0xe => match nn {
+            0x9e => SkipIfKey(x),
+            0xa1 => SkipIfNotKey(x),
+            _ => return Err(DecodeError{ opcode, platform })
+        },
+        0xf => match nn {
+            0x00 if xo && x == 0 => SetILong,
+            0x01 if xo => Plane(x),
+            0x02 if xo && x == 0 => Audio,
+            0x07 => GetDelay(x),
+            0x0a => WaitKey(x),
+            0x15 => SetDelay(x),
+            0x18 => SetSound(x),
+            0x1e => AddI(x),
+            0x29 => Font(x),
+            0x30 if schip => BigFont(x),
+            0x33 => Bcd(x),
+            0x3a if xo => Pitch(x),
+            0x55 => Store(x),
+            0x65 => Load(x),
+            0x75 if schip => SaveFlags(x),
+            0x85 if schip => LoadFlags(x),
+            _ => return Err(DecodeError{ opcode, platform })
+        },
+        _ => return Err(DecodeError{ opcode, platform })
+    };
+    Ok(instruction)
+}
+
+/// The opcode is not an instruction of the platform.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+    pub platform: Platform
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04x} is not a {} instruction", self.opcode, self.platform)
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Encodes an instruction as its opcode. The address of `F000 NNNN` is not
+/// part of the opcode.
+pub fn encode(instruction: Instruction) -> u16 {
+    use Instruction::*;
+
+    let xy = |op: u16, x: u8, y: u8, n: u16| op << 12 | (x as u16) << 8 | (y as u16) << 4 | n;
+    let xnn = |op: u16, x: u8, nn: u8| op << 12 | (x as u16) << 8 | nn as u16;
+    match instruction {
+        ScrollDown(n) => 0x00c0 | n as u16,
+        ScrollUp(n) => 0x00d0 | n as u16,
+        Clear => 0x00e0,
+        Return => 0x00ee,
+        ScrollRight => 0x00fb,
+        ScrollLeft => 0x00fc,
+        Exit => 0x00fd,
+        LowRes => 0x00fe,
+        HighRes => 0x00ff,
+        Jump(nnn) => 0x1000 | nnn,
+        Call(nnn) => 0x2000 | nnn,
+        SkipIfEqual{ x, nn } => xnn(0x3, x, nn),
+        SkipIfNotEqual{ x, nn } => xnn(0x4, x, nn),
+        SkipIfRegistersEqual{ x, y } => xy(0x5, x, y, 0x0),
+        SaveRange{ x, y } => xy(0x5, x, y, 0x2),
+        LoadRange{ x, y } => xy(0x5, x, y, 0x3),
+        Set{ x, nn } => xnn(0x6, x, nn),
+        Add{ x, nn } => xnn(0x7, x, nn),
+        Move{ x, y } => xy(0x8, x, y, 0x0),
+        Or{ x, y } => xy(0x8, x, y, 0x1),
+        And{ x, y } => xy(0x8, x, y, 0x2),
+        Xor{ x, y } => xy(0x8, x, y, 0x3),
+        AddRegisters{ x, y } => xy(0x8, x, y, 0x4),
+        Subtract{ x, y } => xy(0x8, x, y, 0x5),
+        ShiftRight{ x, y } => xy(0x8, x, y, 0x6),
+        SubtractReversed{ x, y } => xy(0x8, x, y, 0x7),
+        ShiftLeft{ x, y } => xy(0x8, x, y, 0xe),
+        SkipIfRegistersNotEqual{ x, y } => xy(0x9, x, y, 0x0),
+        SetI(nnn) => 0xa000 | nnn,
+        JumpOffset(nnn) => 0xb000 | nnn,
+        Random{ x, nn } => xnn(0xc, x, nn),
+        Draw{ x, y, n } => xy(0xd, x, y, n as u16),
+        SkipIfKey(x) => xnn(0xe, x, 0x9e),
+        SkipIfNotKey(x) => xnn(0xe, x, 0xa1),
+        SetILong => 0xf000,
+        Plane(n) => xnn(0xf, n, 0x01),
+        Audio => 0xf002,
+        GetDelay(x) => xnn(0xf, x, 0x07),
+        WaitKey(x) => xnn(0xf, x, 0x0a),
+        SetDelay(x) => xnn(0xf, x, 0x15),
+        SetSound(x) => xnn(0xf, x, 0x18),
+        AddI(x) => xnn(0xf, x, 0x1e),
+        Font(x) => xnn(0xf, x, 0x29),
+        BigFont(x) => xnn(0xf, x, 0x30),
+        Bcd(x) => xnn(0xf, x, 0x33),
+        Pitch(x) => xnn(0xf, x, 0x3a),
+        Store(x) => xnn(0xf, x, 0x55),
+        Load(x) => xnn(0xf, x, 0x65),
+        SaveFlags(x) => xnn(0xf, x, 0x75),
+        LoadFlags(x) => xnn(0xf, x, 0x85)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_by_nibbles() {
+        assert_eq!(decode(0x6534, Platform::Chip8), Ok(Instruction::Set{ x: 5, nn: 0x34 }));
+        assert_eq!(decode(0xd015, Platform::Chip8), Ok(Instruction::Draw{ x: 0, y: 1, n: 5 }));
+        assert_eq!(decode(0x8ab6, Platform::Chip8), Ok(Instruction::ShiftRight{ x: 0xa, y: 0xb }));
+        assert_eq!(decode(0xf265, Platform::Chip8), Ok(Instruction::Load(2)));
+        assert!(decode(0x8008, Platform::Chip8).is_err());
+        assert!(decode(0x0123, Platform::Chip8).is_err());
+    }
+
+    #[test]
+    fn decode_depends_on_platform() {
+        assert!(decode(0x00ff, Platform::Chip8).is_err());
+        assert_eq!(decode(0x00ff, Platform::SuperChip), Ok(Instruction::HighRes));
+        assert!(decode(0x00d2, Platform::SuperChip).is_err());
+        assert_eq!(decode(0x00d2, Platform::XoChip), Ok(Instruction::ScrollUp(2)));
+        assert_eq!(decode(0xf000, Platform::XoChip), Ok(Instruction::SetILong));
+        assert_eq!(decode(0xf201, Platform::XoChip), Ok(Instruction::Plane(2)));
+    }
+
+    #[test]
+    fn encode_reverses_decode_for_every_opcode() {
+        for platform in [Platform::Chip8, Platform::SuperChip, Platform::XoChip] {
+            for opcode in 0..=u16::MAX {
+                // act
+                let result = decode(opcode, platform);
+
+                // assert
+                match result {
+                    Ok(instruction) => assert_eq!(encode(instruction), opcode, "{:?} on {}", instruction, platform),
+                    Err(error) => assert_eq!(error, DecodeError{ opcode, platform })
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decode_rejects_unknown_opcodes() {
+        assert_eq!(decode(0x0123, Platform::XoChip), Err(DecodeError{ opcode: 0x0123, platform: Platform::XoChip }));
+        assert_eq!(decode(0xf100, Platform::XoChip).map_err(|error| error.to_string()), Err("0xf100 is not a xochip instruction".to_string()));
+        assert!(decode(0x5121, Platform::XoChip).is_err());
+        assert!(decode(0x9121, Platform::Chip8).is_err());
+        assert!(decode(0xe19f, Platform::Chip8).is_err());
+    }
+}
@@ -1,2 +1,40 @@
-mod processor;
-mod utils;
\ No newline at end of file
+//! A CHIP-8 emulator core.
+//!
+//! The [`Processor`] is host independent and can be driven from native code
+//! or tests. The WebAssembly bindings used by the web frontend live in the
+//! `wasm` module and are only built with the `wasm` feature.
+
+#[macro_use]
+mod utils;
+pub mod asm;
+pub mod audio;
+#[cfg(feature = "wall-clock")]
+pub mod clock;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod error;
+#[cfg(all(feature = "gdb", not(target_arch = "wasm32")))]
+pub mod gdb;
+pub mod headless;
+pub mod instruction;
+pub mod movie;
+pub mod platform;
+pub mod processor;
+pub mod profiler;
+pub mod quirks;
+pub mod random;
+pub mod render;
+pub mod rewind;
+pub mod state;
+pub mod timing;
+pub mod trace;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use error::Chip8Error;
+pub use instruction::Instruction;
+pub use platform::Platform;
+pub use processor::{Processor, StepDelta, StepOutcome};
+pub use quirks::Quirks;
+pub use timing::Timing;
@@ -0,0 +1,345 @@
+//! Input movies: recording key events and replaying them deterministically.
+//!
+//! A [`Movie`] holds everything needed to reproduce a run: the hash of the
+//! ROM, the platform, quirks, random seed and speed, and every key event tagged
+//! with the frame at whose start it was applied. Movies are stored as text:
+//!
+//! ```text
+//! chip8-movie 1
+//! rom 2a1bc0d5e0f3a471
+//! platform chip8
+//! quirks vf_reset clip_sprites
+//! seed 1234
+//! ipf 15
+//! frames 600
+//! key 10:a+
+//! key 14:a-
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::Chip8Error;
+use crate::headless::{self, ScriptedKey};
+use crate::platform::Platform;
+use crate::processor::Processor;
+use crate::quirks::Quirks;
+
+const HEADER: &str = "chip8-movie 1";
+const QUIRK_NAMES: [&str; 6] = [
+    "shift_uses_vy",
+    "load_store_increments_i",
+    "jump_uses_vx",
+    "vf_reset",
+    "clip_sprites",
+    "display_wait"
+];
+
+/// Why a movie could not be read or played.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    /// The movie file is malformed at the given line, counted from 1.
+    Parse { line: usize, message: String },
+    /// The movie was recorded with a different ROM.
+    RomMismatch { expected: u64, actual: u64 }
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Parse{ line, message } => write!(f, "movie line {}: {}", line, message),
+            MovieError::RomMismatch{ expected, actual } =>
+                write!(f, "movie was recorded with ROM {:016x}, not {:016x}", expected, actual)
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+/// 64-bit FNV-1a hash identifying a ROM.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+/// A recorded run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub seed: u32,
+    pub instructions_per_frame: usize,
+    /// Length of the run in frames.
+    pub frames: usize,
+    /// Key events in the order they happened.
+    pub events: Vec<ScriptedKey>
+}
+
+impl Movie {
+
+    /// Creates a processor for `rom` in the movie's initial state.
+    ///
+    /// Fails if `rom` is not the ROM the movie was recorded with.
+    pub fn start(&self, rom: &[u8]) -> Result<Processor, MovieError> {
+        let actual = rom_hash(rom);
+        if actual != self.rom_hash {
+            return Err(MovieError::RomMismatch{ expected: self.rom_hash, actual });
+        }
+        let mut processor = Processor::with_platform(rom, self.platform, self.quirks);
+        processor.seed_random(self.seed);
+        Ok(processor)
+    }
+
+    /// Replays the whole movie on `rom` and returns the processor at its end.
+    pub fn play(&self, rom: &[u8]) -> Result<Result<Processor, Chip8Error>, MovieError> {
+        let mut processor = self.start(rom)?;
+        Ok(headless::run(&mut processor, self.frames, self.instructions_per_frame, &self.events)
+            .map(|_| processor))
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "platform {}", self.platform)?;
+        write!(f, "quirks")?;
+        for (name, enabled) in QUIRK_NAMES.iter().zip(quirk_flags(&self.quirks)) {
+            if enabled {
+                write!(f, " {}", name)?;
+            }
+        }
+        writeln!(f)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "ipf {}", self.instructions_per_frame)?;
+        writeln!(f, "frames {}", self.frames)?;
+        for event in &self.events {
+            writeln!(f, "key {}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+        match lines.next() {
+            Some((_, HEADER)) => (),
+            _ => return Err(MovieError::Parse{ line: 1, message: format!("expected '{}'", HEADER) })
+        }
+
+        let mut rom_hash = None;
+        let mut platform = None;
+        let mut quirks = None;
+        let mut seed = None;
+        let mut instructions_per_frame = None;
+        let mut frames = None;
+        let mut events = Vec::new();
+        let mut last_line = 1;
+        for (line, text) in lines {
+            last_line = line;
+            if text.is_empty() {
+                continue;
+            }
+            let error = |message: String| MovieError::Parse{ line, message };
+            let (field, value) = text.split_once(' ').unwrap_or((text, ""));
+            let invalid = || error(format!("invalid {} '{}'", field, value));
+            match field {
+                "rom" => rom_hash = Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?),
+                "platform" => platform = Some(value.parse().map_err(error)?),
+                "quirks" => quirks = Some(parse_quirks(value).map_err(error)?),
+                "seed" => seed = Some(value.parse().map_err(|_| invalid())?),
+                "ipf" => instructions_per_frame = Some(value.parse().map_err(|_| invalid())?),
+                "frames" => frames = Some(value.parse().map_err(|_| invalid())?),
+                "key" => events.push(value.parse().map_err(error)?),
+                _ => return Err(error(format!("unknown field '{}'", field)))
+            }
+        }
+
+        let missing = |field: &str| MovieError::Parse{ line: last_line, message: format!("missing {}", field) };
+        Ok(Movie{
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            platform: platform.ok_or_else(|| missing("platform"))?,
+            quirks: quirks.ok_or_else(|| missing("quirks"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            instructions_per_frame: instructions_per_frame.ok_or_else(|| missing("ipf"))?,
+            frames: frames.ok_or_else(|| missing("frames"))?,
+            events
+        })
+    }
+}
+
+fn quirk_flags(quirks: &Quirks) -> [bool; 6] {
+    [
+        quirks.shift_uses_vy,
+        quirks.load_store_increments_i,
+        quirks.jump_uses_vx,
+        quirks.vf_reset,
+        quirks.clip_sprites,
+        quirks.display_wait
+    ]
+}
+
+fn parse_quirks(names: &str) -> Result<Quirks, String> {
+    let mut quirks = Quirks::default();
+    for name in names.split_whitespace() {
+        let flag = match name {
+            "shift_uses_vy" => &mut quirks.shift_uses_vy,
+            "load_store_increments_i" => &mut quirks.load_store_increments_i,
+            "jump_uses_vx" => &mut quirks.jump_uses_vx,
+            "vf_reset" => &mut quirks.vf_reset,
+            "clip_sprites" => &mut quirks.clip_sprites,
+            "display_wait" => &mut quirks.display_wait,
+            _ => return Err(format!("unknown quirk '{}'", name))
+        };
+        *flag = true;
+    }
+    Ok(quirks)
+}
+
+/// Records the key events of a run into a [`Movie`].
+///
+/// Key events go through the recorder instead of directly to the processor,
+/// and frames are run with [`Recorder::run_frame`].
+pub struct Recorder {
+    movie: Movie,
+    key_state: [bool; 16]
+}
+
+impl Recorder {
+
+    /// Restarts `processor` with `rom` and `seed` and starts recording.
+    pub fn start(processor: &mut Processor, rom: &[u8], seed: u32, instructions_per_frame: usize) -> Self {
+        processor.load_rom(rom);
+        processor.seed_random(seed);
+        Recorder{
+            movie: Movie{
+                rom_hash: rom_hash(rom),
+                platform: processor.platform(),
+                quirks: processor.quirks(),
+                seed,
+                instructions_per_frame,
+                frames: 0,
+                events: Vec::new()
+            },
+            key_state: [false; 16]
+        }
+    }
+
+    /// Presses or releases a key and records the change for the next frame.
+    pub fn set_key(&mut self, processor: &mut Processor, key: usize, pressed: bool) {
+        if key >= self.key_state.len() {
+            return;
+        }
+        processor.set_key(key, pressed);
+        if self.key_state[key] != pressed {
+            self.key_state[key] = pressed;
+            self.movie.events.push(ScriptedKey{ frame: self.movie.frames, key, pressed });
+        }
+    }
+
+    pub fn key_pressed(&mut self, processor: &mut Processor, key: usize) {
+        self.set_key(processor, key, true);
+    }
+
+    pub fn key_released(&mut self, processor: &mut Processor, key: usize) {
+        self.set_key(processor, key, false);
+    }
+
+    /// Emulates and records one frame, see [`Processor::run_frame`].
+    pub fn run_frame(&mut self, processor: &mut Processor) -> Result<(), Chip8Error> {
+        self.movie.frames += 1;
+        processor.run_frame(self.movie.instructions_per_frame)
+    }
+
+    /// The movie recorded so far.
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // F00A: wait for key into V0, F029: I = font V0, 6100: V1 = 0, C23F: V2 = random,
+    // 00E0: clear, D125: draw, 1200: loop
+    const KEY_ECHO: [u8; 14] = [0xf0, 0x0a, 0xf0, 0x29, 0x61, 0x00, 0xc2, 0x3f, 0x00, 0xe0, 0xd1, 0x25, 0x12, 0x00];
+
+    fn record() -> (Movie, Processor) {
+        let mut processor = Processor::new(&[], Quirks::default());
+        let mut recorder = Recorder::start(&mut processor, &KEY_ECHO, 77, 10);
+        for frame in 0..20 {
+            match frame {
+                3 => recorder.key_pressed(&mut processor, 0x7),
+                5 => recorder.key_released(&mut processor, 0x7),
+                9 => recorder.key_pressed(&mut processor, 0xc),
+                _ => ()
+            }
+            recorder.run_frame(&mut processor).unwrap();
+        }
+        (recorder.finish(), processor)
+    }
+
+    #[test]
+    fn play_reproduces_recorded_run() {
+        // arrange
+        let (movie, recorded) = record();
+
+        // act
+        let played = movie.play(&KEY_ECHO).unwrap().unwrap();
+
+        // assert
+        assert_eq!(movie.frames, 20);
+        assert_eq!(movie.events.len(), 3);
+        assert_eq!(played.save_state(), recorded.save_state());
+    }
+
+    #[test]
+    fn movie_text_round_trip() {
+        // arrange
+        let (mut movie, _) = record();
+        movie.quirks = Quirks::COSMAC_VIP;
+        movie.platform = Platform::SuperChip;
+
+        // act
+        let parsed: Movie = movie.to_string().parse().unwrap();
+
+        // assert
+        assert_eq!(parsed, movie);
+        assert!(movie.to_string().contains("\nkey 3:7+\n"));
+    }
+
+    #[test]
+    fn start_rejects_other_rom() {
+        // arrange
+        let (movie, _) = record();
+
+        // act
+        let result = movie.start(&[0x12, 0x00]);
+
+        // assert
+        assert_eq!(result.err(), Some(MovieError::RomMismatch{ expected: rom_hash(&KEY_ECHO), actual: rom_hash(&[0x12, 0x00]) }));
+    }
+
+    #[test]
+    fn parse_reports_line() {
+        // arrange
+        let text = "chip8-movie 1\nrom 00ff\nplatform chip8\nkey 3:q+\n";
+
+        // act
+        let result = text.parse::<Movie>();
+
+        // assert
+        assert!(matches!(result, Err(MovieError::Parse{ line: 4, .. })));
+        assert!(matches!("chip8-movie 1\n".parse::<Movie>(), Err(MovieError::Parse{ line: 1, .. })));
+        assert!(matches!("rom 00ff\n".parse::<Movie>(), Err(MovieError::Parse{ line: 1, .. })));
+    }
+}
@@ -0,0 +1,80 @@
+//! The instruction set variants the processor can emulate.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::processor::{RAM_SIZE_BYTE, XO_CHIP_RAM_SIZE_BYTE};
+use crate::quirks::Quirks;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Platform {
+    /// The original CHIP-8 instruction set with a 64x32 display.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1: adds a 128x64 high resolution mode, scrolling,
+    /// 16x16 sprites, a large font and the RPL user flags.
+    SuperChip,
+    /// XO-CHIP: SUPER-CHIP plus 64 KiB of memory, two bit-planes for up to
+    /// four colours, programmable audio patterns and a few new opcodes.
+    XoChip
+}
+
+impl Platform {
+
+    /// The quirks ROMs written for this platform usually expect.
+    pub fn default_quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::SUPER_CHIP,
+            Platform::XoChip => Quirks::XO_CHIP
+        }
+    }
+
+    /// Size of the memory in bytes.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => RAM_SIZE_BYTE,
+            Platform::XoChip => XO_CHIP_RAM_SIZE_BYTE
+        }
+    }
+}
+
+/// Parses a platform name: `chip8`, `schip` or `xochip`.
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" | "superchip" => Ok(Platform::SuperChip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!("unknown platform '{}', expected chip8, schip or xochip", s))
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "chip8"),
+            Platform::SuperChip => write!(f, "schip"),
+            Platform::XoChip => write!(f, "xochip")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_platform_names() {
+        assert_eq!("chip8".parse(), Ok(Platform::Chip8));
+        assert_eq!("CHIP-8".parse(), Ok(Platform::Chip8));
+        assert_eq!("super-chip".parse(), Ok(Platform::SuperChip));
+        assert_eq!(Platform::SuperChip.to_string().parse(), Ok(Platform::SuperChip));
+        assert_eq!("XO-CHIP".parse(), Ok(Platform::XoChip));
+        assert_eq!(Platform::XoChip.to_string().parse(), Ok(Platform::XoChip));
+        assert!("megachip".parse::<Platform>().is_err());
+    }
+}
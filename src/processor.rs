@@ -1,479 +1,1774 @@
-use wasm_bindgen::prelude::*;
-use rand::prelude::*;
-use crate::utils::set_panic_hook;
-extern crate web_sys;
+use std::mem;
+use std::ops::Range;
+
+use crate::display::Display;
+pub use crate::display::{HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::error::Chip8Error;
+use crate::instruction::{decode, DecodeError, Instruction};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::random::{RandomSource, XorShift};
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::timing::{self, Timing, VIP_INTERPRETER_CYCLES};
+
+/// Address at which ROMs are loaded and execution starts.
+pub const PC_START: usize = 0x200;
+/// Size of the emulated memory in bytes.
+pub const RAM_SIZE_BYTE: usize = 4096;
+/// Size of the XO-CHIP memory in bytes, addressable with `F000 NNNN`.
+pub const XO_CHIP_RAM_SIZE_BYTE: usize = 0x10000;
+/// XO-CHIP pitch register value at which the audio pattern plays at 4000 bits per second.
+pub const DEFAULT_PITCH: u8 = 64;
+const FONT_START: usize = 0x50;
+const BIG_FONT_START: usize = 0xa0;
+const VF: usize = 0xf;
 
-// When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
-// allocator.
-#[cfg(feature = "wee_alloc")]
-#[global_allocator]
-static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
+const FONT_SET: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80  // F
+];
+
+// SUPER-CHIP 8x10 font, selected by FX30.
+const BIG_FONT_SET: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+/// Snapshot of the buzzer driven by the sound timer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SoundState {
+    /// Whether the buzzer should currently sound.
+    pub active: bool,
+    /// Remaining 60 Hz frames until the buzzer stops.
+    pub remaining_frames: u8,
+    /// The XO-CHIP audio pattern loaded by `F002`, 128 one-bit samples.
+    /// The buzzer plays a square wave while no pattern was loaded.
+    pub pattern: Option<[u8; 16]>,
+    /// The XO-CHIP pitch register set by `FX3A`, see [`DEFAULT_PITCH`].
+    pub pitch: u8
+}
 
-macro_rules! log {
-    ( $( $t:tt )* ) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into());
-    }
+/// What a call to [`Processor::step`] did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed.
+    Executed,
+    /// `FX0A` is waiting for a key press, nothing was executed.
+    WaitingForKey,
+    /// A draw is waiting for the next frame, see [`Quirks::display_wait`].
+    WaitingForVblank,
+    /// The processor is halted, nothing was executed.
+    Halted
 }
 
-const PC_START: usize = 0x200;
-const RAM_SIZE_BYTE: usize = 4096;
-const FONT_START: usize = 0x50;
-const VRAM_START: usize = 0xf00;
-const VRAM_SIZE_BYTE: usize = 256;
-const VF: usize = 0xf;
-const SCREEN_HEIGHT: usize = 32;
-const SCREEN_WIDTH: usize = 64;
+/// Everything a single step changed, see [`Processor::step_with_delta`].
+#[derive(Clone)]
+pub struct StepDelta {
+    // The processor before the step, without memory and display.
+    before: Box<Processor>,
+    // The previous value of each written address, in the order written.
+    memory: Vec<(usize, u8)>,
+    // The display before the step, if the step changed it.
+    display: Option<Display>
+}
+
+impl StepDelta {
 
-#[wasm_bindgen]
+    /// Approximate number of bytes the delta occupies.
+    pub fn size(&self) -> usize {
+        mem::size_of::<Self>()
+            + mem::size_of::<Processor>()
+            + self.memory.len() * mem::size_of::<(usize, u8)>()
+            + self.display.as_ref().map_or(0, |display| display.pixels().len())
+    }
+
+    /// The addresses the step wrote to, in the order written.
+    pub fn written(&self) -> impl Iterator<Item = usize> + '_ {
+        self.memory.iter().map(|&(address, _)| address)
+    }
+}
+
+/// The CHIP-8 virtual machine: memory, registers, timers and keypad.
+#[derive(Clone)]
 pub struct Processor {
-    ram: [u8; RAM_SIZE_BYTE],
+    ram: Vec<u8>,
     stack: [usize; 12],
     v: [u8; 16],
     pc: usize,
     sp: usize,
     i: usize,
     delay_timer: u8,
-    time: instant::Instant,
     sound_timer: u8,
+    frames: u64,
     wait_key: bool,
     wait_key_reg: usize,
-    pub halt: bool,
-    key_state: [bool; 16]
+    halt: bool,
+    // The error that halted the processor, if any.
+    error: Option<Chip8Error>,
+    key_state: [bool; 16],
+    quirks: Quirks,
+    // Set after a draw when the display wait quirk is on, cleared by the next timer tick.
+    wait_vblank: bool,
+    timing: Timing,
+    // Machine cycles taken in the current frame with `Timing::CosmacVip`.
+    frame_cycles: usize,
+    platform: Platform,
+    // Kept outside of `ram` so that the resolution can change and programs
+    // can use all of memory.
+    display: Display,
+    // SUPER-CHIP RPL user flags, saved and restored by FX75/FX85.
+    rpl: [u8; 16],
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    rng: Box<dyn RandomSource>,
+    // Previous values of the memory written during `step_with_delta`.
+    write_log: Option<Vec<(usize, u8)>>
 }
 
-#[wasm_bindgen]
 impl Processor {
 
-    pub fn new(rom: Vec<u8>) -> Self {
-        set_panic_hook();
-
-        // Load rom
-        let mut ram = [0 as u8; RAM_SIZE_BYTE];
-        for i in 0..rom.len() {
-            ram[PC_START + i] = rom[i];
-        }
-
-        // Load font set
-        let font_set: Vec<u8> = vec![
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80  // F
-        ];
-        for i in 0..font_set.len() {
-            ram[FONT_START + i] = font_set[i];
-        }
+    /// Creates a CHIP-8 processor with the font set and the given ROM loaded.
+    pub fn new(rom: &[u8], quirks: Quirks) -> Self {
+        Processor::with_platform(rom, Platform::Chip8, quirks)
+    }
 
-        Processor{
-            ram,
-            stack: [0 as usize; 12],
-            v: [0 as u8; 16],
+    /// Creates a processor for the given platform with the fonts and the ROM loaded.
+    pub fn with_platform(rom: &[u8], platform: Platform, quirks: Quirks) -> Self {
+        let mut processor = Processor{
+            ram: Vec::new(),
+            stack: [0; 12],
+            v: [0; 16],
             pc: PC_START,
             sp: 0,
             i: 0,
             delay_timer: 0,
-            time: instant::Instant::now(),
             sound_timer: 0,
+            frames: 0,
             wait_key: false,
             wait_key_reg: 0,
             halt: false,
-            key_state: [false; 16]
-        }
+            error: None,
+            key_state: [false; 16],
+            quirks,
+            wait_vblank: false,
+            timing: Timing::default(),
+            frame_cycles: 0,
+            platform,
+            display: Display::new(),
+            rpl: [0; 16],
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            rng: Box::new(XorShift::from_entropy()),
+            write_log: None
+        };
+        processor.load_rom(rom);
+        processor
+    }
+
+    /// Resets the processor and loads the ROM at `PC_START`.
+    ///
+    /// Panics if the ROM does not fit into the platform's memory.
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        let ram_size = self.platform.memory_size();
+        assert!(rom.len() <= ram_size - PC_START, "ROM too large: {} bytes", rom.len());
+
+        self.ram = vec![0; ram_size];
+        self.ram[FONT_START..FONT_START + FONT_SET.len()].copy_from_slice(&FONT_SET);
+        self.ram[BIG_FONT_START..BIG_FONT_START + BIG_FONT_SET.len()].copy_from_slice(&BIG_FONT_SET);
+        self.ram[PC_START..PC_START + rom.len()].copy_from_slice(rom);
+
+        self.stack = [0; 12];
+        self.v = [0; 16];
+        self.pc = PC_START;
+        self.sp = 0;
+        self.i = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.frames = 0;
+        self.wait_key = false;
+        self.wait_key_reg = 0;
+        self.halt = false;
+        self.error = None;
+        self.key_state = [false; 16];
+        self.wait_vblank = false;
+        self.frame_cycles = 0;
+        self.display = Display::new();
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
     }
 
     pub fn key_pressed(&mut self, key: usize) {
-        self.key_state[key] = true;
+        self.set_key(key, true);
     }
 
     pub fn key_released(&mut self, key: usize) {
-        self.key_state[key] = false;
+        self.set_key(key, false);
     }
 
-    pub fn screen(&self) -> *const u8{
-        self.ram[VRAM_START..VRAM_START+VRAM_SIZE_BYTE].as_ptr()
+    /// Sets the state of a key on the hex keypad (0x0 - 0xF). Other keys are ignored.
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        if let Some(state) = self.key_state.get_mut(key) {
+            *state = pressed;
+        }
     }
 
-    pub fn tick(&mut self) {
-        if self.wait_key {
-            for i in 0..self.key_state.len() {
-                if self.key_state[i] {
-                    self.v[self.wait_key_reg] = i as u8;
-                    self.wait_key = false;
-                    self.pc+=2;
-                }
-            }
-        }
+    /// The display, one byte per pixel, row by row.
+    ///
+    /// Bit 0 of a pixel is plane 1 and bit 1 is plane 2, so XO-CHIP pixels
+    /// range from 0 to 3 while CHIP-8 and SUPER-CHIP pixels are 0 or 1.
+    pub fn framebuffer(&self) -> &[u8] {
+        self.display.pixels()
+    }
 
-        if self.wait_key {
-            return;
-        }
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
 
-        let elapsed = self.time.elapsed();
-        if self.delay_timer > 0 && elapsed.as_millis() >= 1000/60 {
-            self.delay_timer -= 1;
-            self.time = instant::Instant::now();
-        }
+    /// The display, e.g. to take its changes with [`Display::take_dirty_rects`].
+    pub fn display_mut(&mut self) -> &mut Display {
+        &mut self.display
+    }
 
-        let opcode = self.read_16_bit(self.pc);
-        self.execute_opcode(opcode);
+    /// Current width of the display in pixels.
+    pub fn screen_width(&self) -> usize {
+        self.display.width()
     }
 
-    fn read_16_bit(&self, pointer: usize) -> usize {
-        let left = self.ram[pointer] as u16;
-        let right = self.ram[pointer + 1] as u16;
-        (left << 8 | right).into()
+    /// Current height of the display in pixels.
+    pub fn screen_height(&self) -> usize {
+        self.display.height()
     }
 
-    // fn write_16_bit(&mut self, pointer: usize, value: usize) {
-    //     self.ram[pointer] = (value >> 8) as u8;
-    //     self.ram[pointer + 1] = value as u8;
-    // }
+    /// Returns whether the SUPER-CHIP high resolution mode is active.
+    pub fn is_hires(&self) -> bool {
+        self.display.is_hires()
+    }
+
+    /// Returns whether the pixel at (x, y) is set in any plane.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.display.pixel(x, y)
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    /// The return addresses currently on the stack, oldest first.
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Decodes the instruction at the program counter, which `step` executes next.
+    pub fn current_instruction(&self) -> Result<Instruction, Chip8Error> {
+        let opcode = self.read_16_bit(self.pc)?;
+        self.decode(opcode).map_err(|_| self.unknown_opcode(opcode))
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// The SUPER-CHIP RPL user flags.
+    pub fn rpl_flags(&self) -> &[u8; 16] {
+        &self.rpl
+    }
 
-    fn execute_opcode(&mut self, opcode: usize) {
-        //log!("Running opcode: 0x{opcode:0>4x}");
-        match opcode >> 12 {
-            0x0 => self.op_0(opcode),
-            0x1 => self.op_1(opcode),
-            0x2 => self.op_2(opcode),
-            0x3 => self.op_3(opcode),
-            0x4 => self.op_4(opcode),
-            0x5 => self.op_5(opcode),
-            0x6 => self.op_6(opcode),
-            0x7 => self.op_7(opcode),
-            0x8 => self.op_8(opcode),
-            0x9 => self.op_9(opcode),
-            0xa => self.op_a(opcode),
-            0xb => self.op_b(opcode),
-            0xc => self.op_c(opcode),
-            0xd => self.op_d(opcode),
-            0xe => self.op_e(opcode),
-            0xf => self.op_f(opcode),
-            _ => println!("Unknown opcode: 0x{opcode:0>4x}")
+    /// Replaces the source of the random numbers for `CXNN`, e.g. with a
+    /// seeded [`XorShift`] for reproducible runs.
+    pub fn set_random_source(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    /// Seeds the default random number source, see [`XorShift::new`].
+    pub fn seed_random(&mut self, seed: u32) {
+        self.rng = Box::new(XorShift::new(seed));
+    }
+
+    /// The XO-CHIP bit-planes drawn to and cleared, bit 0 being plane 1.
+    pub fn selected_planes(&self) -> u8 {
+        self.display.selected_planes()
+    }
+
+    /// Returns whether the processor stopped because the ROM jumped onto itself,
+    /// exited with `00FD` or an instruction failed.
+    pub fn is_halted(&self) -> bool {
+        self.halt
+    }
+
+    /// The error that halted the processor, if any.
+    pub fn error(&self) -> Option<Chip8Error> {
+        self.error
+    }
+
+    /// Returns whether the buzzer should sound, i.e. the sound timer is running.
+    pub fn is_sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    pub fn sound_state(&self) -> SoundState {
+        SoundState{
+            active: self.is_sound_active(),
+            remaining_frames: self.sound_timer,
+            pattern: self.audio_pattern,
+            pitch: self.pitch
         }
     }
 
-    fn op_0(&mut self, opcode: usize) {
-        match opcode {
-            // Clears the screen.
-            0xe0 => {
-                for i in 0..VRAM_SIZE_BYTE {
-                    self.ram[i + VRAM_START] = 0;
-                }
-            },
-            // Returns from a subroutine.
-            0xee => {
-                self.sp-=1;
-                self.pc = self.stack[self.sp];
-            },
-            _ => println!("Unknown opcode: 0x{opcode:0>4x}")
+    /// Number of 60 Hz frames, i.e. timer ticks, since the ROM was loaded.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Advances the timers by one 60 Hz frame.
+    ///
+    /// The host calls this once per frame; `run_frame` does so after executing
+    /// the frame's instructions.
+    pub fn timer_tick(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.frames += 1;
+        self.wait_vblank = false;
+        // An instruction that ran past the end of the frame delays the next one.
+        self.frame_cycles = self.frame_cycles.saturating_sub(VIP_INTERPRETER_CYCLES);
+        self.rng.timer_tick();
+    }
+
+    /// Emulates one 60 Hz frame: executes up to `cycles_per_frame` instructions,
+    /// or as many as fit into the frame with [`Timing::CosmacVip`], stopping
+    /// early if the processor halts, and then ticks the timers once.
+    ///
+    /// Returns the error if an instruction failed, without ticking the timers.
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> Result<(), Chip8Error> {
+        self.run_frame_with(cycles_per_frame, Processor::step)
+    }
+
+    /// Emulates one frame like [`Processor::run_frame`], executing every
+    /// instruction with `step` instead of [`Processor::step`]. Lets tools like
+    /// the tracer wrap each step without repeating the frame's timing.
+    pub fn run_frame_with(
+        &mut self,
+        cycles_per_frame: usize,
+        mut step: impl FnMut(&mut Processor) -> Result<StepOutcome, Chip8Error>
+    ) -> Result<(), Chip8Error> {
+        let mut steps = 0;
+        while self.frame_has_time(steps, cycles_per_frame) && !self.halt {
+            step(self)?;
+            steps += 1;
         }
-        self.pc += 2;
+        self.timer_tick();
+        Ok(())
     }
 
-    // 1NNN. Jumps to address NNN.
-    fn op_1(&mut self, opcode: usize) {
-        let address = opcode & 0x0fff;
-        // halt processor if loop detected.
-        if address == self.pc {
-            self.halt = true;
-            log!("Processor halted.");
+    /// Ends a step taken outside of [`Processor::run_frame`], the `steps`th of
+    /// the frame, by ticking the timers if the frame has no time left. Returns
+    /// whether a new frame started.
+    pub fn end_step(&mut self, steps: usize, cycles_per_frame: usize) -> bool {
+        if self.frame_has_time(steps, cycles_per_frame) {
+            return false;
+        }
+        self.timer_tick();
+        true
+    }
+
+    // Whether the frame has time for another step after `steps` steps: with
+    // `Timing::Instructions` if fewer than `cycles_per_frame` were taken, with
+    // `Timing::CosmacVip` if the frame's machine cycles are not used up yet.
+    fn frame_has_time(&self, steps: usize, cycles_per_frame: usize) -> bool {
+        match self.timing {
+            Timing::Instructions => steps < cycles_per_frame,
+            Timing::CosmacVip => self.frame_cycles < VIP_INTERPRETER_CYCLES
         }
-        self.pc = address;
     }
 
-    // 2NNN. Calls subroutine at NNN.
-    fn op_2(&mut self, opcode: usize) {
-        let address = opcode & 0x0fff;
-        self.stack[self.sp] = self.pc;
-        self.sp += 1;
-        self.pc = address;
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Selects how instructions are timed within a frame, see [`Timing`].
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.frame_cycles = 0;
     }
 
-    // 3XNN. Skips the next instruction if VX equals NN.
-    // Usually the next instruction is a jump to skip a code block.
-    fn op_3(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        let value = opcode & 0x00ff;
-        if self.v[reg_x] == value as u8 {
-            self.pc+=2;
+    /// Executes a single instruction, or polls the keypad while `FX0A` waits for a key.
+    ///
+    /// Does nothing while a draw waits for the next frame, see [`Quirks::display_wait`],
+    /// or while the processor is halted. A failing instruction halts the processor.
+    pub fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if self.halt {
+            return Ok(StepOutcome::Halted);
+        }
+        if self.wait_vblank {
+            self.idle_until_interrupt();
+            return Ok(StepOutcome::WaitingForVblank);
+        }
+
+        if self.wait_key {
+            match self.key_state.iter().position(|&pressed| pressed) {
+                Some(key) => {
+                    self.v[self.wait_key_reg] = key as u8;
+                    self.wait_key = false;
+                    self.pc+=2;
+                },
+                None => {
+                    self.idle_until_interrupt();
+                    return Ok(StepOutcome::WaitingForKey);
+                }
+            }
+        }
+
+        let (pc, v) = (self.pc, self.v);
+        let opcode = self.read_16_bit(pc);
+        let result = opcode.and_then(|opcode| self.execute_opcode(opcode));
+        if let Err(error) = result {
+            self.halt = true;
+            self.error = Some(error);
+            log!("Processor halted: {}", error);
+            return Err(error);
+        }
+        if let (Timing::CosmacVip, Ok(opcode)) = (self.timing, opcode) {
+            if let Ok(instruction) = self.decode(opcode) {
+                self.frame_cycles += timing::cosmac_vip_cycles(instruction, self.pc == pc + 4, &v);
+            }
         }
-        self.pc+=2;
+        Ok(StepOutcome::Executed)
     }
 
-    // 4XNN. Skips the next instruction if VX does not equal NN.
-    // Usually the next instruction is a jump to skip a code block.
-    fn op_4(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        let value = opcode & 0x00ff;
-        if self.v[reg_x] != value as u8 {
-            self.pc+=2;
+    // A waiting VIP interpreter does nothing until the next display interrupt.
+    fn idle_until_interrupt(&mut self) {
+        if self.timing == Timing::CosmacVip {
+            self.frame_cycles = self.frame_cycles.max(VIP_INTERPRETER_CYCLES);
         }
-        self.pc+=2;
     }
 
-    // 5XY0. Skips the next instruction if VX equals VY.
-    // Usually the next instruction is a jump to skip a code block.
-    fn op_5(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        let reg_y = (opcode & 0x00f0) >> 4;
-        if self.v[reg_x] == self.v[reg_y] {
-            self.pc+=2;
+    /// Executes a single instruction like [`Processor::step`] and returns what
+    /// it changed, so that [`Processor::undo_step`] can revert it.
+    pub fn step_with_delta(&mut self) -> (Result<StepOutcome, Chip8Error>, StepDelta) {
+        let ram = mem::take(&mut self.ram);
+        let display = mem::take(&mut self.display);
+        let before = Box::new(self.clone());
+        self.ram = ram;
+        self.display = display.clone();
+
+        self.write_log = Some(Vec::new());
+        let result = self.step();
+        let memory = self.write_log.take().unwrap_or_default();
+        let display = if self.display != display { Some(display) } else { None };
+        (result, StepDelta{ before, memory, display })
+    }
+
+    /// Reverts the step that returned `delta`. Deltas have to be undone
+    /// newest first, starting from the state right after their step.
+    pub fn undo_step(&mut self, delta: StepDelta) {
+        let mut ram = mem::take(&mut self.ram);
+        for &(address, value) in delta.memory.iter().rev() {
+            ram[address] = value;
+        }
+        let mut display = delta.display.unwrap_or_else(|| mem::take(&mut self.display));
+        *self = *delta.before;
+        self.ram = ram;
+        display.mark_dirty();
+        self.display = display;
+    }
+
+    fn read_16_bit(&self, pointer: usize) -> Result<usize, Chip8Error> {
+        let range = self.memory_range(pointer, 2)?;
+        let left = self.ram[range.start] as u16;
+        let right = self.ram[range.start + 1] as u16;
+        Ok((left << 8 | right).into())
+    }
+
+    // Returns the range of `len` bytes starting at `address`, if it lies within memory.
+    fn memory_range(&self, address: usize, len: usize) -> Result<Range<usize>, Chip8Error> {
+        if address + len <= self.ram.len() {
+            Ok(address..address + len)
+        } else {
+            Err(Chip8Error::MemoryOutOfBounds{ addr: address.max(self.ram.len()) })
         }
-        self.pc+=2;
     }
 
-    // 6XNN. Sets VX to NN.
-    fn op_6(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        let value = opcode & 0x00ff;
-        self.v[reg_x] = value as u8;
-        self.pc+=2;
+    /// Serializes the complete machine state, including memory, registers,
+    /// timers, keypad, display, random number generator, quirks and timing.
+    ///
+    /// The result can be restored with [`Processor::load_state`], also by later
+    /// versions of the emulator, see [`state`](crate::state).
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.u8(match self.platform {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2
+        });
+        state.quirks(self.quirks);
+        state.bytes(&self.ram);
+        state.u8(self.stack.len() as u8);
+        for &address in &self.stack {
+            state.u32(address as u32);
+        }
+        state.u8(self.sp as u8);
+        state.bytes(&self.v);
+        state.u32(self.pc as u32);
+        state.u32(self.i as u32);
+        state.u8(self.delay_timer);
+        state.u8(self.sound_timer);
+        state.u64(self.frames);
+        state.bool(self.wait_key);
+        state.u8(self.wait_key_reg as u8);
+        state.bool(self.wait_vblank);
+        state.bool(self.halt);
+        state.u32(self.key_state.iter().rev().fold(0, |keys, &pressed| keys << 1 | pressed as u32));
+        state.bool(self.display.is_hires());
+        state.bytes(self.display.pixels());
+        state.bytes(&self.rpl);
+        state.u8(self.display.selected_planes());
+        state.bool(self.audio_pattern.is_some());
+        state.bytes(&self.audio_pattern.unwrap_or_default());
+        state.u8(self.pitch);
+        state.bytes(&self.rng.save());
+        state.u8(match self.timing {
+            Timing::Instructions => 0,
+            Timing::CosmacVip => 1
+        });
+        state.u32(self.frame_cycles as u32);
+        state.finish()
+    }
+
+    /// Restores a state written by [`Processor::save_state`].
+    ///
+    /// The processor is left unchanged if the state cannot be loaded. Errors
+    /// that halted the processor are not part of the state.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(state)?;
+        let platform = match state.u8()? {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            _ => return Err(StateError::Invalid("platform"))
+        };
+        let quirks = state.quirks()?;
+        let ram = state.bytes()?;
+        if ram.len() != platform.memory_size() {
+            return Err(StateError::Invalid("memory size"));
+        }
+        let mut stack = [0; 12];
+        if state.u8()? as usize != stack.len() {
+            return Err(StateError::Invalid("stack size"));
+        }
+        for address in stack.iter_mut() {
+            *address = state.u32()? as usize;
+        }
+        let sp = state.u8()? as usize;
+        if sp > stack.len() {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        let v = state.array("registers")?;
+        let pc = state.u32()? as usize;
+        let i = state.u32()? as usize;
+        let delay_timer = state.u8()?;
+        let sound_timer = state.u8()?;
+        let frames = state.u64()?;
+        let wait_key = state.bool()?;
+        let wait_key_reg = state.u8()? as usize;
+        if wait_key_reg >= v.len() {
+            return Err(StateError::Invalid("key register"));
+        }
+        let wait_vblank = state.bool()?;
+        let halt = state.bool()?;
+        let keys = state.u32()?;
+        let hires = state.bool()?;
+        let screen = state.bytes()?;
+        let screen_len = if hires {
+            HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT
+        } else {
+            SCREEN_WIDTH * SCREEN_HEIGHT
+        };
+        if screen.len() != screen_len {
+            return Err(StateError::Invalid("display size"));
+        }
+        let rpl = state.array("user flags")?;
+        let planes = state.u8()?;
+        if planes > 0b11 {
+            return Err(StateError::Invalid("planes"));
+        }
+        let has_pattern = state.bool()?;
+        let pattern = state.array("audio pattern")?;
+        let pitch = state.u8()?;
+        // Version 1 stored the xorshift state as a plain integer.
+        let rng_state = if state.version() < 2 {
+            state.u32()?.to_le_bytes().to_vec()
+        } else {
+            state.bytes()?.to_vec()
+        };
+        let mut rng = self.rng.clone();
+        if !rng.restore(&rng_state) {
+            return Err(StateError::Invalid("random number generator state"));
+        }
+        // Versions 1 and 2 were written before there was a choice of timing.
+        let (timing, frame_cycles) = if state.version() < 3 {
+            (Timing::Instructions, 0)
+        } else {
+            let timing = match state.u8()? {
+                0 => Timing::Instructions,
+                1 => Timing::CosmacVip,
+                _ => return Err(StateError::Invalid("timing"))
+            };
+            (timing, state.u32()? as usize)
+        };
+        state.finish()?;
+
+        self.platform = platform;
+        self.quirks = quirks;
+        self.ram = ram.to_vec();
+        self.stack = stack;
+        self.sp = sp;
+        self.v = v;
+        self.pc = pc;
+        self.i = i;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.frames = frames;
+        self.wait_key = wait_key;
+        self.wait_key_reg = wait_key_reg;
+        self.wait_vblank = wait_vblank;
+        self.timing = timing;
+        self.frame_cycles = frame_cycles;
+        self.halt = halt;
+        self.error = None;
+        for (key, pressed) in self.key_state.iter_mut().enumerate() {
+            *pressed = keys & (1 << key) != 0;
+        }
+        self.display = Display::from_parts(hires, screen.to_vec(), planes);
+        self.rpl = rpl;
+        self.audio_pattern = if has_pattern { Some(pattern) } else { None };
+        self.pitch = pitch;
+        self.rng = rng;
+        Ok(())
+    }
+
+    // Writes `bytes` to memory starting at `address`, logging the previous values if requested.
+    fn write_memory(&mut self, address: usize, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let range = self.memory_range(address, bytes.len())?;
+        if let Some(log) = self.write_log.as_mut() {
+            let ram = &self.ram;
+            log.extend(range.clone().map(|address| (address, ram[address])));
+        }
+        self.ram[range].copy_from_slice(bytes);
+        Ok(())
     }
 
-    // 7XNN. Adds NN to VX. (Carry flag is not changed).
-    fn op_7(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        let value = opcode & 0x00ff;
-        self.v[reg_x] += value as u8;
-        self.pc+=2;
+    fn unknown_opcode(&self, opcode: usize) -> Chip8Error {
+        Chip8Error::UnknownOpcode{ pc: self.pc, opcode: opcode as u16 }
     }
 
-    fn op_8(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        let reg_y = (opcode & 0x00f0) >> 4;
-        match opcode & 0x000f {
+    // fn write_16_bit(&mut self, pointer: usize, value: usize) {
+    //     self.ram[pointer] = (value >> 8) as u8;
+    //     self.ram[pointer + 1] = value as u8;
+    // }
+
+    fn execute_opcode(&mut self, opcode: usize) -> Result<(), Chip8Error> {
+        let instruction = self.decode(opcode).map_err(|_| self.unknown_opcode(opcode))?;
+        self.execute(instruction)
+    }
+
+    // Like `instruction::decode`, except that `5XYN` and `9XYN` ignore N as they
+    // always have here, while the disassembler treats them as data. XO-CHIP
+    // gives `5XY2` and `5XY3` a meaning of their own.
+    fn decode(&self, opcode: usize) -> Result<Instruction, DecodeError> {
+        let opcode = match opcode >> 12 {
+            0x5 if self.platform != Platform::XoChip => opcode & 0xfff0,
+            0x9 => opcode & 0xfff0,
+            _ => opcode
+        };
+        decode(opcode as u16, self.platform)
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        use Instruction::*;
+
+        match instruction {
+            // 00CN. Scrolls the display down by N pixels. (SUPER-CHIP)
+            ScrollDown(n) => self.display.scroll_down(n as usize),
+            // 00DN. Scrolls the display up by N pixels. (XO-CHIP)
+            ScrollUp(n) => self.display.scroll_up(n as usize),
+            // 00E0. Clears the screen, on XO-CHIP only the selected planes.
+            Clear => self.display.clear(),
+            // 00EE. Returns from a subroutine.
+            Return => {
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow{ pc: self.pc });
+                }
+                self.sp-=1;
+                self.pc = self.stack[self.sp];
+            },
+            // 00FB. Scrolls the display right by 4 pixels. (SUPER-CHIP)
+            ScrollRight => self.display.scroll_right(4),
+            // 00FC. Scrolls the display left by 4 pixels. (SUPER-CHIP)
+            ScrollLeft => self.display.scroll_left(4),
+            // 00FD. Exits the interpreter. (SUPER-CHIP)
+            Exit => {
+                self.halt = true;
+                log!("Processor exited.");
+                return Ok(());
+            },
+            // 00FE. Switches to the 64x32 low resolution mode. (SUPER-CHIP)
+            LowRes => self.display.set_resolution(false),
+            // 00FF. Switches to the 128x64 high resolution mode. (SUPER-CHIP)
+            HighRes => self.display.set_resolution(true),
+            // 1NNN. Jumps to address NNN.
+            Jump(address) => {
+                let address = address as usize;
+                // halt processor if loop detected.
+                if address == self.pc {
+                    self.halt = true;
+                    log!("Processor halted.");
+                }
+                self.pc = address;
+                return Ok(());
+            },
+            // 2NNN. Calls subroutine at NNN.
+            Call(address) => {
+                if self.sp == self.stack.len() {
+                    return Err(Chip8Error::StackOverflow{ pc: self.pc });
+                }
+                self.stack[self.sp] = self.pc;
+                self.sp += 1;
+                self.pc = address as usize;
+                return Ok(());
+            },
+            // 3XNN. Skips the next instruction if VX equals NN.
+            // Usually the next instruction is a jump to skip a code block.
+            SkipIfEqual{ x, nn } => {
+                if self.v[x as usize] == nn {
+                    self.skip_next_instruction();
+                }
+            },
+            // 4XNN. Skips the next instruction if VX does not equal NN.
+            // Usually the next instruction is a jump to skip a code block.
+            SkipIfNotEqual{ x, nn } => {
+                if self.v[x as usize] != nn {
+                    self.skip_next_instruction();
+                }
+            },
+            // 5XY0. Skips the next instruction if VX equals VY.
+            // Usually the next instruction is a jump to skip a code block.
+            SkipIfRegistersEqual{ x, y } => {
+                if self.v[x as usize] == self.v[y as usize] {
+                    self.skip_next_instruction();
+                }
+            },
+            // 5XY2. Stores VX to VY (including VY) in memory, starting at address I.
+            // I is not modified. (XO-CHIP)
+            SaveRange{ x, y } => {
+                let values: Vec<u8> = register_range(x as usize, y as usize).into_iter().map(|reg| self.v[reg]).collect();
+                self.write_memory(self.i, &values)?;
+            },
+            // 5XY3. Fills VX to VY (including VY) with values from memory, starting at address I.
+            // I is not modified. (XO-CHIP)
+            LoadRange{ x, y } => {
+                let registers = register_range(x as usize, y as usize);
+                let range = self.memory_range(self.i, registers.len())?;
+                for (address, reg) in range.zip(registers) {
+                    self.v[reg] = self.ram[address];
+                }
+            },
+            // 6XNN. Sets VX to NN.
+            Set{ x, nn } => self.v[x as usize] = nn,
+            // 7XNN. Adds NN to VX. (Carry flag is not changed).
+            Add{ x, nn } => self.v[x as usize] = self.v[x as usize].wrapping_add(nn),
             // 8XY0. Sets VX to the value of VY.
-            0x0 => self.v[reg_x] = self.v[reg_y],
+            Move{ x, y } => self.v[x as usize] = self.v[y as usize],
             // 8XY1. Sets VX to VX or VY. (Bitwise OR operation).
-            0x1 => self.v[reg_x] |= self.v[reg_y],
+            Or{ x, y } => {
+                self.v[x as usize] |= self.v[y as usize];
+                self.reset_vf_after_logic();
+            },
             // 8XY2. Sets VX to VX and VY. (Bitwise AND operation).
-            0x2 => self.v[reg_x] &= self.v[reg_y],
+            And{ x, y } => {
+                self.v[x as usize] &= self.v[y as usize];
+                self.reset_vf_after_logic();
+            },
             // 8XY3. Sets VX to VX xor VY.
-            0x3 => self.v[reg_x] ^= self.v[reg_y],
+            Xor{ x, y } => {
+                self.v[x as usize] ^= self.v[y as usize];
+                self.reset_vf_after_logic();
+            },
             // 8XY4. Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there is not.
-            0x4 => {
-                let (result, carry) = self.v[reg_x].overflowing_add(self.v[reg_y]);
-                self.v[reg_x] = result;
+            AddRegisters{ x, y } => {
+                let (result, carry) = self.v[x as usize].overflowing_add(self.v[y as usize]);
+                self.v[x as usize] = result;
                 self.v[VF] = carry as u8;
             },
             // 8XY5. VY is subtracted from VX.
             // VF is set to 0 when there's a borrow, and 1 when there is not.
-            0x5 => {
-                let (result, borrow) = self.v[reg_x].overflowing_sub(self.v[reg_y]);
-                self.v[reg_x] = result;
+            Subtract{ x, y } => {
+                let (result, borrow) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
+                self.v[x as usize] = result;
                 self.v[VF] = !borrow as u8;
             },
             // 8XY6. Stores the least significant bit of VX in VF and then shifts VX to the right by 1.
-            0x6 => {
-                self.v[VF] = self.v[reg_x] & 0x1;
-                self.v[reg_x] >>= 1;
+            // With the shift quirk VY is shifted into VX instead.
+            ShiftRight{ x, y } => {
+                let value = self.shift_source(x as usize, y as usize);
+                self.v[x as usize] = value >> 1;
+                self.v[VF] = value & 0x1;
             },
             // 8XY7. Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there is not.
-            0x7 => {
-                let (result, borrow) = self.v[reg_y].overflowing_sub(self.v[reg_x]);
-                self.v[reg_x] = result;
+            SubtractReversed{ x, y } => {
+                let (result, borrow) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
+                self.v[x as usize] = result;
                 self.v[VF] = !borrow as u8;
             },
             // 8XYE. Stores the most significant bit of VX in VF and then shifts VX to the left by 1.
-            0xe => {
-                self.v[VF] = self.v[reg_x] >> 7;
-                self.v[reg_x] = self.v[reg_x] << 1;
+            // With the shift quirk VY is shifted into VX instead.
+            ShiftLeft{ x, y } => {
+                let value = self.shift_source(x as usize, y as usize);
+                self.v[x as usize] = value << 1;
+                self.v[VF] = value >> 7;
             },
-            _ => println!("Unknown opcode: 0x{opcode:0>4x}")
-        }
-        self.pc+=2;
-    }
-
-    // 9XY0. Skips the next instruction if VX does not equal VY.
-    // Usually the next instruction is a jump to skip a code block.
-    fn op_9(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        let reg_y = (opcode & 0x00f0) >> 4;
-        if self.v[reg_x] != self.v[reg_y] {
-            self.pc+=2;
-        }
-        self.pc+=2;
-    }
-
-    // ANNN. Sets I to the address NNN.
-    fn op_a(&mut self, opcode: usize) {
-        self.i = opcode & 0x0fff;
-        self.pc+=2;
-    }
-
-    // BNNN. Jumps to the address NNN plus V0.
-    fn op_b(&mut self, opcode: usize) {
-        self.pc = (opcode & 0x0fff) + self.v[0] as usize;
-    }
-
-    // CXNN. Sets VX to the result of a bitwise and operation on a random number 
-    // (Typically: 0 to 255) and NN.
-    fn op_c(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        let value = opcode & 0x00ff;
-        self.v[reg_x] = (rand::thread_rng().gen_range(0..255) & value) as u8;
-        self.pc+=2;
-    }
-
-    // DXYN. Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels and a height of N pixels.
-    // Each row of 8 pixels is read as bit-coded starting from memory location I;
-    // I value does not change after the execution of this instruction. As described above,
-    // VF is set to 1 if any screen pixels are flipped from set to unset when the sprite is drawn,
-    // and to 0 if that does not happen
-    fn op_d(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        let reg_y = (opcode & 0x00f0) >> 4;
-        let height = opcode & 0x000f;
-        self.v[VF] = 0;
-
-        for byte in 0..height {
-            let y = (self.v[reg_y] as usize + byte) % SCREEN_HEIGHT;
-            for bit in 0..8 {
-                let color = (self.ram[self.i + byte] & 2_i32.pow(7-bit) as u8) >= 1;
-                let x = (self.v[reg_x] + bit as u8) as usize % SCREEN_WIDTH;
-                let collision = self.set_pixel(x, y, color);
-                if collision {
-                    self.v[VF] = 1;
+            // 9XY0. Skips the next instruction if VX does not equal VY.
+            // Usually the next instruction is a jump to skip a code block.
+            SkipIfRegistersNotEqual{ x, y } => {
+                if self.v[x as usize] != self.v[y as usize] {
+                    self.skip_next_instruction();
                 }
-            }
-        }
-        self.pc+=2;
-    }
-
-    fn set_pixel(&mut self, x: usize, y: usize, color: bool) -> bool {
-        let address = VRAM_START + y * 8 + x / 8;
-        let data = self.ram[address];
-        let bit = 7 - (x % 8);
-        self.ram[address] = data ^ ((color as u8) << bit);
-        ((data & 2_i32.pow(bit as u32) as u8) >= 1) && color
-    }
-
-    fn op_e(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        match opcode & 0xff {
+            },
+            // ANNN. Sets I to the address NNN.
+            SetI(address) => self.i = address as usize,
+            // BNNN. Jumps to the address NNN plus V0.
+            // With the jump quirk this is BXNN, jumping to XNN plus VX.
+            JumpOffset(address) => {
+                let reg = if self.quirks.jump_uses_vx { (address >> 8) as usize } else { 0 };
+                self.pc = address as usize + self.v[reg] as usize;
+                return Ok(());
+            },
+            // CXNN. Sets VX to the result of a bitwise and operation on a random number
+            // (Typically: 0 to 255) and NN.
+            Random{ x, nn } => self.v[x as usize] = self.rng.next_byte(&self.ram) & nn,
+            // DXYN. Draws a sprite at (VX, VY), see `draw`.
+            Draw{ x, y, n } => self.draw(x as usize, y as usize, n as usize)?,
             // EX9E. Skips the next instruction if the key stored in VX is pressed.
             // Usually the next instruction is a jump to skip a code block.
-            0x9E => {
-                if self.key_state[self.v[reg_x] as usize] {
-                    self.pc += 2;
+            SkipIfKey(x) => {
+                if self.key_state[self.v[x as usize] as usize & 0xf] {
+                    self.skip_next_instruction();
                 }
             },
             // EXA1. Skips the next instruction if the key stored in VX is not pressed.
             // Usually the next instruction is a jump to skip a code block.
-            0xA1 => {
-                if !self.key_state[self.v[reg_x] as usize] {
-                    self.pc += 2;
+            SkipIfNotKey(x) => {
+                if !self.key_state[self.v[x as usize] as usize & 0xf] {
+                    self.skip_next_instruction();
                 }
             },
-            _ => println!("Unknown opcode: 0x{opcode:0>4x}")
-        }
-        self.pc += 2;
-    }
-
-    fn op_f(&mut self, opcode: usize) {
-        let reg_x = (opcode & 0x0f00) >> 8;
-        match opcode & 0xff {
-            // Sets VX to the value of the delay timer. 
-            0x07 => self.v[reg_x] = self.delay_timer,
-            // A key press is awaited, and then stored in VX 
-            //(blocking operation, all instruction halted until next key event). 
-            0x0A => {
+            // F000 NNNN. Sets I to the 16-bit address NNNN in the following word. (XO-CHIP)
+            SetILong => {
+                self.i = self.read_16_bit(self.pc + 2)?;
+                self.pc += 4;
+                return Ok(());
+            },
+            // FN01. Selects the bit-planes N for drawing, clearing and scrolling. (XO-CHIP)
+            Plane(n) => self.display.select_planes(n),
+            // F002. Loads the 16 byte audio pattern from memory, starting at address I. (XO-CHIP)
+            Audio => {
+                let range = self.memory_range(self.i, 16)?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.ram[range]);
+                self.audio_pattern = Some(pattern);
+            },
+            // FX07. Sets VX to the value of the delay timer.
+            GetDelay(x) => self.v[x as usize] = self.delay_timer,
+            // FX0A. A key press is awaited, and then stored in VX
+            //(blocking operation, all instruction halted until next key event).
+            WaitKey(x) => {
                 self.wait_key = true;
-                self.wait_key_reg = reg_x;
-                return;
+                self.wait_key_reg = x as usize;
+                return Ok(());
             },
             // FX15. Sets the delay timer to VX.
-            0x15 => self.delay_timer = self.v[reg_x],
+            SetDelay(x) => self.delay_timer = self.v[x as usize],
             // FX18. Sets the sound timer to VX.
-            0x18 => self.sound_timer = self.v[reg_x],
+            SetSound(x) => self.sound_timer = self.v[x as usize],
             // FX1E. Adds VX to I. VF is not affected.
-            0x1E => self.i += self.v[reg_x] as usize,
+            AddI(x) => self.i += self.v[x as usize] as usize,
             // FX29. Sets I to the location of the sprite for the character in VX.
             // Characters 0-F (in hexadecimal) are represented by a 4x5 font.
-            0x29 => self.i = FONT_START + self.v[reg_x] as usize * 5,
-            // FX33. Stores the binary-coded decimal representation of VX, 
-            // with the hundreds digit in memory at location in I, the tens digit at location I+1, 
-            // and the ones digit at location I+2. 
-            0x33 => {
-                self.ram[self.i] = self.v[reg_x] / 100;
-                self.ram[self.i + 1] = (self.v[reg_x] % 100) / 10;
-                self.ram[self.i + 2] = self.v[reg_x] % 10;
+            Font(x) => self.i = FONT_START + self.v[x as usize] as usize * 5,
+            // FX30. Sets I to the location of the 8x10 sprite for the character in VX. (SUPER-CHIP)
+            BigFont(x) => self.i = BIG_FONT_START + (self.v[x as usize] & 0xf) as usize * 10,
+            // FX33. Stores the binary-coded decimal representation of VX,
+            // with the hundreds digit in memory at location in I, the tens digit at location I+1,
+            // and the ones digit at location I+2.
+            Bcd(x) => {
+                let value = self.v[x as usize];
+                self.write_memory(self.i, &[value / 100, (value % 100) / 10, value % 10])?;
             },
-            // FX55. Stores from V0 to VX (including VX) in memory, starting at address I. 
-            // The offset from I is increased by 1 for each value written, but I itself is left unmodified.
-            0x55 => {
-                for i in 0..reg_x+1 as usize {
-                    self.ram[self.i + i] = self.v[i];
+            // FX3A. Sets the audio pattern playback pitch to VX. (XO-CHIP)
+            Pitch(x) => self.pitch = self.v[x as usize],
+            // FX55. Stores from V0 to VX (including VX) in memory, starting at address I.
+            // The offset from I is increased by 1 for each value written, but I itself is left unmodified
+            // unless the load/store quirk is on.
+            Store(x) => {
+                let count = x as usize + 1;
+                let values = self.v;
+                self.write_memory(self.i, &values[..count])?;
+                if self.quirks.load_store_increments_i {
+                    self.i += count;
                 }
             },
-            // FX65. Fills from V0 to VX (including VX) with values from memory, starting at address I. 
-            // The offset from I is increased by 1 for each value read, but I itself is left unmodified.
-            0x65 => {
-                for i in 0..reg_x+1 as usize {
-                    self.v[i] = self.ram[self.i + i];
+            // FX65. Fills from V0 to VX (including VX) with values from memory, starting at address I.
+            // The offset from I is increased by 1 for each value read, but I itself is left unmodified
+            // unless the load/store quirk is on.
+            Load(x) => {
+                let count = x as usize + 1;
+                let range = self.memory_range(self.i, count)?;
+                self.v[..count].copy_from_slice(&self.ram[range]);
+                if self.quirks.load_store_increments_i {
+                    self.i += count;
                 }
             },
-            _ => println!("Unknown opcode: 0x{opcode:0>4x}")
+            // FX75. Stores V0 to VX (including VX) in the RPL user flags. (SUPER-CHIP)
+            SaveFlags(x) => {
+                let count = x as usize + 1;
+                self.rpl[..count].copy_from_slice(&self.v[..count]);
+            },
+            // FX85. Fills V0 to VX (including VX) from the RPL user flags. (SUPER-CHIP)
+            LoadFlags(x) => {
+                let count = x as usize + 1;
+                self.v[..count].copy_from_slice(&self.rpl[..count]);
+            }
         }
         self.pc += 2;
+        Ok(())
+    }
+
+    // Skips the next instruction, which is four bytes long if it is an XO-CHIP `F000 NNNN`.
+    fn skip_next_instruction(&mut self) {
+        let next = self.pc + 2;
+        let long = self.read_16_bit(next)
+            .is_ok_and(|opcode| decode(opcode as u16, self.platform) == Ok(Instruction::SetILong));
+        self.pc += if long { 4 } else { 2 };
+    }
+
+    fn shift_source(&self, reg_x: usize, reg_y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.v[reg_y]
+        } else {
+            self.v[reg_x]
+        }
+    }
+
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[VF] = 0;
+        }
+    }
+
+    // DXYN. Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels and a height of N pixels.
+    // Each row of 8 pixels is read as bit-coded starting from memory location I;
+    // I value does not change after the execution of this instruction. As described above,
+    // VF is set to 1 if any screen pixels are flipped from set to unset when the sprite is drawn,
+    // and to 0 if that does not happen.
+    // Sprites wrap around the screen edges, or are clipped with the clipping quirk.
+    // DXY0 draws a 16x16 sprite of two bytes per row on SUPER-CHIP and XO-CHIP.
+    // On XO-CHIP the sprite is drawn to each selected plane, the data for the
+    // next plane following the previous one in memory.
+    fn draw(&mut self, reg_x: usize, reg_y: usize, n: usize) -> Result<(), Chip8Error> {
+        let (height, width) = match n {
+            0 if self.platform != Platform::Chip8 => (16, 16),
+            n => (n, 8)
+        };
+        let sprite_len = height * width / 8;
+        let planes = self.display.selected_planes().count_ones() as usize;
+        let sprite = self.memory_range(self.i, sprite_len * planes)?;
+        let (x, y) = (self.v[reg_x] as usize, self.v[reg_y] as usize);
+        let collided = self.display.draw_sprite(x, y, &self.ram[sprite], width, self.quirks.clip_sprites);
+        self.v[VF] = collided as u8;
+        self.wait_vblank = self.quirks.display_wait;
+        Ok(())
+    }
+}
+
+// The registers VX to VY (including VY), in descending order if X is greater than Y.
+fn register_range(reg_x: usize, reg_y: usize) -> Vec<usize> {
+    if reg_x <= reg_y {
+        (reg_x..=reg_y).collect()
+    } else {
+        (reg_y..=reg_x).rev().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::CosmacVipRandom;
+
+    // Packs the display into one bit per pixel, most significant bit first,
+    // the layout the screen had when it lived in RAM.
+    fn packed_screen(processor: &Processor) -> Vec<u8> {
+        processor.framebuffer()
+            .chunks(8)
+            .map(|pixels| pixels.iter().fold(0, |byte, &pixel| byte << 1 | pixel))
+            .collect()
+    }
+
+    #[test]
+    fn new_loads_rom_and_font() {
+        // act
+        let processor = Processor::new(&[0x12, 0x34], Quirks::default());
+
+        // assert
+        assert_eq!(processor.memory()[PC_START], 0x12);
+        assert_eq!(processor.memory()[PC_START + 1], 0x34);
+        assert_eq!(processor.memory()[FONT_START..FONT_START + 5], [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        assert_eq!(processor.pc(), PC_START);
+    }
+
+    #[test]
+    fn load_rom_resets_state() {
+        // arrange
+        let mut processor = Processor::new(&[0x60, 0x05], Quirks::default());
+        processor.step().unwrap();
+        processor.display.flip_pixel(0, 0, 1);
+
+        // act
+        processor.load_rom(&[0x61, 0x07]);
+
+        // assert
+        assert_eq!(processor.registers()[0], 0);
+        assert_eq!(processor.pc(), PC_START);
+        assert_eq!(processor.memory()[PC_START], 0x61);
+        assert!(processor.framebuffer().iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn pixel_reads_framebuffer() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+
+        // act
+        processor.display.flip_pixel(4, 1, 1);
+
+        // assert
+        assert!(processor.pixel(4, 1));
+        assert!(!processor.pixel(5, 1));
+        assert!(!processor.pixel(4, 0));
+    }
+
+    #[test]
+    fn run_frame_stops_when_halted() {
+        // arrange
+        // 6001: V0 = 1, 7001: V0 += 1, 1204: jump to self
+        let mut processor = Processor::new(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x04], Quirks::default());
+
+        // act
+        processor.run_frame(10).unwrap();
+
+        // assert
+        assert!(processor.is_halted());
+        assert_eq!(processor.registers()[0], 2);
+        assert_eq!(processor.pc(), 0x204);
+    }
+
+    #[test]
+    fn run_frame_with_vip_timing_fills_the_frame() {
+        // arrange
+        // 7001 repeated: V0 += 1, 50 machine cycles each
+        let rom: Vec<u8> = [0x70, 0x01].repeat(120);
+        let mut processor = Processor::new(&rom, Quirks::default());
+        processor.set_timing(Timing::CosmacVip);
+
+        // act
+        processor.run_frame(10).unwrap();
+        let first = processor.registers()[0];
+        processor.run_frame(10).unwrap();
+
+        // assert
+        assert_eq!(first, 53);
+        // The 35 cycles the first frame overran are taken from the second.
+        assert_eq!(processor.registers()[0], 53 + 52);
+    }
+
+    #[test]
+    fn run_frame_with_vip_timing_charges_clear_and_draw_wait() {
+        // arrange
+        // 00E0: clear, D001: draw, 7001 repeated: V0 += 1
+        let mut rom = vec![0x00, 0xe0, 0xd0, 0x01];
+        rom.extend([0x70, 0x01].repeat(100));
+        let mut processor = Processor::new(&rom, Quirks::COSMAC_VIP);
+        processor.set_timing(Timing::CosmacVip);
+
+        // act
+        processor.run_frame(1000).unwrap();
+        let after_clear = processor.pc();
+        processor.run_frame(1000).unwrap();
+        let after_draw = processor.pc();
+        processor.run_frame(1000).unwrap();
+
+        // assert
+        assert_eq!(after_clear, 0x202);
+        assert_eq!(after_draw, 0x204);
+        assert_eq!(processor.registers()[0], 53);
+    }
+
+    #[test]
+    fn op_0x7xnn_add_nn_to_vx_wraps() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.v[5] = 0xff;
+
+        // act
+        processor.execute_opcode(0x7502).unwrap();
+
+        // assert
+        assert_eq!(processor.v[5], 0x01);
+        assert_eq!(processor.v[VF], 0);
+    }
+
+    #[test]
+    fn op_0x00e0_clear_screen() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.display.flip_pixel(39, 27, 1);
+        assert!(processor.pixel(39, 27));
+
+        // act
+        processor.execute_opcode(0x00e0).unwrap();
+
+        // assert
+        assert!(!processor.pixel(39, 27));
+        assert_eq!(processor.pc, 0x0202);
+        
+    }
+
+    #[test]
+    fn op_0x00ee_return_from_subroutine() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.stack[0] = 0x0236;
+        processor.sp = 1;
+
+        // act
+        processor.execute_opcode(0x00EE).unwrap();
+
+        // assert
+        assert_eq!(processor.pc, 0x0238);
+    }
+
+    fn schip() -> Processor {
+        Processor::with_platform(&[], Platform::SuperChip, Quirks::SUPER_CHIP)
+    }
+
+    #[test]
+    fn op_0x00cn_scroll_down() {
+        // arrange
+        let mut processor = schip();
+        processor.display.flip_pixel(3, 0, 1);
+        processor.display.flip_pixel(3, 31, 1);
+
+        // act
+        processor.execute_opcode(0x00c2).unwrap();
+
+        // assert
+        assert!(processor.pixel(3, 2));
+        assert!(!processor.pixel(3, 0));
+        assert_eq!(processor.framebuffer().iter().filter(|&&p| p != 0).count(), 1);
+        assert_eq!(processor.pc, 0x0202);
+    }
+
+    #[test]
+    fn op_0x00fb_scroll_right() {
+        // arrange
+        let mut processor = schip();
+        processor.display.flip_pixel(0, 5, 1);
+        processor.display.flip_pixel(63, 5, 1);
+
+        // act
+        processor.execute_opcode(0x00fb).unwrap();
+
+        // assert
+        assert!(processor.pixel(4, 5));
+        assert!(!processor.pixel(0, 5));
+        assert!(!processor.pixel(3, 5));
+        assert_eq!(processor.framebuffer().iter().filter(|&&p| p != 0).count(), 1);
+    }
+
+    #[test]
+    fn op_0x00fc_scroll_left() {
+        // arrange
+        let mut processor = schip();
+        processor.execute_opcode(0x00ff).unwrap();
+        processor.display.flip_pixel(2, 5, 1);
+        processor.display.flip_pixel(127, 5, 1);
+
+        // act
+        processor.execute_opcode(0x00fc).unwrap();
+
+        // assert
+        assert!(processor.pixel(123, 5));
+        assert!(!processor.pixel(127, 5));
+        assert_eq!(processor.framebuffer().iter().filter(|&&p| p != 0).count(), 1);
+    }
+
+    #[test]
+    fn op_0x00fd_exit() {
+        // arrange
+        let mut processor = schip();
+
+        // act
+        processor.execute_opcode(0x00fd).unwrap();
+
+        // assert
+        assert!(processor.is_halted());
+        assert_eq!(processor.pc, 0x0200);
+    }
+
+    #[test]
+    fn op_0x00ff_high_resolution_and_0x00fe_low_resolution() {
+        // arrange
+        let mut processor = schip();
+        processor.display.flip_pixel(1, 1, 1);
+
+        // act
+        processor.execute_opcode(0x00ff).unwrap();
+        let hires = (processor.screen_width(), processor.screen_height(), processor.is_hires());
+        processor.display.flip_pixel(100, 60, 1);
+        processor.execute_opcode(0x00fe).unwrap();
+
+        // assert
+        assert_eq!(hires, (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT, true));
+        assert_eq!((processor.screen_width(), processor.screen_height()), (SCREEN_WIDTH, SCREEN_HEIGHT));
+        assert_eq!(processor.framebuffer().len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        assert!(processor.framebuffer().iter().all(|&p| p == 0));
+        assert_eq!(processor.pc, 0x0204);
+    }
+
+    #[test]
+    fn op_0x00ff_is_unknown_on_chip8() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+
+        // act
+        let result = processor.execute_opcode(0x00ff);
+
+        // assert
+        assert_eq!(result, Err(Chip8Error::UnknownOpcode{ pc: 0x0200, opcode: 0x00ff }));
+        assert!(!processor.is_hires());
+        assert_eq!(processor.screen_width(), SCREEN_WIDTH);
+    }
+
+    #[test]
+    fn step_halts_on_unknown_opcode() {
+        // arrange
+        // 6001: V0 = 1, E001: unknown
+        let mut processor = Processor::new(&[0x60, 0x01, 0xe0, 0x01], Quirks::default());
+
+        // act
+        let result = processor.run_frame(10);
+        let after_halt = processor.step();
+
+        // assert
+        let error = Chip8Error::UnknownOpcode{ pc: 0x0202, opcode: 0xe001 };
+        assert_eq!(result, Err(error));
+        assert!(processor.is_halted());
+        assert_eq!(processor.error(), Some(error));
+        assert_eq!(after_halt, Ok(StepOutcome::Halted));
+        assert_eq!(processor.pc(), 0x0202);
+        assert_eq!(processor.frames(), 0);
+    }
+
+    #[test]
+    fn step_reports_waiting() {
+        // arrange
+        // F00A: wait for key into V0
+        let mut processor = Processor::new(&[0xf0, 0x0a], Quirks::default());
+
+        // act
+        let executed = processor.step();
+        let waiting = processor.step();
+
+        // assert
+        assert_eq!(executed, Ok(StepOutcome::Executed));
+        assert_eq!(waiting, Ok(StepOutcome::WaitingForKey));
+    }
+
+    #[test]
+    fn op_0x2nnn_stack_overflow() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.sp = processor.stack.len();
+
+        // act
+        let result = processor.execute_opcode(0x2300);
+
+        // assert
+        assert_eq!(result, Err(Chip8Error::StackOverflow{ pc: 0x0200 }));
+        assert_eq!(processor.pc, 0x0200);
+    }
+
+    #[test]
+    fn op_0x00ee_stack_underflow() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+
+        // act
+        let result = processor.execute_opcode(0x00ee);
+
+        // assert
+        assert_eq!(result, Err(Chip8Error::StackUnderflow{ pc: 0x0200 }));
+        assert_eq!(processor.sp, 0);
+    }
+
+    #[test]
+    fn op_0xfx55_memory_out_of_bounds() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.i = RAM_SIZE_BYTE - 2;
+
+        // act
+        let result = processor.execute_opcode(0xf355);
+
+        // assert
+        assert_eq!(result, Err(Chip8Error::MemoryOutOfBounds{ addr: RAM_SIZE_BYTE }));
+        assert_eq!(processor.memory()[RAM_SIZE_BYTE - 2..], [0, 0]);
+    }
+
+    #[test]
+    fn op_0xdxyn_memory_out_of_bounds() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.i = RAM_SIZE_BYTE + 4;
+
+        // act
+        let result = processor.execute_opcode(0xd001);
+
+        // assert
+        assert_eq!(result, Err(Chip8Error::MemoryOutOfBounds{ addr: RAM_SIZE_BYTE + 4 }));
+    }
+
+    #[test]
+    fn step_reports_pc_out_of_bounds() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.pc = RAM_SIZE_BYTE - 1;
+
+        // act
+        let result = processor.step();
+
+        // assert
+        assert_eq!(result, Err(Chip8Error::MemoryOutOfBounds{ addr: RAM_SIZE_BYTE }));
+        assert!(processor.is_halted());
+    }
+
+    #[test]
+    fn load_state_restores_saved_state() {
+        // arrange
+        // 6005: V0 = 5, F015: delay timer = V0, C1FF: V1 = random, 2208: call 0x208,
+        // F20A: wait for key into V2, 120A: jump to self
+        let rom = [0x60, 0x05, 0xf0, 0x15, 0xc1, 0xff, 0x22, 0x08, 0xf2, 0x0a, 0x12, 0x0a];
+        let mut processor = Processor::new(&rom, Quirks::COSMAC_VIP);
+        processor.run_frame(2).unwrap();
+        processor.set_key(0xa, true);
+        let state = processor.save_state();
+        processor.run_frame(10).unwrap();
+        let expected = (processor.registers().to_vec(), processor.pc(), processor.delay_timer());
+
+        // act
+        processor.set_key(0xa, false);
+        processor.execute_opcode(0x00e0).unwrap();
+        processor.load_state(&state).unwrap();
+        let restored = processor.save_state();
+        processor.run_frame(10).unwrap();
+
+        // assert
+        assert_eq!(restored, state);
+        assert_eq!((processor.registers().to_vec(), processor.pc(), processor.delay_timer()), expected);
+        assert_eq!(processor.stack(), [0x0206]);
+    }
+
+    #[test]
+    fn load_state_switches_platform() {
+        // arrange
+        let mut xo = xochip();
+        xo.execute_opcode(0x00ff).unwrap();
+        xo.execute_opcode(0xf201).unwrap();
+        xo.display.flip_pixel(100, 60, 0b10);
+        let mut processor = Processor::new(&[], Quirks::default());
+
+        // act
+        processor.load_state(&xo.save_state()).unwrap();
+
+        // assert
+        assert_eq!(processor.platform(), Platform::XoChip);
+        assert_eq!(processor.quirks(), Quirks::XO_CHIP);
+        assert_eq!(processor.memory().len(), XO_CHIP_RAM_SIZE_BYTE);
+        assert!(processor.is_hires());
+        assert!(processor.pixel(100, 60));
+        assert_eq!(processor.selected_planes(), 0b10);
+    }
+
+    #[test]
+    fn load_state_rejects_invalid_state() {
+        // arrange
+        let mut processor = Processor::new(&[0x60, 0x05], Quirks::default());
+        let mut state = processor.save_state();
+        processor.step().unwrap();
+        let before = processor.save_state();
+
+        // act
+        let truncated = processor.load_state(&state[..state.len() - 1]);
+        state[5] = 7;
+        let bad_platform = processor.load_state(&state);
+
+        // assert
+        assert_eq!(truncated, Err(StateError::Truncated));
+        assert_eq!(bad_platform, Err(StateError::Invalid("platform")));
+        assert_eq!(processor.save_state(), before);
+    }
+
+    #[test]
+    fn load_state_reads_version_1() {
+        // arrange
+        let mut processor = Processor::new(&[0xc0, 0xff], Quirks::default());
+        processor.seed_random(99);
+        let state = processor.save_state();
+        // Version 3 appended the timing.
+        let state = &state[..state.len() - 5];
+        // Version 1 stored the generator state without a length prefix.
+        let mut version_1 = state[..state.len() - 8].to_vec();
+        version_1.extend_from_slice(&state[state.len() - 4..]);
+        version_1[4] = 1;
+        processor.step().unwrap();
+        let expected = processor.registers()[0];
+
+        // act
+        processor.load_state(&version_1).unwrap();
+        processor.step().unwrap();
+
+        // assert
+        assert_eq!(processor.registers()[0], expected);
+    }
+
+    #[test]
+    fn load_state_restores_timing() {
+        // arrange
+        let rom: Vec<u8> = [0x70, 0x01].repeat(120);
+        let mut processor = Processor::new(&rom, Quirks::default());
+        processor.set_timing(Timing::CosmacVip);
+        processor.run_frame(10).unwrap();
+        let state = processor.save_state();
+        processor.run_frame(10).unwrap();
+        let expected = processor.registers()[0];
+
+        // act
+        let mut restored = Processor::new(&rom, Quirks::default());
+        restored.load_state(&state).unwrap();
+        restored.run_frame(10).unwrap();
+
+        // assert
+        assert_eq!(restored.timing(), Timing::CosmacVip);
+        assert_eq!(restored.registers()[0], expected);
+    }
+
+    #[test]
+    fn load_state_reads_version_2_with_instruction_timing() {
+        // arrange
+        let mut processor = Processor::new(&[0x60, 0x05], Quirks::default());
+        let state = processor.save_state();
+        let mut version_2 = state[..state.len() - 5].to_vec();
+        version_2[4] = 2;
+        processor.set_timing(Timing::CosmacVip);
+
+        // act
+        let result = processor.load_state(&version_2);
+
+        // assert
+        assert_eq!(result, Ok(()));
+        assert_eq!(processor.timing(), Timing::Instructions);
+    }
+
+    fn xochip() -> Processor {
+        Processor::with_platform(&[], Platform::XoChip, Quirks::XO_CHIP)
+    }
+
+    #[test]
+    fn xochip_loads_rom_into_64k_memory() {
+        // arrange
+        let rom = vec![0xab; RAM_SIZE_BYTE];
+
+        // act
+        let processor = Processor::with_platform(&rom, Platform::XoChip, Quirks::XO_CHIP);
+
+        // assert
+        assert_eq!(processor.memory().len(), XO_CHIP_RAM_SIZE_BYTE);
+        assert_eq!(processor.memory()[PC_START + RAM_SIZE_BYTE - 1], 0xab);
+    }
+
+    #[test]
+    fn op_0xf000_nnnn_set_i_to_long_address() {
+        // arrange
+        let mut processor = Processor::with_platform(&[0xf0, 0x00, 0xe1, 0x23], Platform::XoChip, Quirks::XO_CHIP);
+
+        // act
+        processor.step().unwrap();
+
+        // assert
+        assert_eq!(processor.i, 0xe123);
+        assert_eq!(processor.pc, 0x0204);
+    }
+
+    #[test]
+    fn op_0x3xnn_skips_long_instruction() {
+        // arrange
+        // 3000: skip if V0 == 0, F000 1234: I = 0x1234
+        let mut processor = Processor::with_platform(&[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34], Platform::XoChip, Quirks::XO_CHIP);
+
+        // act
+        processor.step().unwrap();
+
+        // assert
+        assert_eq!(processor.pc, 0x0206);
+    }
+
+    #[test]
+    fn op_0x5xy2_save_register_range() {
+        // arrange
+        let mut processor = xochip();
+        processor.v[2..=4].copy_from_slice(&[1, 2, 3]);
+        processor.i = 0x300;
+
+        // act
+        processor.execute_opcode(0x5242).unwrap();
+        processor.i = 0x310;
+        processor.execute_opcode(0x5422).unwrap();
+
+        // assert
+        assert_eq!(processor.ram[0x300..0x303], [1, 2, 3]);
+        assert_eq!(processor.ram[0x310..0x313], [3, 2, 1]);
+        assert_eq!(processor.i, 0x310);
+        assert_eq!(processor.pc, 0x0204);
+    }
+
+    #[test]
+    fn op_0x5xy3_load_register_range() {
+        // arrange
+        let mut processor = xochip();
+        processor.ram[0x300..0x303].copy_from_slice(&[1, 2, 3]);
+        processor.i = 0x300;
+
+        // act
+        processor.execute_opcode(0x5793).unwrap();
+        processor.execute_opcode(0x5313).unwrap();
+
+        // assert
+        assert_eq!(processor.v[7..=9], [1, 2, 3]);
+        assert_eq!(processor.v[1..=3], [3, 2, 1]);
+        assert_eq!(processor.i, 0x300);
+    }
+
+    #[test]
+    fn op_0x5xy1_is_unknown_on_xochip() {
+        // arrange
+        let mut processor = xochip();
+
+        // act
+        let result = processor.execute_opcode(0x5001);
+
+        // assert
+        assert_eq!(result, Err(Chip8Error::UnknownOpcode{ pc: 0x0200, opcode: 0x5001 }));
+        assert_eq!(processor.pc, 0x0200);
+    }
+
+    #[test]
+    fn op_0xfn01_select_planes_and_draw() {
+        // arrange
+        let mut processor = xochip();
+        processor.ram[0x300..0x302].copy_from_slice(&[0b1100_0000, 0b1010_0000]);
+        processor.i = 0x300;
+
+        // act
+        processor.execute_opcode(0xf301).unwrap();
+        processor.execute_opcode(0xd001).unwrap();
+
+        // assert
+        assert_eq!(processor.selected_planes(), 0b11);
+        assert_eq!(processor.framebuffer()[..4], [3, 1, 2, 0]);
+        assert_eq!(processor.v[VF], 0);
+    }
+
+    #[test]
+    fn op_0xdxyn_collides_per_plane() {
+        // arrange
+        let mut processor = xochip();
+        processor.ram[0x300] = 0b1000_0000;
+        processor.i = 0x300;
+        processor.display.flip_pixel(0, 0, 0b01);
+
+        // act
+        processor.execute_opcode(0xf201).unwrap();
+        processor.execute_opcode(0xd001).unwrap();
+        let second_plane = processor.v[VF];
+        processor.execute_opcode(0xf101).unwrap();
+        processor.execute_opcode(0xd001).unwrap();
+
+        // assert
+        assert_eq!(second_plane, 0);
+        assert_eq!(processor.v[VF], 1);
+        assert_eq!(processor.framebuffer()[0], 0b10);
+    }
+
+    #[test]
+    fn op_0x00e0_clears_selected_planes() {
+        // arrange
+        let mut processor = xochip();
+        processor.display.flip_pixel(1, 1, 0b11);
+        processor.execute_opcode(0xf201).unwrap();
+
+        // act
+        processor.execute_opcode(0x00e0).unwrap();
+
+        // assert
+        assert_eq!(processor.framebuffer()[SCREEN_WIDTH + 1], 0b01);
+    }
+
+    #[test]
+    fn op_0x00dn_scroll_up() {
+        // arrange
+        let mut processor = xochip();
+        processor.display.flip_pixel(3, 0, 1);
+        processor.display.flip_pixel(3, 5, 1);
+
+        // act
+        processor.execute_opcode(0x00d2).unwrap();
+
+        // assert
+        assert!(processor.pixel(3, 3));
+        assert!(!processor.pixel(3, 5));
+        assert_eq!(processor.framebuffer().iter().filter(|&&p| p != 0).count(), 1);
+        assert_eq!(processor.pc, 0x0202);
     }
 
-}
+    #[test]
+    fn op_0x00dn_is_unknown_on_schip() {
+        // arrange
+        let mut processor = schip();
+        processor.display.flip_pixel(3, 5, 1);
 
-#[cfg(test)]    
-mod tests {
-    use super::*;
-    use test;
+        // act
+        let result = processor.execute_opcode(0x00d2);
+
+        // assert
+        assert!(result.is_err());
+        assert!(processor.pixel(3, 5));
+    }
 
     #[test]
-    fn op_0x00e0_clear_screen() {
+    fn scroll_moves_only_selected_planes() {
+        // arrange
+        let mut processor = xochip();
+        processor.display.flip_pixel(0, 0, 0b11);
+        processor.execute_opcode(0xf101).unwrap();
+
+        // act
+        processor.execute_opcode(0x00fb).unwrap();
+
+        // assert
+        assert_eq!(processor.framebuffer()[0], 0b10);
+        assert_eq!(processor.framebuffer()[4], 0b01);
+    }
+
+    #[test]
+    fn op_0xf002_load_audio_pattern() {
         // arrange
-        let mut processor = Processor::new(vec![]);
-        processor.ram[4060] = 1;
-        assert_eq!(processor.ram[4060], 1);
+        let mut processor = xochip();
+        let pattern: Vec<u8> = (0..16).collect();
+        processor.ram[0x300..0x310].copy_from_slice(&pattern);
+        processor.i = 0x300;
 
         // act
-        processor.execute_opcode(0x00e0);
+        processor.execute_opcode(0xf002).unwrap();
 
         // assert
-        assert_eq!(processor.ram[4060], 0);
+        assert_eq!(processor.sound_state().pattern.map(|p| p.to_vec()), Some(pattern));
+        assert_eq!(processor.i, 0x300);
         assert_eq!(processor.pc, 0x0202);
-        
     }
 
     #[test]
-    fn op_0x00ee_return_from_subroutine() {
+    fn op_0xfx3a_set_pitch() {
         // arrange
-        let mut processor = Processor::new(vec![]);
-        processor.stack[0] = 0x0236;
-        processor.sp = 1;
+        let mut processor = xochip();
+        processor.v[4] = 112;
 
         // act
-        processor.execute_opcode(0x00EE);
+        processor.execute_opcode(0xf43a).unwrap();
 
         // assert
-        assert_eq!(processor.pc, 0x0238);
+        assert_eq!(processor.sound_state().pitch, 112);
     }
 
     #[test]
     fn op_0x1nnn_jump_to_address() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
 
         // act
-        processor.execute_opcode(0x1280);
+        processor.execute_opcode(0x1280).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0280);
@@ -482,11 +1777,11 @@ mod tests {
     #[test]
     fn op_0x2nnn_call_subroutine() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0222;
 
         // act
-        processor.execute_opcode(0x2244);
+        processor.execute_opcode(0x2244).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0244);
@@ -497,12 +1792,12 @@ mod tests {
     #[test]
     fn op_0x3xnn_skip_vx_equals_nn() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0222;
         processor.v[5] = 0x34;
 
         // act
-        processor.execute_opcode(0x3534);
+        processor.execute_opcode(0x3534).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0226);
@@ -511,12 +1806,12 @@ mod tests {
     #[test]
     fn op_0x3xnn_no_skip_vx_not_equals_nn() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0222;
         processor.v[5] = 0x37;
 
         // act
-        processor.execute_opcode(0x3534);
+        processor.execute_opcode(0x3534).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0224);
@@ -525,12 +1820,12 @@ mod tests {
     #[test]
     fn op_0x4xnn_skip_if_vx_not_equals_nn() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0222;
         processor.v[5] = 0x37;
 
         // act
-        processor.execute_opcode(0x4534);
+        processor.execute_opcode(0x4534).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0226);
@@ -539,12 +1834,12 @@ mod tests {
     #[test]
     fn op_0x4xnn_no_skip_if_vx_equals_nn() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0222;
         processor.v[5] = 0x37;
 
         // act
-        processor.execute_opcode(0x4537);
+        processor.execute_opcode(0x4537).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0224);
@@ -553,13 +1848,13 @@ mod tests {
     #[test]
     fn op_0x5xy0_skip_vx_equals_vy() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0222;
         processor.v[5] = 0x37;
         processor.v[7] = 0x37;
 
         // act
-        processor.execute_opcode(0x5570);
+        processor.execute_opcode(0x5570).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0226);
@@ -568,13 +1863,13 @@ mod tests {
     #[test]
     fn op_0x5xy0_no_skip_vx_not_equals_vy() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0222;
         processor.v[5] = 0x37;
         processor.v[7] = 0x38;
 
         // act
-        processor.execute_opcode(0x5570);
+        processor.execute_opcode(0x5570).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0224);
@@ -583,10 +1878,10 @@ mod tests {
     #[test]
     fn op_0x6xnn_set_vx_to_nn() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
 
         // act
-        processor.execute_opcode(0x6570);
+        processor.execute_opcode(0x6570).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0x70);
@@ -596,11 +1891,11 @@ mod tests {
     #[test]
     fn op_0x7xnn_add_nn_to_vx() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0x05;
 
         // act
-        processor.execute_opcode(0x7505);
+        processor.execute_opcode(0x7505).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0x0a);
@@ -610,12 +1905,12 @@ mod tests {
     #[test]
     fn op_0x8xy0_set_vx_to_vy() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0x05;
         processor.v[7] = 0xfa;
 
         // act
-        processor.execute_opcode(0x8570);
+        processor.execute_opcode(0x8570).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0xfa);
@@ -626,12 +1921,12 @@ mod tests {
     #[test]
     fn op_0x8xy1_set_vx_to_vx_or_vy_bitwise() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0x05;
         processor.v[7] = 0xfa;
 
         // act
-        processor.execute_opcode(0x8571);
+        processor.execute_opcode(0x8571).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0xff);
@@ -642,12 +1937,12 @@ mod tests {
     #[test]
     fn op_0x8xy2_set_vx_to_vx_and_vy_bitwise() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0xfa;
         processor.v[7] = 0x0a;
 
         // act
-        processor.execute_opcode(0x8572);
+        processor.execute_opcode(0x8572).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0x0a);
@@ -658,12 +1953,12 @@ mod tests {
     #[test]
     fn op_0x8xy4_add_vy_to_vx_carry_set() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0xfe;
         processor.v[7] = 0x03;
 
         // act
-        processor.execute_opcode(0x8574);
+        processor.execute_opcode(0x8574).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0x01);
@@ -675,12 +1970,12 @@ mod tests {
     #[test]
     fn op_0x8xy4_add_vy_to_vx_carry_not_set() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0x0e;
         processor.v[7] = 0x03;
 
         // act
-        processor.execute_opcode(0x8574);
+        processor.execute_opcode(0x8574).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0x11);
@@ -692,12 +1987,12 @@ mod tests {
     #[test]
     fn op_0x8xy5_subtract_vy_from_vx_borrow_not_set() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0x01;
         processor.v[7] = 0x02;
 
         // act
-        processor.execute_opcode(0x8575);
+        processor.execute_opcode(0x8575).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0xff);
@@ -709,12 +2004,12 @@ mod tests {
     #[test]
     fn op_0x8xy5_subtract_vy_from_vx_borrow_set() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0x01;
         processor.v[7] = 0x01;
 
         // act
-        processor.execute_opcode(0x8575);
+        processor.execute_opcode(0x8575).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0x00);
@@ -726,11 +2021,11 @@ mod tests {
     #[test]
     fn op_0x8xy6_shift_right() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0x03;
 
         // act
-        processor.execute_opcode(0x8506);
+        processor.execute_opcode(0x8506).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0x01);
@@ -741,12 +2036,12 @@ mod tests {
     #[test]
     fn op_0x8xy7_subtract_vx_from_vy_borrow_set() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0x01;
         processor.v[7] = 0x01;
 
         // act
-        processor.execute_opcode(0x8577);
+        processor.execute_opcode(0x8577).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0x00);
@@ -758,11 +2053,11 @@ mod tests {
     #[test]
     fn op_0x8xye_shift_left() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[5] = 0x81;
 
         // act
-        processor.execute_opcode(0x850e);
+        processor.execute_opcode(0x850e).unwrap();
 
         // assert
         assert_eq!(processor.v[5], 0x02);
@@ -770,16 +2065,78 @@ mod tests {
         assert_eq!(processor.pc, 0x0202);
     }
 
+    #[test]
+    fn op_0x8xy6_shift_right_quirk_uses_vy() {
+        // arrange
+        let quirks = Quirks{ shift_uses_vy: true, ..Quirks::default() };
+        let mut processor = Processor::new(&[], quirks);
+        processor.v[5] = 0xf0;
+        processor.v[7] = 0x03;
+
+        // act
+        processor.execute_opcode(0x8576).unwrap();
+
+        // assert
+        assert_eq!(processor.v[5], 0x01);
+        assert_eq!(processor.v[7], 0x03);
+        assert_eq!(processor.v[VF], 0x1);
+    }
+
+    #[test]
+    fn op_0x8xye_shift_left_quirk_uses_vy() {
+        // arrange
+        let quirks = Quirks{ shift_uses_vy: true, ..Quirks::default() };
+        let mut processor = Processor::new(&[], quirks);
+        processor.v[5] = 0x01;
+        processor.v[7] = 0x81;
+
+        // act
+        processor.execute_opcode(0x857e).unwrap();
+
+        // assert
+        assert_eq!(processor.v[5], 0x02);
+        assert_eq!(processor.v[VF], 0x1);
+    }
+
+    #[test]
+    fn op_0x8xy1_vf_reset_quirk() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::COSMAC_VIP);
+        processor.v[5] = 0x05;
+        processor.v[7] = 0xfa;
+        processor.v[VF] = 0x1;
+
+        // act
+        processor.execute_opcode(0x8571).unwrap();
+
+        // assert
+        assert_eq!(processor.v[5], 0xff);
+        assert_eq!(processor.v[VF], 0x0);
+    }
+
+    #[test]
+    fn op_0x8xy2_no_vf_reset_by_default() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.v[VF] = 0x1;
+
+        // act
+        processor.execute_opcode(0x8572).unwrap();
+
+        // assert
+        assert_eq!(processor.v[VF], 0x1);
+    }
+
     #[test]
     fn op_0x9xy0_skip_vx_not_equals_vy() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0222;
         processor.v[5] = 0x37;
         processor.v[7] = 0x38;
 
         // act
-        processor.execute_opcode(0x9570);
+        processor.execute_opcode(0x9570).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0226);
@@ -788,25 +2145,57 @@ mod tests {
     #[test]
     fn op_0x9xy0_no_skip_vx_equals_vy() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0222;
         processor.v[5] = 0x37;
         processor.v[7] = 0x37;
 
         // act
-        processor.execute_opcode(0x9570);
+        processor.execute_opcode(0x9570).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0224);
     }
 
+    #[test]
+    fn op_0x5xyn_ignores_n_on_chip8() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.pc = 0x0222;
+        processor.v[0] = 9;
+        processor.v[1] = 9;
+        processor.i = 0x300;
+
+        // act
+        processor.execute_opcode(0x5012).unwrap();
+
+        // assert
+        assert_eq!(processor.pc, 0x0226);
+        assert_eq!(processor.ram[0x300], 0);
+    }
+
+    #[test]
+    fn op_0x9xyn_ignores_n() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.pc = 0x0222;
+        processor.v[5] = 0x37;
+        processor.v[7] = 0x38;
+
+        // act
+        processor.execute_opcode(0x957f).unwrap();
+
+        // assert
+        assert_eq!(processor.pc, 0x0226);
+    }
+
     #[test]
     fn op_0xannn_set_i_to_nnn() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
 
         // act
-        processor.execute_opcode(0xa123);
+        processor.execute_opcode(0xa123).unwrap();
 
         // assert
         assert_eq!(processor.i, 0x0123);
@@ -816,51 +2205,80 @@ mod tests {
     #[test]
     fn op_0xbnnn_jump_to_nnn_plus_v0() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[0] = 0x8;
 
         // act
-        processor.execute_opcode(0xb123);
+        processor.execute_opcode(0xb123).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x012b);
     }
 
+    #[test]
+    fn op_0xbxnn_jump_quirk_uses_vx() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::SUPER_CHIP);
+        processor.v[0] = 0x8;
+        processor.v[1] = 0x2;
+
+        // act
+        processor.execute_opcode(0xb123).unwrap();
+
+        // assert
+        assert_eq!(processor.pc, 0x0125);
+    }
+
     #[test]
     fn op_0xcxnn_random_number_god() {
         // arrange
-        let mut processor = Processor::new(vec![]);
-        // let mut rng = rand::rng(123);
+        let mut processor = Processor::new(&[], Quirks::default());
+        let mut expected = XorShift::new(123);
+        processor.seed_random(123);
 
         // act
-        processor.execute_opcode(0xc101);
+        processor.execute_opcode(0xc1ff).unwrap();
+        processor.execute_opcode(0xc20f).unwrap();
 
         // assert
-        // assert_eq!(processor.v[1], rng + 1);
-        assert_eq!(processor.pc, 0x0202);
+        assert_eq!(processor.v[1], expected.next_byte(&[]));
+        assert_eq!(processor.v[2], expected.next_byte(&[]) & 0x0f);
+        assert_eq!(processor.pc, 0x0204);
+    }
+
+    #[test]
+    fn op_0xcxnn_uses_injected_random_source() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.ram[0x101] = 0xff;
+        processor.set_random_source(Box::new(CosmacVipRandom::default()));
+
+        // act
+        processor.execute_opcode(0xc1ff).unwrap();
+
+        // assert
+        assert_eq!(processor.v[1], 0xff);
     }
 
     #[test]
     fn op_0xdxyn_draw_0_0() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[1] = 0;
         processor.v[2] = 0;
         processor.i = 0x300;
-        let skull = vec![
+        let skull = [
             0x7e, 0xc9, 0xc9, 0xf7, 0x6a, 0x3e, 0x2a, 0x2a
         ];
-        for i in 0..skull.len() {
-            processor.ram[0x300 + i] = skull[i];
-        }
+        processor.ram[0x300..0x300 + skull.len()].copy_from_slice(&skull);
 
         // act
-        processor.execute_opcode(0xd128);
+        processor.execute_opcode(0xd128).unwrap();
 
         // assert
         let expected_screen: Vec<u8> = vec![0x7e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc9, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc9, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf7, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x6a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
         assert_eq!(
-            processor.ram[VRAM_START..VRAM_START+VRAM_SIZE_BYTE], 
+            packed_screen(&processor), 
             expected_screen);
         assert_eq!(processor.pc, 0x0202);
         assert_eq!(processor.v[VF], 0);
@@ -869,24 +2287,22 @@ mod tests {
     #[test]
     fn op_0xdxyn_draw_1_1() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[1] = 1;
         processor.v[2] = 1;
         processor.i = 0x300;
-        let skull = vec![
+        let skull = [
             0x7e, 0xc9, 0xc9, 0xf7, 0x6a, 0x3e, 0x2a, 0x2a
         ];
-        for i in 0..skull.len() {
-            processor.ram[0x300 + i] = skull[i];
-        }
+        processor.ram[0x300..0x300 + skull.len()].copy_from_slice(&skull);
 
         // act
-        processor.execute_opcode(0xd128);
+        processor.execute_opcode(0xd128).unwrap();
 
         // assert
         let expected_screen: Vec<u8> = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7b, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x35, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x15, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x15, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
         assert_eq!(
-            processor.ram[VRAM_START..VRAM_START+VRAM_SIZE_BYTE], 
+            packed_screen(&processor), 
             expected_screen);
         assert_eq!(processor.pc, 0x0202);
         assert_eq!(processor.v[VF], 0);
@@ -895,24 +2311,22 @@ mod tests {
     #[test]
     fn op_0xdxyn_draw_9_59_overlap() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[1] = 60;
         processor.v[2] = 9;
         processor.i = 0x300;
-        let skull = vec![
+        let skull = [
             0x7e, 0xc9, 0xc9, 0xf7, 0x6a, 0x3e, 0x2a, 0x2a
         ];
-        for i in 0..skull.len() {
-            processor.ram[0x300 + i] = skull[i];
-        }
+        processor.ram[0x300..0x300 + skull.len()].copy_from_slice(&skull);
 
         // act
-        processor.execute_opcode(0xd128);
+        processor.execute_opcode(0xd128).unwrap();
 
         // assert
         let expected_screen: Vec<u8> = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x70, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0xa0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0xe0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xa0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0xa0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
         assert_eq!(
-            processor.ram[VRAM_START..VRAM_START+VRAM_SIZE_BYTE], 
+            packed_screen(&processor), 
             expected_screen);
         assert_eq!(processor.pc, 0x0202);
         assert_eq!(processor.v[VF], 0);
@@ -921,24 +2335,22 @@ mod tests {
     #[test]
     fn op_0xdxyn_draw_28_59_overlap() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[1] = 59;
         processor.v[2] = 28;
         processor.i = 0x300;
-        let skull = vec![
+        let skull = [
             0x7e, 0xc9, 0xc9, 0xf7, 0x6a, 0x3e, 0x2a, 0x2a
         ];
-        for i in 0..skull.len() {
-            processor.ram[0x300 + i] = skull[i];
-        }
+        processor.ram[0x300..0x300 + skull.len()].copy_from_slice(&skull);
 
         // act
-        processor.execute_opcode(0xd128);
+        processor.execute_opcode(0xd128).unwrap();
 
         // assert
         let expected_screen: Vec<u8> = vec![0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x19, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x19, 0xe0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1e];
         assert_eq!(
-            processor.ram[VRAM_START..VRAM_START+VRAM_SIZE_BYTE], 
+            packed_screen(&processor), 
             expected_screen);
         assert_eq!(processor.pc, 0x0202);
         assert_eq!(processor.v[VF], 0);
@@ -947,25 +2359,23 @@ mod tests {
     #[test]
     fn op_0xdxyn_draw_28_59_overlap_collision() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[1] = 59;
         processor.v[2] = 28;
         processor.i = 0x300;
-        processor.ram[VRAM_START] = 0b0100_0000;
-        let skull = vec![
+        processor.display.flip_pixel(1, 0, 1);
+        let skull = [
             0x7e, 0xc9, 0xc9, 0xf7, 0x6a, 0x3e, 0x2a, 0x2a
         ];
-        for i in 0..skull.len() {
-            processor.ram[0x300 + i] = skull[i];
-        }
+        processor.ram[0x300..0x300 + skull.len()].copy_from_slice(&skull);
 
         // act
-        processor.execute_opcode(0xd128);
+        processor.execute_opcode(0xd128).unwrap();
 
         // assert
         let expected_screen: Vec<u8> = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x19, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x19, 0xe0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1e];
         assert_eq!(
-            processor.ram[VRAM_START..VRAM_START+VRAM_SIZE_BYTE], 
+            packed_screen(&processor), 
             expected_screen);
         assert_eq!(processor.pc, 0x0202);
         assert_eq!(processor.v[VF], 1);
@@ -974,53 +2384,165 @@ mod tests {
     #[test]
     fn op_0xdxyn_draw_28_59_overlap_no_collision() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[1] = 59;
         processor.v[2] = 28;
         processor.i = 0x300;
-        processor.ram[VRAM_START] = 0b1000_0000;
-        let skull = vec![
+        processor.display.flip_pixel(0, 0, 1);
+        let skull = [
             0x7e, 0xc9, 0xc9, 0xf7, 0x6a, 0x3e, 0x2a, 0x2a
         ];
-        for i in 0..skull.len() {
-            processor.ram[0x300 + i] = skull[i];
-        }
+        processor.ram[0x300..0x300 + skull.len()].copy_from_slice(&skull);
 
         // act
-        processor.execute_opcode(0xd128);
+        processor.execute_opcode(0xd128).unwrap();
 
         // assert
         let expected_screen: Vec<u8> = vec![0b1100_0000, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x19, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x19, 0xe0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1e];
         assert_eq!(
-            processor.ram[VRAM_START..VRAM_START+VRAM_SIZE_BYTE], 
+            packed_screen(&processor), 
             expected_screen);
         assert_eq!(processor.pc, 0x0202);
         assert_eq!(processor.v[VF], 0);
     }
 
+    #[test]
+    fn op_0xdxyn_clip_quirk() {
+        // arrange
+        let quirks = Quirks{ clip_sprites: true, ..Quirks::default() };
+        let mut processor = Processor::new(&[], quirks);
+        processor.v[1] = 60;
+        processor.v[2] = 30;
+        processor.i = 0x300;
+        processor.ram[0x300..0x304].copy_from_slice(&[0xff; 4]);
+
+        // act
+        processor.execute_opcode(0xd124).unwrap();
+
+        // assert
+        assert!(processor.pixel(63, 31));
+        assert!(!processor.pixel(0, 31));
+        assert!(!processor.pixel(63, 0));
+        assert!(!processor.pixel(0, 0));
+    }
+
+    #[test]
+    fn op_0xdxyn_clip_quirk_wraps_start_position() {
+        // arrange
+        let quirks = Quirks{ clip_sprites: true, ..Quirks::default() };
+        let mut processor = Processor::new(&[], quirks);
+        processor.v[1] = 65;
+        processor.v[2] = 33;
+        processor.i = 0x300;
+        processor.ram[0x300] = 0x80;
+
+        // act
+        processor.execute_opcode(0xd121).unwrap();
+
+        // assert
+        assert!(processor.pixel(1, 1));
+    }
+
+    #[test]
+    fn op_0xdxyn_display_wait_quirk() {
+        // arrange
+        // D001: draw, 6101: V1 = 1
+        let quirks = Quirks{ display_wait: true, ..Quirks::default() };
+        let mut processor = Processor::new(&[0xd0, 0x01, 0x61, 0x01], quirks);
+
+        // act
+        processor.step().unwrap();
+        processor.step().unwrap();
+        let v1_before_vblank = processor.v[1];
+        processor.timer_tick();
+        processor.step().unwrap();
+
+        // assert
+        assert_eq!(v1_before_vblank, 0);
+        assert_eq!(processor.v[1], 1);
+    }
+
+    #[test]
+    fn op_0xdxy0_draw_16x16_sprite() {
+        // arrange
+        let mut processor = schip();
+        processor.execute_opcode(0x00ff).unwrap();
+        processor.v[1] = 120;
+        processor.v[2] = 10;
+        processor.i = 0x300;
+        processor.ram[0x300..0x320].copy_from_slice(&[0x80, 0x01].repeat(16));
+
+        // act
+        processor.execute_opcode(0xd120).unwrap();
+
+        // assert
+        for y in 10..26 {
+            assert!(processor.pixel(120, y));
+            assert!(!processor.pixel(127, y));
+        }
+        assert!(!processor.pixel(120, 26));
+        assert_eq!(processor.framebuffer().iter().filter(|&&p| p != 0).count(), 16);
+        assert_eq!(processor.v[VF], 0);
+
+        // act
+        processor.execute_opcode(0xd120).unwrap();
+
+        // assert
+        assert!(processor.framebuffer().iter().all(|&p| p == 0));
+        assert_eq!(processor.v[VF], 1);
+    }
+
+    #[test]
+    fn op_0xdxy0_draws_nothing_on_chip8() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.i = 0x300;
+        processor.ram[0x300..0x320].copy_from_slice(&[0xff; 32]);
+
+        // act
+        processor.execute_opcode(0xd120).unwrap();
+
+        // assert
+        assert!(processor.framebuffer().iter().all(|&p| p == 0));
+        assert_eq!(processor.pc, 0x0202);
+    }
+
     #[test]
     fn set_pixel_test() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
 
         // act
-        processor.set_pixel(4, 1, true);
+        processor.display.flip_pixel(4, 1, 1);
 
         // assert
+        assert_eq!(packed_screen(&processor)[SCREEN_WIDTH/8], 0b0000_1000);
+    }
+
+    #[test]
+    fn keys_outside_the_keypad_are_ignored() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+
+        // act
+        processor.key_pressed(16);
+        processor.set_key(usize::MAX, true);
+        processor.key_released(16);
 
-        assert_eq!(processor.ram[VRAM_START+SCREEN_WIDTH/8], 0b0000_1000);
+        // assert
+        assert!(processor.key_state.iter().all(|&pressed| !pressed));
     }
 
     #[test]
     fn op_0xex9e_skip() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0220;
         processor.v[3] = 5;
         processor.key_state[5] = true;
 
         // act
-        processor.execute_opcode(0xe39e);
+        processor.execute_opcode(0xe39e).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0224);
@@ -1029,13 +2551,13 @@ mod tests {
     #[test]
     fn op_0xex9e_no_skip() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0220;
         processor.v[3] = 5;
         processor.key_state[5] = false;
 
         // act
-        processor.execute_opcode(0xe39e);
+        processor.execute_opcode(0xe39e).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0222);
@@ -1044,13 +2566,13 @@ mod tests {
     #[test]
     fn op_0xexa1_skip() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0220;
         processor.v[3] = 5;
         processor.key_state[5] = false;
 
         // act
-        processor.execute_opcode(0xe3a1);
+        processor.execute_opcode(0xe3a1).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0224);
@@ -1059,13 +2581,13 @@ mod tests {
     #[test]
     fn op_0xexa1_no_skip() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0220;
         processor.v[3] = 5;
         processor.key_state[5] = true;
 
         // act
-        processor.execute_opcode(0xe3a1);
+        processor.execute_opcode(0xe3a1).unwrap();
 
         // assert
         assert_eq!(processor.pc, 0x0222);
@@ -1074,12 +2596,12 @@ mod tests {
     #[test]
     fn op_0xfx07_set_delay_timer() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0220;
         processor.delay_timer = 15;
 
         // act
-        processor.execute_opcode(0xf307);
+        processor.execute_opcode(0xf307).unwrap();
 
         // assert
         assert_eq!(processor.v[3], 15);
@@ -1089,13 +2611,15 @@ mod tests {
     #[test]
     fn op_0xfx0a_wait_for_key_pressed() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.pc = 0x0220;
         processor.key_state[12] = true;
+        // 6000: V0 = 0, executed once the key was stored
+        processor.ram[0x0222] = 0x60;
 
         // act
-        processor.execute_opcode(0xf30a);
-        processor.tick();
+        processor.execute_opcode(0xf30a).unwrap();
+        processor.step().unwrap();
 
         // assert
         assert_eq!(processor.v[3], 12);
@@ -1105,12 +2629,12 @@ mod tests {
     #[test]
     fn op_0xfx15_set_delay_timer_to_vx() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[3] = 35;
         processor.pc = 0x0220;
 
         // act
-        processor.execute_opcode(0xf315);
+        processor.execute_opcode(0xf315).unwrap();
 
         // assert
         assert_eq!(processor.delay_timer, 35);
@@ -1120,28 +2644,112 @@ mod tests {
     #[test]
     fn op_0xfx18_set_sound_timer_to_vx() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[3] = 35;
         processor.pc = 0x0220;
 
         // act
-        processor.execute_opcode(0xf318);
+        processor.execute_opcode(0xf318).unwrap();
 
         // assert
         assert_eq!(processor.sound_timer, 35);
         assert_eq!(processor.pc, 0x0222);
     }
 
+    #[test]
+    fn timer_tick_counts_down_both_timers() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.delay_timer = 3;
+        processor.sound_timer = 1;
+
+        // act
+        processor.timer_tick();
+        processor.timer_tick();
+
+        // assert
+        assert_eq!(processor.delay_timer, 1);
+        assert_eq!(processor.sound_timer, 0);
+        assert!(!processor.is_sound_active());
+        assert_eq!(processor.frames(), 2);
+    }
+
+    #[test]
+    fn run_frame_ticks_timers_once_per_frame() {
+        // arrange
+        // 6A3C: VA = 60, FA15: delay timer = VA, 1204: loop
+        let mut processor = Processor::new(&[0x6a, 0x3c, 0xfa, 0x15, 0x12, 0x06, 0x12, 0x04], Quirks::default());
+
+        // act
+        for _ in 0..10 {
+            processor.run_frame(100).unwrap();
+        }
+
+        // assert
+        assert_eq!(processor.delay_timer(), 50);
+        assert_eq!(processor.frames(), 10);
+    }
+
+    #[test]
+    fn run_frame_with_runs_each_step_through_the_hook() {
+        // arrange
+        // 7001: V0 += 1, 1200: loop
+        let mut processor = Processor::new(&[0x70, 0x01, 0x12, 0x00], Quirks::default());
+        let mut pcs = Vec::new();
+
+        // act
+        processor.run_frame_with(3, |processor| {
+            pcs.push(processor.pc());
+            processor.step()
+        }).unwrap();
+
+        // assert
+        assert_eq!(pcs, [0x200, 0x202, 0x200]);
+        assert_eq!(processor.v[0], 2);
+        assert_eq!(processor.frames(), 1);
+    }
+
+    #[test]
+    fn end_step_ticks_timers_once_the_frame_is_full() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.delay_timer = 5;
+
+        // act
+        let mid_frame = processor.end_step(2, 3);
+        let frame_end = processor.end_step(3, 3);
+
+        // assert
+        assert!(!mid_frame);
+        assert!(frame_end);
+        assert_eq!(processor.delay_timer(), 4);
+        assert_eq!(processor.frames(), 1);
+    }
+
+    #[test]
+    fn sound_state_follows_sound_timer() {
+        // arrange
+        let mut processor = Processor::new(&[], Quirks::default());
+        processor.v[3] = 2;
+
+        // act
+        processor.execute_opcode(0xf318).unwrap();
+
+        // assert
+        assert!(processor.is_sound_active());
+        assert_eq!(processor.sound_state(), SoundState{ active: true, remaining_frames: 2, pattern: None, pitch: DEFAULT_PITCH });
+    }
+
     #[test]
     fn op_0xfx1e_add_vx_to_i() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[3] = 35;
         processor.pc = 0x0220;
         processor.i = 3;
 
         // act
-        processor.execute_opcode(0xf31e);
+        processor.execute_opcode(0xf31e).unwrap();
 
         // assert
         assert_eq!(processor.i, 38);
@@ -1151,28 +2759,60 @@ mod tests {
     #[test]
     fn op_0xfx29_set_i_to_sprite() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[3] = 0xd;
         processor.pc = 0x0220;
 
         // act
-        processor.execute_opcode(0xf329);
+        processor.execute_opcode(0xf329).unwrap();
 
         // assert
         assert_eq!(processor.i, FONT_START + 13 * 5);
         assert_eq!(processor.pc, 0x0222);
     }
 
+    #[test]
+    fn op_0xfx30_set_i_to_big_sprite() {
+        // arrange
+        let mut processor = schip();
+        processor.v[3] = 0x9;
+
+        // act
+        processor.execute_opcode(0xf330).unwrap();
+
+        // assert
+        assert_eq!(processor.i, BIG_FONT_START + 9 * 10);
+        assert_eq!(processor.ram[processor.i..processor.i + 10], BIG_FONT_SET[90..100]);
+        assert_eq!(processor.pc, 0x0202);
+    }
+
+    #[test]
+    fn op_0xfx75_and_0xfx85_rpl_user_flags() {
+        // arrange
+        let mut processor = schip();
+        processor.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+
+        // act
+        processor.execute_opcode(0xf275).unwrap();
+        processor.v[..4].copy_from_slice(&[0; 4]);
+        processor.execute_opcode(0xf385).unwrap();
+
+        // assert
+        assert_eq!(processor.rpl_flags()[..4], [1, 2, 3, 0]);
+        assert_eq!(processor.v[..4], [1, 2, 3, 0]);
+        assert_eq!(processor.pc, 0x0204);
+    }
+
     #[test]
     fn op_0xfx33_store_decimal() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[3] = 123;
         processor.pc = 0x0220;
         processor.i = 0x300;
 
         // act
-        processor.execute_opcode(0xf333);
+        processor.execute_opcode(0xf333).unwrap();
 
         // assert
         assert_eq!(processor.ram[0x300], 1);
@@ -1184,7 +2824,7 @@ mod tests {
     #[test]
     fn op_0xfx55_store_v_to_ram() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.v[0] = 0;
         processor.v[1] = 1;
         processor.v[2] = 2;
@@ -1193,7 +2833,7 @@ mod tests {
         processor.i = 0x300;
 
         // act
-        processor.execute_opcode(0xf355);
+        processor.execute_opcode(0xf355).unwrap();
 
         // assert
         assert_eq!(processor.ram[0x300], 0);
@@ -1204,10 +2844,38 @@ mod tests {
         assert_eq!(processor.pc, 0x0222);
     }
 
+    #[test]
+    fn op_0xfx55_load_store_quirk_increments_i() {
+        // arrange
+        let quirks = Quirks{ load_store_increments_i: true, ..Quirks::default() };
+        let mut processor = Processor::new(&[], quirks);
+        processor.i = 0x300;
+
+        // act
+        processor.execute_opcode(0xf355).unwrap();
+
+        // assert
+        assert_eq!(processor.i, 0x304);
+    }
+
+    #[test]
+    fn op_0xfx65_load_store_quirk_increments_i() {
+        // arrange
+        let quirks = Quirks{ load_store_increments_i: true, ..Quirks::default() };
+        let mut processor = Processor::new(&[], quirks);
+        processor.i = 0x300;
+
+        // act
+        processor.execute_opcode(0xf265).unwrap();
+
+        // assert
+        assert_eq!(processor.i, 0x303);
+    }
+
     #[test]
     fn op_0xfx65_store_ram_to_v() {
         // arrange
-        let mut processor = Processor::new(vec![]);
+        let mut processor = Processor::new(&[], Quirks::default());
         processor.ram[0x300] = 0;
         processor.ram[0x301] = 1;
         processor.ram[0x302] = 2;
@@ -1217,7 +2885,7 @@ mod tests {
         processor.i = 0x300;
 
         // act
-        processor.execute_opcode(0xf365);
+        processor.execute_opcode(0xf365).unwrap();
 
         // assert
         assert_eq!(processor.v[0], 0);
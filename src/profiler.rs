@@ -0,0 +1,231 @@
+//! Coverage and hotspot profiling of ROM execution.
+//!
+//! A [`Profiler`] runs the processor like [`Processor::step`] and
+//! [`Processor::run_frame`] while counting how often each address is executed
+//! and how many cycles each subroutine takes, one cycle per instruction. It
+//! follows `2NNN` calls and `00EE` returns with the stack pointer, so it keeps
+//! track of the call stack for [`Profiler::folded`], the input format of
+//! flamegraph tools such as `flamegraph.pl` and `inferno`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::disasm::{Disassembly, Item, Syntax};
+use crate::error::Chip8Error;
+use crate::processor::{Processor, StepOutcome};
+
+/// The cycles spent in a subroutine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubroutineStats {
+    /// Number of times the subroutine was called.
+    pub calls: u64,
+    /// Cycles spent in the subroutine and the subroutines it called.
+    pub inclusive: u64,
+    /// Cycles spent in the subroutine itself.
+    pub exclusive: u64
+}
+
+/// Counts executions per address and cycles per subroutine.
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    // Executions per address, grown to the highest executed address.
+    counts: Vec<u64>,
+    cycles: u64,
+    // Entry addresses of the active subroutines, outermost first. `None` for
+    // calls made before profiling started.
+    stack: Vec<Option<usize>>,
+    subroutines: BTreeMap<usize, SubroutineStats>,
+    // Cycles per call stack.
+    stacks: BTreeMap<Vec<Option<usize>>, u64>,
+    // Whether the last step waited for a key, see `Tracer`.
+    waiting_for_key: bool
+}
+
+impl Profiler {
+
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// Emulates one frame like [`Processor::run_frame`] while profiling.
+    pub fn run_frame(&mut self, processor: &mut Processor, cycles_per_frame: usize) -> Result<(), Chip8Error> {
+        processor.run_frame_with(cycles_per_frame, |processor| self.step(processor))
+    }
+
+    /// Executes one instruction like [`Processor::step`] and counts it.
+    pub fn step(&mut self, processor: &mut Processor) -> Result<StepOutcome, Chip8Error> {
+        let pc = processor.pc() + if self.waiting_for_key { 2 } else { 0 };
+        let result = processor.step();
+        self.waiting_for_key = result == Ok(StepOutcome::WaitingForKey);
+        if result != Ok(StepOutcome::Executed) {
+            return result;
+        }
+
+        if self.counts.len() <= pc {
+            self.counts.resize(pc + 1, 0);
+        }
+        self.counts[pc] += 1;
+        self.cycles += 1;
+        // A call is counted for the caller and a return for the subroutine.
+        self.count_cycle();
+
+        let sp = processor.sp();
+        if sp < self.stack.len() {
+            self.stack.truncate(sp);
+        } else if sp > self.stack.len() {
+            self.stack.resize(sp - 1, None);
+            self.stack.push(Some(processor.pc()));
+            self.subroutines.entry(processor.pc()).or_default().calls += 1;
+        }
+        result
+    }
+
+    fn count_cycle(&mut self) {
+        for (depth, entry) in self.stack.iter().enumerate() {
+            if let Some(entry) = *entry {
+                // Recursive calls are only counted once.
+                let outer = self.stack[..depth].contains(&Some(entry));
+                let stats = self.subroutines.entry(entry).or_default();
+                if !outer {
+                    stats.inclusive += 1;
+                }
+                if depth + 1 == self.stack.len() {
+                    stats.exclusive += 1;
+                }
+            }
+        }
+        match self.stacks.get_mut(&self.stack) {
+            Some(cycles) => *cycles += 1,
+            None => {
+                self.stacks.insert(self.stack.clone(), 1);
+            }
+        }
+    }
+
+    /// Total number of profiled cycles.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// How often the instruction at `address` was executed.
+    pub fn count(&self, address: usize) -> u64 {
+        self.counts.get(address).copied().unwrap_or(0)
+    }
+
+    /// The executed addresses and their counts, in address order.
+    pub fn counts(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.counts.iter().copied().enumerate().filter(|&(_, count)| count > 0)
+    }
+
+    /// The called subroutines by entry address.
+    pub fn subroutines(&self) -> &BTreeMap<usize, SubroutineStats> {
+        &self.subroutines
+    }
+
+    /// The cycles per call stack in the folded format of flamegraph tools:
+    /// a line per stack with its frames separated by `;` and the cycle count.
+    /// Subroutines are named like the disassembler's labels, the code outside
+    /// of subroutines `main`.
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        for (stack, cycles) in &self.stacks {
+            out.push_str("main");
+            for entry in stack {
+                match entry {
+                    Some(entry) => out.push_str(&format!(";{}", Disassembly::label(*entry))),
+                    None => out.push_str(";?")
+                }
+            }
+            let _ = writeln!(out, " {}", cycles);
+        }
+        out
+    }
+
+    /// The disassembly's listing with the execution count and share of every
+    /// instruction appended to its comment, preceded by a comment per
+    /// subroutine with its calls and cycles.
+    pub fn annotated_listing(&self, disassembly: &Disassembly, syntax: Syntax) -> String {
+        let comment = match syntax {
+            Syntax::Cowgod => ';',
+            Syntax::Octo => '#'
+        };
+        let mut out = String::new();
+        let _ = writeln!(out, "{} {} cycles", comment, self.cycles);
+        for (entry, stats) in &self.subroutines {
+            let _ = writeln!(out, "{} {}: {} calls, {} cycles, {} in itself",
+                comment, Disassembly::label(*entry), stats.calls, stats.inclusive, stats.exclusive);
+        }
+        out.push_str(&disassembly.annotated_listing(syntax, &|item| match item {
+            Item::Code{ address, .. } => Some(self.annotation(*address)),
+            Item::Data{ .. } => None
+        }));
+        out
+    }
+
+    fn annotation(&self, address: usize) -> String {
+        match self.count(address) {
+            0 => "never".to_string(),
+            count => format!("{}x {:.1}%", count, count as f64 * 100.0 / self.cycles as f64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+    use crate::platform::Platform;
+    use crate::quirks::Quirks;
+
+    // 200: 6003 V0 = 3, 202: 220A call, 204: 3000 skip if V0 == 0, 206: 1202 loop,
+    // 208: 1208 exit, 20A: 70FF V0 -= 1, 20C: 00EE
+    const ROM: [u8; 14] = [0x60, 0x03, 0x22, 0x0a, 0x30, 0x00, 0x12, 0x02, 0x12, 0x08, 0x70, 0xff, 0x00, 0xee];
+
+    fn profile() -> Profiler {
+        let mut processor = Processor::new(&ROM, Quirks::default());
+        let mut profiler = Profiler::new();
+        profiler.run_frame(&mut processor, 100).unwrap();
+        profiler
+    }
+
+    #[test]
+    fn counts_executions_per_address() {
+        // arrange
+        let profiler = profile();
+
+        // act
+        let counts: Vec<(usize, u64)> = profiler.counts().collect();
+
+        // assert
+        assert_eq!(counts, [(0x200, 1), (0x202, 3), (0x204, 3), (0x206, 2), (0x208, 1), (0x20a, 3), (0x20c, 3)]);
+        assert_eq!(profiler.cycles(), 16);
+    }
+
+    #[test]
+    fn counts_cycles_per_subroutine() {
+        // arrange
+        let profiler = profile();
+
+        // act
+        let stats = profiler.subroutines()[&0x20a];
+
+        // assert
+        assert_eq!(stats, SubroutineStats{ calls: 3, inclusive: 6, exclusive: 6 });
+        assert_eq!(profiler.folded(), "main 10\nmain;L20A 6\n");
+    }
+
+    #[test]
+    fn annotated_listing_shows_counts() {
+        // arrange
+        let profiler = profile();
+        let disassembly = disassemble(&ROM, Platform::Chip8);
+
+        // act
+        let listing = profiler.annotated_listing(&disassembly, Syntax::Cowgod);
+
+        // assert
+        assert!(listing.starts_with("; 16 cycles\n; L20A: 3 calls, 6 cycles, 6 in itself\n"));
+        assert!(listing.contains("; 200: 6003 1x 6"));
+        assert!(listing.contains("; 20a: 70ff 3x 18"));
+    }
+}
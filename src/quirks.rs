@@ -0,0 +1,97 @@
+//! Behaviour of the opcodes that CHIP-8 interpreters disagree on.
+
+use std::str::FromStr;
+
+/// Selects how the ambiguous opcodes behave.
+///
+/// The default matches what this emulator always did and suits most modern
+/// CHIP-8 ROMs. The presets follow the interpreters ROMs were written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift VY and store the result in VX, instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// `FX55`/`FX65` leave I pointing behind the last register stored or loaded.
+    pub load_store_increments_i: bool,
+    /// `BXNN` jumps to XNN plus VX, instead of `BNNN` jumping to NNN plus V0.
+    pub jump_uses_vx: bool,
+    /// `8XY1`/`8XY2`/`8XY3` reset VF to 0.
+    pub vf_reset: bool,
+    /// `DXYN` clips sprites at the screen edges instead of wrapping them around.
+    pub clip_sprites: bool,
+    /// `DXYN` waits for the vertical blank, so at most one sprite is drawn per frame.
+    pub display_wait: bool
+}
+
+impl Quirks {
+
+    /// The original COSMAC VIP interpreter.
+    pub const COSMAC_VIP: Quirks = Quirks{
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        vf_reset: true,
+        clip_sprites: true,
+        display_wait: true
+    };
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub const CHIP_48: Quirks = Quirks{
+        shift_uses_vy: false,
+        load_store_increments_i: true,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false
+    };
+
+    /// SUPER-CHIP 1.1.
+    pub const SUPER_CHIP: Quirks = Quirks{
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false
+    };
+
+    /// XO-CHIP as implemented by Octo.
+    pub const XO_CHIP: Quirks = Quirks{
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        display_wait: false
+    };
+}
+
+/// Parses a preset name: `default`, `vip`, `chip48`, `schip` or `xochip`.
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "default" => Ok(Quirks::default()),
+            "vip" | "cosmacvip" => Ok(Quirks::COSMAC_VIP),
+            "chip48" => Ok(Quirks::CHIP_48),
+            "schip" | "superchip" => Ok(Quirks::SUPER_CHIP),
+            "xochip" => Ok(Quirks::XO_CHIP),
+            _ => Err(format!("unknown quirks preset '{}', expected default, vip, chip48, schip or xochip", s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_preset_names() {
+        assert_eq!("default".parse(), Ok(Quirks::default()));
+        assert_eq!("VIP".parse(), Ok(Quirks::COSMAC_VIP));
+        assert_eq!("chip-48".parse(), Ok(Quirks::CHIP_48));
+        assert_eq!("super-chip".parse(), Ok(Quirks::SUPER_CHIP));
+        assert_eq!("xochip".parse(), Ok(Quirks::XO_CHIP));
+        assert!("megachip".parse::<Quirks>().is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Random number sources for `CXNN`.
+//!
+//! The processor draws its random bytes from a [`RandomSource`], by default an
+//! [`XorShift`] generator seeded from the operating system. Seeding it makes
+//! runs reproducible, e.g. for tests and input movie replays.
+
+use std::convert::TryFrom;
+
+/// Generates the random bytes for `CXNN`.
+///
+/// The state of the source is part of save states, so it has to be serializable.
+pub trait RandomSource {
+    /// Returns the next random byte. `memory` is the processor's RAM, for
+    /// sources that derive their numbers from it.
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+
+    /// Called on every 60 Hz timer tick.
+    fn timer_tick(&mut self) {}
+
+    /// Serializes the state of the source.
+    fn save(&self) -> Vec<u8>;
+
+    /// Restores a state written by [`RandomSource::save`]. Returns false,
+    /// leaving the source unchanged, if the state is invalid.
+    fn restore(&mut self, state: &[u8]) -> bool;
+
+    fn clone_box(&self) -> Box<dyn RandomSource>;
+}
+
+impl Clone for Box<dyn RandomSource> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// The xorshift32 generator, the default source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XorShift {
+    // Never zero, which would only ever yield zeros.
+    state: u32
+}
+
+impl XorShift {
+
+    /// Creates a generator with the given seed. A seed of zero is replaced by
+    /// a fixed non-zero seed.
+    pub fn new(seed: u32) -> Self {
+        XorShift{ state: if seed == 0 { 0x9e37_79b9 } else { seed } }
+    }
+
+    /// Creates a generator seeded by the operating system.
+    pub fn from_entropy() -> Self {
+        XorShift::new(rand::random())
+    }
+}
+
+impl RandomSource for XorShift {
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        // The high byte has the best distribution.
+        (self.state >> 24) as u8
+    }
+
+    fn save(&self) -> Vec<u8> {
+        self.state.to_le_bytes().to_vec()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> bool {
+        match <[u8; 4]>::try_from(state).map(u32::from_le_bytes) {
+            Ok(seed) if seed != 0 => {
+                self.state = seed;
+                true
+            },
+            _ => false
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn RandomSource> {
+        Box::new(*self)
+    }
+}
+
+/// Mimics the random number routine of the COSMAC VIP interpreter.
+///
+/// The VIP kept a 16-bit seed in register R9 whose low byte the 60 Hz
+/// interrupt incremented. `CXNN` incremented it once more, added the high byte
+/// to the byte at `0x100` plus the low byte and stored the sum as the new high
+/// byte. The numbers therefore depend on the timing of the program. On the VIP
+/// that page held the interpreter itself; here it holds whatever the emulated
+/// memory contains, so the sequences differ from real hardware.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct CosmacVipRandom {
+    seed: u16
+}
+
+impl CosmacVipRandom {
+
+    pub fn new(seed: u16) -> Self {
+        CosmacVipRandom{ seed }
+    }
+}
+
+impl RandomSource for CosmacVipRandom {
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        let [high, low] = self.seed.to_be_bytes();
+        let low = low.wrapping_add(1);
+        let value = memory.get(0x100 + low as usize).copied().unwrap_or(0).wrapping_add(high);
+        self.seed = u16::from_be_bytes([value, low]);
+        value
+    }
+
+    fn timer_tick(&mut self) {
+        let [high, low] = self.seed.to_be_bytes();
+        self.seed = u16::from_be_bytes([high, low.wrapping_add(1)]);
+    }
+
+    fn save(&self) -> Vec<u8> {
+        self.seed.to_le_bytes().to_vec()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> bool {
+        match <[u8; 2]>::try_from(state) {
+            Ok(seed) => {
+                self.seed = u16::from_le_bytes(seed);
+                true
+            },
+            Err(_) => false
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn RandomSource> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xorshift_is_reproducible() {
+        // arrange
+        let mut first = XorShift::new(42);
+        let mut second = XorShift::new(42);
+
+        // act
+        let a: Vec<u8> = (0..16).map(|_| first.next_byte(&[])).collect();
+        let b: Vec<u8> = (0..16).map(|_| second.next_byte(&[])).collect();
+
+        // assert
+        assert_eq!(a, b);
+        assert_ne!(a, vec![a[0]; 16]);
+    }
+
+    #[test]
+    fn xorshift_yields_every_byte() {
+        // arrange
+        let mut rng = XorShift::new(1);
+        let mut seen = [false; 256];
+
+        // act
+        for _ in 0..10_000 {
+            seen[rng.next_byte(&[]) as usize] = true;
+        }
+
+        // assert
+        assert!(seen.iter().all(|&seen| seen));
+    }
+
+    #[test]
+    fn xorshift_restore_rejects_invalid_state() {
+        // arrange
+        let mut rng = XorShift::new(7);
+
+        // act
+        let zero = rng.restore(&[0, 0, 0, 0]);
+        let short = rng.restore(&[1, 2]);
+
+        // assert
+        assert!(!zero);
+        assert!(!short);
+        assert_eq!(rng, XorShift::new(7));
+    }
+
+    #[test]
+    fn cosmac_vip_adds_memory_byte_to_previous_result() {
+        // arrange
+        let mut memory = vec![0; 0x200];
+        memory[0x101] = 0x10;
+        memory[0x102] = 0x20;
+        memory[0x104] = 0x05;
+        let mut rng = CosmacVipRandom::new(0);
+
+        // act
+        let first = rng.next_byte(&memory);
+        let second = rng.next_byte(&memory);
+        rng.timer_tick();
+        let third = rng.next_byte(&memory);
+
+        // assert
+        assert_eq!((first, second, third), (0x10, 0x30, 0x35));
+    }
+}
@@ -0,0 +1,412 @@
+//! Rendering the display into RGBA8 images.
+//!
+//! A [`Renderer`] turns the [`Display`]'s pixels into four bytes per pixel,
+//! red, green, blue and alpha, coloured by a [`Palette`] and optionally scaled
+//! up by an integer factor. The result has the layout of the browser's
+//! `ImageData`, so a frontend can blit it in one `putImageData` call or hand
+//! it to an image encoder.
+//!
+//! Because CHIP-8 games erase and redraw sprites by XOR, moving objects are
+//! often missing from the frame that happens to be shown and flicker. A
+//! [`Persistence`] other than `Off` blends each rendered frame with the
+//! previous ones like the phosphor of a CRT.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::display::Display;
+
+/// An RGBA8 colour.
+pub type Rgba = [u8; 4];
+
+/// The colours of the four pixel values: unset, plane 1, plane 2 and both
+/// planes. CHIP-8 and SUPER-CHIP only use the first two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgba; 4]
+}
+
+impl Palette {
+
+    /// Black and white, with grey for the XO-CHIP planes.
+    pub const MONOCHROME: Palette = Palette{ colors: [
+        [0x00, 0x00, 0x00, 0xff],
+        [0xff, 0xff, 0xff, 0xff],
+        [0xaa, 0xaa, 0xaa, 0xff],
+        [0x55, 0x55, 0x55, 0xff]
+    ]};
+
+    /// The colours of the web frontend.
+    pub const WEB: Palette = Palette{ colors: [
+        [0x0b, 0x26, 0x33, 0xff],
+        [0x2a, 0x9f, 0xd6, 0xff],
+        [0xd6, 0x61, 0x2a, 0xff],
+        [0xf2, 0xe3, 0x5a, 0xff]
+    ]};
+
+    /// Octo's default XO-CHIP colours.
+    pub const OCTO: Palette = Palette{ colors: [
+        [0x99, 0x66, 0x00, 0xff],
+        [0xff, 0xcc, 0x00, 0xff],
+        [0xff, 0x66, 0x00, 0xff],
+        [0x66, 0x22, 0x00, 0xff]
+    ]};
+
+    /// The colour of a pixel value, ignoring bits above the two planes.
+    pub fn color(&self, pixel: u8) -> Rgba {
+        self.colors[(pixel & 0b11) as usize]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::MONOCHROME
+    }
+}
+
+/// Parsed from two or four comma separated `#rrggbb` colours, e.g.
+/// `#000000,#ffffff`. With two colours the XO-CHIP planes keep the colours of
+/// [`Palette::MONOCHROME`].
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid palette '{}', expected 2 or 4 comma separated #rrggbb colours", s);
+        let colors = s.split(',').map(parse_color).collect::<Option<Vec<Rgba>>>().ok_or_else(invalid)?;
+        let mut palette = Palette::MONOCHROME;
+        match colors.len() {
+            2 | 4 => palette.colors[..colors.len()].copy_from_slice(&colors),
+            _ => return Err(invalid())
+        }
+        Ok(palette)
+    }
+}
+
+fn parse_color(s: &str) -> Option<Rgba> {
+    let hex = s.trim().strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 0xff])
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (n, [r, g, b, _]) in self.colors.iter().enumerate() {
+            if n > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "#{:02x}{:02x}{:02x}", r, g, b)?;
+        }
+        Ok(())
+    }
+}
+
+/// How long pixels stay visible after they were cleared.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Persistence {
+    /// Every frame shows the display as it is.
+    #[default]
+    Off,
+    /// Cleared pixels fade to the background colour, keeping the given share
+    /// (0.0 to 1.0) of their previous colour each frame.
+    Decay(f32),
+    /// Cleared pixels keep the colour they last had within the last `n`
+    /// frames, including the current one.
+    MaxOf(usize)
+}
+
+/// Parsed from `off`, `decay:<share>` or `max:<frames>`, e.g. `decay:0.6`.
+impl FromStr for Persistence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid persistence '{}', expected off, decay:<0.0-1.0> or max:<frames>", s);
+        match s.split_once(':') {
+            None if s == "off" => Ok(Persistence::Off),
+            Some(("decay", share)) => share.parse()
+                .ok()
+                .filter(|share| (0.0..=1.0).contains(share))
+                .map(Persistence::Decay)
+                .ok_or_else(invalid),
+            Some(("max", frames)) => frames.parse()
+                .ok()
+                .filter(|&frames| frames > 0)
+                .map(Persistence::MaxOf)
+                .ok_or_else(invalid),
+            _ => Err(invalid())
+        }
+    }
+}
+
+impl fmt::Display for Persistence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Persistence::Off => write!(f, "off"),
+            Persistence::Decay(share) => write!(f, "decay:{}", share),
+            Persistence::MaxOf(frames) => write!(f, "max:{}", frames)
+        }
+    }
+}
+
+/// Renders a display into an RGBA8 buffer it keeps between frames.
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    palette: Palette,
+    scale: usize,
+    persistence: Persistence,
+    rgba: Vec<u8>,
+    width: usize,
+    height: usize,
+    // The colour of every display pixel in the last frame, for `Decay`.
+    previous: Vec<[f32; 3]>,
+    // The last set value of every display pixel and how many frames ago it
+    // was set, for `MaxOf`.
+    held: Vec<(u8, usize)>
+}
+
+impl Renderer {
+
+    /// Creates a renderer drawing every display pixel as `scale` x `scale`
+    /// image pixels. A scale of 0 is treated as 1.
+    pub fn new(palette: Palette, scale: usize) -> Self {
+        Renderer{
+            palette,
+            scale: scale.max(1),
+            persistence: Persistence::Off,
+            rgba: Vec::new(),
+            width: 0,
+            height: 0,
+            previous: Vec::new(),
+            held: Vec::new()
+        }
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.max(1);
+    }
+
+    pub fn persistence(&self) -> Persistence {
+        self.persistence
+    }
+
+    /// Sets how long pixels stay visible, see [`Persistence`]. Forgets the
+    /// previous frames.
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.persistence = persistence;
+        self.previous.clear();
+        self.held.clear();
+    }
+
+    /// Width of the last rendered image in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the last rendered image in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The last rendered image, `width * height * 4` bytes row by row.
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    /// Renders the display as the next frame and returns the image.
+    pub fn render(&mut self, display: &Display) -> &[u8] {
+        let scale = self.scale;
+        self.width = display.width() * scale;
+        self.height = display.height() * scale;
+        self.rgba.resize(self.width * self.height * 4, 0);
+        let colors = self.colors(display);
+
+        let row_len = self.width * 4;
+        for (y, row_colors) in colors.chunks(display.width()).enumerate() {
+            let row = &mut self.rgba[y * scale * row_len..][..row_len];
+            for (x, &color) in row_colors.iter().enumerate() {
+                for image_pixel in row[x * scale * 4..][..scale * 4].chunks_exact_mut(4) {
+                    image_pixel.copy_from_slice(&color);
+                }
+            }
+            // The other rows of a scaled pixel repeat the first one.
+            for copy in 1..scale {
+                self.rgba.copy_within(y * scale * row_len..(y * scale + 1) * row_len, (y * scale + copy) * row_len);
+            }
+        }
+        &self.rgba
+    }
+
+    // The colour of every display pixel after applying the persistence.
+    fn colors(&mut self, display: &Display) -> Vec<Rgba> {
+        let pixels = display.pixels();
+        match self.persistence {
+            Persistence::Off => pixels.iter().map(|&pixel| self.palette.color(pixel)).collect(),
+            Persistence::Decay(share) => {
+                let [r, g, b, _] = self.palette.color(0).map(f32::from);
+                // The previous frames do not fit a new resolution.
+                if self.previous.len() != pixels.len() {
+                    self.previous.clear();
+                }
+                self.previous.resize(pixels.len(), [r, g, b]);
+                let palette = self.palette;
+                pixels.iter().zip(&mut self.previous).map(|(&pixel, previous)| {
+                    let color = palette.color(pixel).map(f32::from);
+                    if pixel == 0 {
+                        for channel in 0..3 {
+                            previous[channel] = color[channel] + (previous[channel] - color[channel]) * share;
+                        }
+                    } else {
+                        previous.copy_from_slice(&color[..3]);
+                    }
+                    [previous[0].round() as u8, previous[1].round() as u8, previous[2].round() as u8, 0xff]
+                }).collect()
+            },
+            Persistence::MaxOf(frames) => {
+                if self.held.len() != pixels.len() {
+                    self.held.clear();
+                }
+                self.held.resize(pixels.len(), (0, usize::MAX));
+                let palette = self.palette;
+                pixels.iter().zip(&mut self.held).map(|(&pixel, held)| {
+                    *held = if pixel != 0 { (pixel, 0) } else { (held.0, held.1.saturating_add(1)) };
+                    palette.color(if held.1 < frames { held.0 } else { 0 })
+                }).collect()
+            }
+        }
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new(Palette::default(), 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba = [0xff, 0xff, 0xff, 0xff];
+    const BLACK: Rgba = [0x00, 0x00, 0x00, 0xff];
+
+    #[test]
+    fn render_scales_pixels() {
+        // arrange
+        let mut display = Display::new();
+        display.flip_pixel(1, 0, 1);
+        let mut renderer = Renderer::new(Palette::MONOCHROME, 2);
+
+        // act
+        let rgba = renderer.render(&display).to_vec();
+
+        // assert
+        assert_eq!((renderer.width(), renderer.height()), (128, 64));
+        assert_eq!(rgba.len(), 128 * 64 * 4);
+        let pixel = |x: usize, y: usize| rgba[(y * 128 + x) * 4..][..4].to_vec();
+        assert_eq!(pixel(1, 0), BLACK);
+        assert_eq!(pixel(2, 0), WHITE);
+        assert_eq!(pixel(3, 1), WHITE);
+        assert_eq!(pixel(4, 1), BLACK);
+    }
+
+    #[test]
+    fn render_colours_planes() {
+        // arrange
+        let mut display = Display::new();
+        display.flip_pixel(0, 0, 0b10);
+        display.flip_pixel(1, 0, 0b11);
+        let mut renderer = Renderer::new(Palette::OCTO, 1);
+
+        // act
+        let rgba = renderer.render(&display);
+
+        // assert
+        assert_eq!(rgba[..8], [0xff, 0x66, 0x00, 0xff, 0x66, 0x22, 0x00, 0xff]);
+        assert_eq!(rgba[8..12], Palette::OCTO.colors[0]);
+    }
+
+    #[test]
+    fn decay_fades_cleared_pixels() {
+        // arrange
+        let mut display = Display::new();
+        let mut renderer = Renderer::new(Palette::MONOCHROME, 1);
+        renderer.set_persistence(Persistence::Decay(0.5));
+        display.flip_pixel(0, 0, 1);
+        renderer.render(&display);
+
+        // act
+        display.flip_pixel(0, 0, 1);
+        let first = renderer.render(&display)[..4].to_vec();
+        let second = renderer.render(&display)[..4].to_vec();
+
+        // assert
+        assert_eq!(first, [0x80, 0x80, 0x80, 0xff]);
+        assert_eq!(second, [0x40, 0x40, 0x40, 0xff]);
+    }
+
+    #[test]
+    fn max_of_holds_cleared_pixels() {
+        // arrange
+        let mut display = Display::new();
+        let mut renderer = Renderer::new(Palette::MONOCHROME, 1);
+        renderer.set_persistence(Persistence::MaxOf(2));
+        display.flip_pixel(0, 0, 1);
+        renderer.render(&display);
+
+        // act
+        display.flip_pixel(0, 0, 1);
+        let held = renderer.render(&display)[..4].to_vec();
+        let dropped = renderer.render(&display)[..4].to_vec();
+
+        // assert
+        assert_eq!(held, WHITE);
+        assert_eq!(dropped, BLACK);
+    }
+
+    #[test]
+    fn persistence_from_str() {
+        // arrange
+        let modes = ["off", "decay:0.5", "max:3"];
+
+        // act
+        let parsed: Vec<Persistence> = modes.iter().map(|mode| mode.parse().unwrap()).collect();
+
+        // assert
+        assert_eq!(parsed, [Persistence::Off, Persistence::Decay(0.5), Persistence::MaxOf(3)]);
+        assert_eq!(Persistence::MaxOf(3).to_string(), "max:3");
+        assert!("decay:2".parse::<Persistence>().is_err());
+        assert!("max:0".parse::<Persistence>().is_err());
+    }
+
+    #[test]
+    fn palette_from_str() {
+        // arrange
+        let two = "#000000,#FFFFFF";
+        let four = "#996600,#ffcc00,#ff6600,#662200";
+
+        // act
+        let two: Result<Palette, String> = two.parse();
+        let four: Result<Palette, String> = four.parse();
+
+        // assert
+        assert_eq!(two, Ok(Palette::MONOCHROME));
+        assert_eq!(four, Ok(Palette::OCTO));
+        assert_eq!(Palette::OCTO.to_string(), "#996600,#ffcc00,#ff6600,#662200");
+        assert!("#000000".parse::<Palette>().is_err());
+        assert!("#000000,white".parse::<Palette>().is_err());
+    }
+}
@@ -0,0 +1,334 @@
+//! Time travel: stepping a [`Processor`] backwards by frames or instructions.
+//!
+//! [`Rewind`] runs the processor like [`Processor::run_frame`] and
+//! [`Processor::step`] while recording a save state at the start of every frame
+//! and a [`StepDelta`] for every instruction. The oldest frames are dropped
+//! once the recording exceeds its memory budget.
+//!
+//! Recording a delta clones the processor, which adds up at hundreds of
+//! instructions per frame. Without [`Rewind::set_record_steps`] only the
+//! snapshots are kept and stepping back replays the frame from its start.
+
+use std::collections::VecDeque;
+
+use crate::error::Chip8Error;
+use crate::processor::{Processor, StepDelta, StepOutcome};
+
+/// Default memory budget in bytes, about ten seconds of CHIP-8 frames.
+pub const DEFAULT_BUDGET: usize = 8 * 1024 * 1024;
+
+// A frame's starting state and the instructions executed since, as deltas
+// or, when steps are not recorded, as their number.
+struct FrameRecord {
+    frame: u64,
+    state: Vec<u8>,
+    deltas: Vec<StepDelta>,
+    steps: usize,
+    size: usize
+}
+
+/// Ring buffer of per-frame snapshots and per-instruction deltas.
+pub struct Rewind {
+    budget: usize,
+    records: VecDeque<FrameRecord>,
+    size: usize,
+    record_steps: bool
+}
+
+impl Rewind {
+
+    /// Creates an empty buffer that keeps at most `budget` bytes of history.
+    /// The latest frame is kept even if it alone exceeds the budget.
+    pub fn new(budget: usize) -> Self {
+        Rewind{ budget, records: VecDeque::new(), size: 0, record_steps: true }
+    }
+
+    pub fn records_steps(&self) -> bool {
+        self.record_steps
+    }
+
+    /// Selects whether a delta is recorded for every instruction, which makes
+    /// [`Rewind::step_back`] cheap, or only the snapshot at the start of every
+    /// frame, which makes recording cheap. Without deltas `step_back` replays
+    /// the frame up to the previous instruction, assuming the keys did not
+    /// change during the frame. Drops the recorded history.
+    pub fn set_record_steps(&mut self, record_steps: bool) {
+        self.record_steps = record_steps;
+        self.clear();
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Changes the budget, dropping the oldest frames if necessary.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    /// Approximate number of bytes used by the recorded history.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of frames that can be rewound.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// The earliest frame that can be rewound to, if any.
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.records.front().map(|record| record.frame)
+    }
+
+    /// Drops the recorded history, e.g. after loading a new ROM.
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.size = 0;
+    }
+
+    /// Emulates one frame like [`Processor::run_frame`] and records it.
+    pub fn run_frame(&mut self, processor: &mut Processor, cycles_per_frame: usize) -> Result<(), Chip8Error> {
+        self.start_frame(processor);
+        processor.run_frame_with(cycles_per_frame, |processor| self.step(processor))
+    }
+
+    /// Executes a single instruction like [`Processor::step`] and records it.
+    pub fn step(&mut self, processor: &mut Processor) -> Result<StepOutcome, Chip8Error> {
+        if self.records.back().map(|record| record.frame) != Some(processor.frames()) {
+            self.start_frame(processor);
+        }
+        if !self.record_steps {
+            if let Some(record) = self.records.back_mut() {
+                record.steps += 1;
+            }
+            return processor.step();
+        }
+        let (result, delta) = processor.step_with_delta();
+        let size = delta.size();
+        if let Some(record) = self.records.back_mut() {
+            record.deltas.push(delta);
+            record.size += size;
+            self.size += size;
+        }
+        self.evict();
+        result
+    }
+
+    /// Undoes the latest recorded instruction. Goes back to the start of the
+    /// latest frame instead if no instruction was executed in it.
+    ///
+    /// Returns false if there is no history left.
+    pub fn step_back(&mut self, processor: &mut Processor) -> bool {
+        let record = match self.records.back_mut() {
+            Some(record) => record,
+            None => return false
+        };
+        match record.deltas.pop() {
+            Some(delta) => {
+                let size = delta.size();
+                record.size -= size;
+                self.size -= size;
+                processor.undo_step(delta);
+                // Back at the start of the frame, which the snapshot already holds.
+                if record.deltas.is_empty() {
+                    self.pop_record();
+                }
+            },
+            None if record.steps > 1 => {
+                record.steps -= 1;
+                processor.load_state(&record.state).expect("recorded states are valid");
+                for _ in 0..record.steps {
+                    let _ = processor.step();
+                }
+            },
+            None => self.restore_latest(processor)
+        }
+        true
+    }
+
+    /// Goes back to the start of the latest recorded frame.
+    ///
+    /// Returns false if there is no history left.
+    pub fn rewind_frame(&mut self, processor: &mut Processor) -> bool {
+        if self.records.is_empty() {
+            return false;
+        }
+        self.restore_latest(processor);
+        true
+    }
+
+    /// Goes back to the start of `frame`, dropping the history after it.
+    ///
+    /// Returns false, leaving the processor unchanged, if the frame is not recorded.
+    pub fn rewind_to_frame(&mut self, processor: &mut Processor, frame: u64) -> bool {
+        if !self.records.iter().any(|record| record.frame == frame) {
+            return false;
+        }
+        while self.records.back().is_some_and(|record| record.frame > frame) {
+            self.pop_record();
+        }
+        self.restore_latest(processor);
+        true
+    }
+
+    fn start_frame(&mut self, processor: &Processor) {
+        let state = processor.save_state();
+        let size = state.len();
+        self.records.push_back(FrameRecord{ frame: processor.frames(), state, deltas: Vec::new(), steps: 0, size });
+        self.size += size;
+        self.evict();
+    }
+
+    // Loads the latest frame's snapshot and drops it.
+    fn restore_latest(&mut self, processor: &mut Processor) {
+        if let Some(record) = self.pop_record() {
+            processor.load_state(&record.state).expect("recorded states are valid");
+        }
+    }
+
+    fn pop_record(&mut self) -> Option<FrameRecord> {
+        let record = self.records.pop_back()?;
+        self.size -= record.size;
+        Some(record)
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.budget && self.records.len() > 1 {
+            if let Some(record) = self.records.pop_front() {
+                self.size -= record.size;
+            }
+        }
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Rewind::new(DEFAULT_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    // 6000: V0 = 0, 7001: V0 += 1, A300: I = 0x300, F033: BCD of V0 at I, D015: draw, 1202: loop
+    const COUNTER: [u8; 12] = [0x60, 0x00, 0x70, 0x01, 0xa3, 0x00, 0xf0, 0x33, 0xd0, 0x15, 0x12, 0x02];
+
+    #[test]
+    fn step_back_undoes_instructions() {
+        // arrange
+        let mut processor = Processor::new(&COUNTER, Quirks::default());
+        let mut rewind = Rewind::default();
+        rewind.run_frame(&mut processor, 6).unwrap();
+        let after_first_frame = processor.save_state();
+        rewind.run_frame(&mut processor, 5).unwrap();
+
+        // act
+        for _ in 0..5 {
+            assert!(rewind.step_back(&mut processor));
+        }
+
+        // assert
+        assert_eq!(processor.save_state(), after_first_frame);
+        assert_eq!(rewind.len(), 1);
+    }
+
+    #[test]
+    fn step_back_restores_memory_and_display() {
+        // arrange
+        let mut processor = Processor::new(&COUNTER, Quirks::default());
+        let mut rewind = Rewind::default();
+        for _ in 0..3 {
+            rewind.step(&mut processor).unwrap();
+        }
+        let before = processor.save_state();
+        rewind.step(&mut processor).unwrap();
+        rewind.step(&mut processor).unwrap();
+
+        // act
+        rewind.step_back(&mut processor);
+        let after_draw_undone = processor.framebuffer().iter().all(|&pixel| pixel == 0);
+        rewind.step_back(&mut processor);
+
+        // assert
+        assert!(after_draw_undone);
+        assert_eq!(processor.memory()[0x300..0x303], [0, 0, 0]);
+        assert_eq!(processor.save_state(), before);
+    }
+
+    #[test]
+    fn step_back_without_deltas_replays_the_frame() {
+        // arrange
+        let mut processor = Processor::new(&COUNTER, Quirks::default());
+        let mut rewind = Rewind::default();
+        rewind.set_record_steps(false);
+        rewind.run_frame(&mut processor, 6).unwrap();
+        let frame_size = rewind.size();
+        let after_first_frame = processor.save_state();
+        rewind.run_frame(&mut processor, 3).unwrap();
+        let states: Vec<Vec<u8>> = (0..2).map(|_| {
+            assert!(rewind.step_back(&mut processor));
+            processor.save_state()
+        }).collect();
+
+        // act
+        assert!(rewind.step_back(&mut processor));
+
+        // assert
+        let mut expected = Processor::new(&COUNTER, Quirks::default());
+        expected.load_state(&after_first_frame).unwrap();
+        expected.step().unwrap();
+        assert_eq!(states[1], expected.save_state());
+        expected.step().unwrap();
+        assert_eq!(states[0], expected.save_state());
+        assert_eq!(processor.save_state(), after_first_frame);
+        assert_eq!(rewind.len(), 1);
+        assert_eq!(rewind.size(), frame_size);
+    }
+
+    #[test]
+    fn rewind_to_frame_restores_snapshot() {
+        // arrange
+        let mut processor = Processor::new(&COUNTER, Quirks::default());
+        let mut rewind = Rewind::default();
+        rewind.run_frame(&mut processor, 10).unwrap();
+        let frame_1 = processor.save_state();
+        for _ in 0..5 {
+            rewind.run_frame(&mut processor, 10).unwrap();
+        }
+
+        // act
+        let rewound = rewind.rewind_to_frame(&mut processor, 1);
+
+        // assert
+        assert!(rewound);
+        assert_eq!(processor.save_state(), frame_1);
+        assert_eq!(rewind.len(), 1);
+        assert!(!rewind.rewind_to_frame(&mut processor, 4));
+    }
+
+    #[test]
+    fn budget_drops_oldest_frames() {
+        // arrange
+        let mut processor = Processor::new(&COUNTER, Quirks::default());
+        let frame_size = processor.save_state().len();
+        let mut rewind = Rewind::new(frame_size * 3);
+
+        // act
+        for _ in 0..10 {
+            rewind.run_frame(&mut processor, 1).unwrap();
+        }
+
+        // assert
+        assert!(rewind.size() <= rewind.budget());
+        assert!(rewind.len() < 3);
+        assert_eq!(rewind.oldest_frame(), Some(10 - rewind.len() as u64));
+    }
+}
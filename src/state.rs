@@ -0,0 +1,220 @@
+//! Binary format of the save states written by
+//! [`Processor::save_state`](crate::processor::Processor::save_state).
+//!
+//! A state starts with the magic bytes `C8ST` and a version byte, followed by
+//! the machine state as little-endian integers and length-prefixed byte
+//! arrays. Fields added in later versions are appended at the end, so older
+//! states stay loadable.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::quirks::Quirks;
+
+pub(crate) const MAGIC: &[u8; 4] = b"C8ST";
+/// Version of the save states written by this build.
+pub const STATE_VERSION: u8 = 3;
+
+/// Why a save state could not be loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the save state magic bytes.
+    NotAState,
+    /// The state was written by a newer version of the emulator.
+    UnsupportedVersion(u8),
+    /// The data ends before the state is complete.
+    Truncated,
+    /// A field holds a value the processor cannot be in.
+    Invalid(&'static str)
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(f, "unsupported save state version {}", version),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(field) => write!(f, "save state has an invalid {}", field)
+        }
+    }
+}
+
+impl Error for StateError {}
+
+pub(crate) struct StateWriter {
+    out: Vec<u8>
+}
+
+impl StateWriter {
+
+    pub(crate) fn new() -> Self {
+        let mut out = MAGIC.to_vec();
+        out.push(STATE_VERSION);
+        StateWriter{ out }
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.out.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.out.push(value as u8);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.out.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn quirks(&mut self, quirks: Quirks) {
+        let flags = [
+            quirks.shift_uses_vy,
+            quirks.load_store_increments_i,
+            quirks.jump_uses_vx,
+            quirks.vf_reset,
+            quirks.clip_sprites,
+            quirks.display_wait
+        ];
+        self.u8(flags.iter().rev().fold(0, |bits, &flag| bits << 1 | flag as u8));
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    version: u8
+}
+
+impl<'a> StateReader<'a> {
+
+    /// Checks the header and returns a reader positioned behind it.
+    pub(crate) fn new(data: &'a [u8]) -> Result<Self, StateError> {
+        if data.len() < MAGIC.len() + 1 || &data[..MAGIC.len()] != MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = data[MAGIC.len()];
+        if version == 0 || version > STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        Ok(StateReader{ data: &data[MAGIC.len() + 1..], version })
+    }
+
+    /// The version the state was written with.
+    pub(crate) fn version(&self) -> u8 {
+        self.version
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("flag"))
+        }
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// Reads a length-prefixed array that must hold exactly `N` bytes.
+    pub(crate) fn array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], StateError> {
+        let bytes = self.bytes()?;
+        let mut array = [0; N];
+        if bytes.len() != N {
+            return Err(StateError::Invalid(field));
+        }
+        array.copy_from_slice(bytes);
+        Ok(array)
+    }
+
+    pub(crate) fn quirks(&mut self) -> Result<Quirks, StateError> {
+        let bits = self.u8()?;
+        if bits >> 6 != 0 {
+            return Err(StateError::Invalid("quirks"));
+        }
+        let flag = |n: u8| bits & (1 << n) != 0;
+        Ok(Quirks{
+            shift_uses_vy: flag(0),
+            load_store_increments_i: flag(1),
+            jump_uses_vx: flag(2),
+            vf_reset: flag(3),
+            clip_sprites: flag(4),
+            display_wait: flag(5)
+        })
+    }
+
+    /// Fails unless all data was read.
+    pub(crate) fn finish(self) -> Result<(), StateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::Invalid("length"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quirks_round_trip() {
+        for quirks in [Quirks::default(), Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::SUPER_CHIP, Quirks::XO_CHIP] {
+            // arrange
+            let mut writer = StateWriter::new();
+            writer.quirks(quirks);
+            let state = writer.finish();
+
+            // act
+            let mut reader = StateReader::new(&state).unwrap();
+
+            // assert
+            assert_eq!(reader.quirks(), Ok(quirks));
+        }
+    }
+
+    #[test]
+    fn reader_checks_header() {
+        assert_eq!(StateReader::new(b"C8").err(), Some(StateError::NotAState));
+        assert_eq!(StateReader::new(b"RIFF\x01").err(), Some(StateError::NotAState));
+        assert_eq!(StateReader::new(b"C8ST\x04").err(), Some(StateError::UnsupportedVersion(4)));
+        assert_eq!(StateReader::new(b"C8ST\x01").map(|reader| reader.version()).ok(), Some(1));
+    }
+}
@@ -0,0 +1,161 @@
+//! How much of a 60 Hz frame an instruction takes.
+//!
+//! By default every instruction counts the same and a frame executes the
+//! number of instructions the host asks for. With [`Timing::CosmacVip`] each
+//! instruction is charged the machine cycles the COSMAC VIP interpreter spends
+//! on it instead, so a frame executes as many instructions as fit into the
+//! time the VIP had between two display interrupts. Clearing the screen then
+//! takes most of a frame, and waiting for a key or for the vertical blank
+//! (see [`Quirks::display_wait`](crate::quirks::Quirks::display_wait)) uses up
+//! the rest of it.
+//!
+//! The costs are approximations of the interpreter's routines, including the
+//! variable ones of `DXYN`, `FX33` and `FX55`/`FX65`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::instruction::Instruction;
+
+/// Machine cycles of the VIP's 1.76 MHz 1802 CPU per 60 Hz frame.
+pub const VIP_CYCLES_PER_FRAME: usize = 3668;
+/// Machine cycles per frame taken by the display interrupt and its DMA.
+pub const VIP_INTERRUPT_CYCLES: usize = 1053;
+/// Machine cycles per frame left for the interpreter.
+pub const VIP_INTERPRETER_CYCLES: usize = VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES;
+// Fetching and decoding an instruction.
+const VIP_FETCH_CYCLES: usize = 40;
+// Instructions the VIP interpreter does not have are charged like a jump.
+const VIP_OTHER_CYCLES: usize = 12;
+
+/// Selects how instructions are timed within a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes the same time; a frame executes the requested
+    /// number of instructions.
+    #[default]
+    Instructions,
+    /// Instructions take the machine cycles of the COSMAC VIP interpreter and
+    /// a frame executes as many as fit into [`VIP_INTERPRETER_CYCLES`]. The
+    /// requested number of instructions per frame is ignored.
+    CosmacVip
+}
+
+/// Parses `instructions` or `vip`.
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "instructions" => Ok(Timing::Instructions),
+            "vip" | "cosmacvip" => Ok(Timing::CosmacVip),
+            _ => Err(format!("unknown timing '{}', expected instructions or vip", s))
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Timing::Instructions => write!(f, "instructions"),
+            Timing::CosmacVip => write!(f, "vip")
+        }
+    }
+}
+
+/// The machine cycles the COSMAC VIP takes for `instruction`, given whether
+/// it skipped the next instruction and the registers before it executed.
+pub fn cosmac_vip_cycles(instruction: Instruction, skipped: bool, v: &[u8; 16]) -> usize {
+    use Instruction::*;
+
+    let skip = if skipped { 4 } else { 0 };
+    VIP_FETCH_CYCLES + match instruction {
+        // The interpreter clears the 256 bytes of display memory one by one.
+        Clear => 3078,
+        Return => 10,
+        Jump(_) => 12,
+        Call(_) => 26,
+        SkipIfEqual{ .. } | SkipIfNotEqual{ .. } => 10 + skip,
+        SkipIfRegistersEqual{ .. } | SkipIfRegistersNotEqual{ .. } => 14 + skip,
+        Set{ .. } => 6,
+        Add{ .. } => 10,
+        Move{ .. } => 12,
+        // The arithmetic and logic instructions run from a self-modified routine.
+        Or{ .. } | And{ .. } | Xor{ .. } | AddRegisters{ .. } | Subtract{ .. }
+            | ShiftRight{ .. } | SubtractReversed{ .. } | ShiftLeft{ .. } => 44,
+        SetI(_) => 12,
+        JumpOffset(_) => 22,
+        Random{ .. } => 36,
+        // Sprite rows not aligned to a display byte are shifted bit by bit
+        // and written to two bytes.
+        Draw{ x, n, .. } => {
+            let rows = if n == 0 { 16 } else { n as usize };
+            let offset = (v[x as usize] % 8) as usize;
+            68 + rows * if offset == 0 { 34 } else { 68 + 4 * offset }
+        },
+        SkipIfKey(_) | SkipIfNotKey(_) => 14 + skip,
+        GetDelay(_) | SetDelay(_) | SetSound(_) | WaitKey(_) => 10,
+        AddI(_) | Font(_) => 16,
+        // The digits are found by repeated subtraction.
+        Bcd(x) => {
+            let value = v[x as usize];
+            80 + 16 * (value / 100 + value / 10 % 10 + value % 10) as usize
+        },
+        Store(x) | Load(x) => 14 + 14 * (x as usize + 1),
+        _ => VIP_OTHER_CYCLES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_cost_depends_on_rows_and_alignment() {
+        // arrange
+        let mut v = [0; 16];
+        let draw = Instruction::Draw{ x: 0, y: 1, n: 5 };
+
+        // act
+        let aligned = cosmac_vip_cycles(draw, false, &v);
+        v[0] = 3;
+        let shifted = cosmac_vip_cycles(draw, false, &v);
+
+        // assert
+        assert_eq!(aligned, 40 + 68 + 5 * 34);
+        assert_eq!(shifted, 40 + 68 + 5 * 80);
+    }
+
+    #[test]
+    fn skips_and_loops_cost_more() {
+        // arrange
+        let mut v = [0; 16];
+        v[2] = 255;
+        let skip = Instruction::SkipIfEqual{ x: 0, nn: 0 };
+
+        // act
+        let costs = [
+            cosmac_vip_cycles(skip, false, &v),
+            cosmac_vip_cycles(skip, true, &v),
+            cosmac_vip_cycles(Instruction::Bcd(2), false, &v),
+            cosmac_vip_cycles(Instruction::Store(3), false, &v)
+        ];
+
+        // assert
+        assert_eq!(costs, [50, 54, 40 + 80 + 16 * 12, 40 + 14 + 14 * 4]);
+    }
+
+    #[test]
+    fn timing_from_str() {
+        // arrange
+        let names = ["instructions", "vip", "COSMAC-VIP"];
+
+        // act
+        let timings: Vec<Timing> = names.iter().map(|name| name.parse().unwrap()).collect();
+
+        // assert
+        assert_eq!(timings, [Timing::Instructions, Timing::CosmacVip, Timing::CosmacVip]);
+        assert_eq!(Timing::CosmacVip.to_string(), "vip");
+        assert!("fast".parse::<Timing>().is_err());
+    }
+}
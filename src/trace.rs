@@ -0,0 +1,358 @@
+//! Execution traces: a line per executed instruction.
+//!
+//! A [`Tracer`] runs the processor like [`Processor::step`] and
+//! [`Processor::run_frame`] and hands a [`TraceEntry`] for every executed
+//! instruction that passes its [`TraceFilter`] to a [`TraceSink`]. Entries
+//! format as fixed columns, the address, opcode and mnemonic followed by the
+//! registers and memory the instruction changed, so that traces of two runs or
+//! emulators can be compared with `diff`:
+//!
+//! ```text
+//! 0200 6005 LD V0, 0x05            V0=05
+//! 0202 a300 LD I, 0x300            I=0300
+//! 0204 f033 LD B, V0               [0300]=00 [0301]=00 [0302]=05
+//! 0206 2208 CALL 0x208             SP=01
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::debugger::Register;
+use crate::error::Chip8Error;
+use crate::instruction::{decode, Instruction};
+use crate::processor::{Processor, StepOutcome};
+
+/// Selects the instructions to trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Addresses of the traced instructions.
+    pub addresses: Range<usize>,
+    /// Traced opcode classes, bit `n` for the opcodes starting with the hex
+    /// digit `n`, e.g. `1 << 0xd` for draws.
+    pub classes: u16
+}
+
+impl TraceFilter {
+
+    /// Traces every instruction.
+    pub const ALL: TraceFilter = TraceFilter{ addresses: 0..usize::MAX, classes: 0xffff };
+
+    pub fn matches(&self, pc: usize, opcode: u16) -> bool {
+        self.addresses.contains(&pc) && self.classes & (1 << (opcode >> 12)) != 0
+    }
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        TraceFilter::ALL
+    }
+}
+
+/// One executed instruction and what it changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The frame the instruction ran in, see [`Processor::frames`].
+    pub frame: u64,
+    pub pc: usize,
+    pub opcode: u16,
+    /// `None` for an opcode the platform does not know.
+    pub instruction: Option<Instruction>,
+    /// The new values of the registers the instruction changed, except PC.
+    pub registers: Vec<(Register, usize)>,
+    /// The bytes the instruction wrote, each address once.
+    pub memory: Vec<(usize, u8)>
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = self.instruction.map_or_else(|| "unknown".to_string(), |instruction| instruction.to_string());
+        write!(f, "{:04x} {:04x} ", self.pc, self.opcode)?;
+        if self.registers.is_empty() && self.memory.is_empty() {
+            return write!(f, "{}", mnemonic);
+        }
+        write!(f, "{:<22}", mnemonic)?;
+        for (register, value) in &self.registers {
+            match register {
+                Register::I => write!(f, " {}={:04x}", register, value)?,
+                _ => write!(f, " {}={:02x}", register, value)?
+            }
+        }
+        for (address, value) in &self.memory {
+            write!(f, " [{:04x}]={:02x}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Receives the entries of a [`Tracer`].
+pub trait TraceSink {
+    fn record(&mut self, entry: &TraceEntry);
+}
+
+impl<T: TraceSink + ?Sized> TraceSink for Box<T> {
+    fn record(&mut self, entry: &TraceEntry) {
+        (**self).record(entry);
+    }
+}
+
+/// Keeps the latest entries in memory.
+#[derive(Clone, Debug)]
+pub struct RingBuffer {
+    capacity: usize,
+    entries: VecDeque<TraceEntry>
+}
+
+impl RingBuffer {
+
+    /// Creates a buffer for the latest `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer{ capacity, entries: VecDeque::with_capacity(capacity.min(4096)) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The buffered entries, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> + '_ {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl TraceSink for RingBuffer {
+    fn record(&mut self, entry: &TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry.clone());
+    }
+}
+
+/// Writes each entry as a line, e.g. to a buffered file.
+pub struct WriteSink<W: Write> {
+    writer: W,
+    // The first failed write, later entries are dropped.
+    error: Option<io::Error>
+}
+
+impl<W: Write> WriteSink<W> {
+
+    pub fn new(writer: W) -> Self {
+        WriteSink{ writer, error: None }
+    }
+
+    /// Flushes the writer and returns it, or the first error writing the trace.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> TraceSink for WriteSink<W> {
+    fn record(&mut self, entry: &TraceEntry) {
+        if self.error.is_none() {
+            self.error = writeln!(self.writer, "{}", entry).err();
+        }
+    }
+}
+
+/// Logs each entry to the browser console.
+#[cfg(feature = "wasm")]
+pub struct ConsoleSink;
+
+#[cfg(feature = "wasm")]
+impl TraceSink for ConsoleSink {
+    fn record(&mut self, entry: &TraceEntry) {
+        log!("{}", entry);
+    }
+}
+
+/// Runs a processor and traces the instructions it executes.
+pub struct Tracer<S: TraceSink> {
+    sink: S,
+    filter: TraceFilter,
+    // Whether the last step waited for a key. The step that receives the key
+    // completes `FX0A` and executes the instruction after it.
+    waiting_for_key: bool
+}
+
+impl<S: TraceSink> Tracer<S> {
+
+    pub fn new(sink: S, filter: TraceFilter) -> Self {
+        Tracer{ sink, filter, waiting_for_key: false }
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    pub fn filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: TraceFilter) {
+        self.filter = filter;
+    }
+
+    /// Emulates one frame like [`Processor::run_frame`] while tracing.
+    pub fn run_frame(&mut self, processor: &mut Processor, cycles_per_frame: usize) -> Result<(), Chip8Error> {
+        processor.run_frame_with(cycles_per_frame, |processor| self.step(processor))
+    }
+
+    /// Executes one instruction like [`Processor::step`] and traces it if it
+    /// passes the filter. A failing instruction is traced without changes.
+    pub fn step(&mut self, processor: &mut Processor) -> Result<StepOutcome, Chip8Error> {
+        let pc = processor.pc() + if self.waiting_for_key { 2 } else { 0 };
+        let opcode = match processor.memory().get(pc..pc + 2) {
+            Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]),
+            None => return self.untraced(processor)
+        };
+        if !self.filter.matches(pc, opcode) {
+            return self.untraced(processor);
+        }
+
+        let frame = processor.frames();
+        let before = registers(processor);
+        let (result, delta) = processor.step_with_delta();
+        self.waiting_for_key = result == Ok(StepOutcome::WaitingForKey);
+        if !matches!(result, Ok(StepOutcome::Executed) | Err(_)) {
+            return result;
+        }
+
+        let after = registers(processor);
+        let mut memory: Vec<(usize, u8)> = Vec::new();
+        for address in delta.written() {
+            if !memory.iter().any(|&(written, _)| written == address) {
+                memory.push((address, processor.memory()[address]));
+            }
+        }
+        self.sink.record(&TraceEntry{
+            frame,
+            pc,
+            opcode,
+            instruction: decode(opcode, processor.platform()).ok(),
+            registers: before.iter().zip(after)
+                .filter(|((_, old), (_, new))| old != new)
+                .map(|(_, change)| change)
+                .collect(),
+            memory
+        });
+        result
+    }
+
+    fn untraced(&mut self, processor: &mut Processor) -> Result<StepOutcome, Chip8Error> {
+        let result = processor.step();
+        self.waiting_for_key = result == Ok(StepOutcome::WaitingForKey);
+        result
+    }
+}
+
+// The registers a trace reports, in their trace order.
+fn registers(processor: &Processor) -> Vec<(Register, usize)> {
+    let v = processor.registers().iter().enumerate().map(|(x, &value)| (Register::V(x as u8), value as usize));
+    v.chain([
+        (Register::I, processor.i()),
+        (Register::Sp, processor.sp()),
+        (Register::DelayTimer, processor.delay_timer() as usize),
+        (Register::SoundTimer, processor.sound_timer() as usize)
+    ]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    // 200: 6005 V0 = 5, 202: A300 I = 0x300, 204: F033 BCD, 206: 2208 call, 208: 1208 exit
+    const ROM: [u8; 10] = [0x60, 0x05, 0xa3, 0x00, 0xf0, 0x33, 0x22, 0x08, 0x12, 0x08];
+
+    fn trace(filter: TraceFilter) -> Vec<String> {
+        let mut processor = Processor::new(&ROM, Quirks::default());
+        let mut tracer = Tracer::new(RingBuffer::new(16), filter);
+        tracer.run_frame(&mut processor, 10).unwrap();
+        tracer.sink().entries().map(TraceEntry::to_string).collect()
+    }
+
+    #[test]
+    fn entries_show_changes() {
+        // arrange
+        let filter = TraceFilter::ALL;
+
+        // act
+        let lines = trace(filter);
+
+        // assert
+        assert_eq!(lines, [
+            "0200 6005 LD V0, 0x05            V0=05",
+            "0202 a300 LD I, 0x300            I=0300",
+            "0204 f033 LD B, V0               [0300]=00 [0301]=00 [0302]=05",
+            "0206 2208 CALL 0x208             SP=01",
+            "0208 1208 JP 0x208"
+        ]);
+    }
+
+    #[test]
+    fn filter_by_address_and_class() {
+        // arrange
+        let by_address = TraceFilter{ addresses: 0x202..0x206, ..TraceFilter::ALL };
+        let by_class = TraceFilter{ classes: 1 << 0x6 | 1 << 0x2, ..TraceFilter::ALL };
+
+        // act
+        let addressed = trace(by_address);
+        let classified = trace(by_class);
+
+        // assert
+        assert_eq!(addressed.len(), 2);
+        assert!(addressed[0].starts_with("0202 a300"));
+        assert_eq!(classified.len(), 2);
+        assert!(classified[1].starts_with("0206 2208"));
+    }
+
+    #[test]
+    fn ring_buffer_keeps_latest() {
+        // arrange
+        let mut processor = Processor::new(&ROM, Quirks::default());
+        let mut tracer = Tracer::new(RingBuffer::new(2), TraceFilter::ALL);
+
+        // act
+        tracer.run_frame(&mut processor, 10).unwrap();
+
+        // assert
+        let pcs: Vec<usize> = tracer.sink().entries().map(|entry| entry.pc).collect();
+        assert_eq!(pcs, [0x206, 0x208]);
+    }
+
+    #[test]
+    fn write_sink_writes_lines() {
+        // arrange
+        let mut processor = Processor::new(&ROM[..2], Quirks::default());
+        let mut tracer = Tracer::new(WriteSink::new(Vec::new()), TraceFilter::ALL);
+
+        // act
+        tracer.step(&mut processor).unwrap();
+        let written = tracer.into_sink().finish().unwrap();
+
+        // assert
+        assert_eq!(String::from_utf8(written).unwrap(), "0200 6005 LD V0, 0x05            V0=05\n");
+    }
+}
@@ -1,3 +1,19 @@
+// Logs to the browser console when built for the web, and is a no-op otherwise.
+#[cfg(feature = "wasm")]
+macro_rules! log {
+    ( $( $t:tt )* ) => {
+        web_sys::console::log_1(&format!( $( $t )* ).into());
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+macro_rules! log {
+    ( $( $t:tt )* ) => {
+        let _ = format_args!( $( $t )* );
+    }
+}
+
+#[cfg(feature = "wasm")]
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -8,4 +24,3 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
-
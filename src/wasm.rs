@@ -0,0 +1,356 @@
+use wasm_bindgen::prelude::*;
+use crate::audio::SquareWave;
+use crate::debugger::{Debugger, StopReason};
+use crate::platform::Platform;
+use crate::processor;
+use crate::quirks::Quirks;
+use crate::render::{Palette, Persistence, Renderer};
+use crate::rewind::Rewind;
+use crate::timing::Timing;
+use crate::trace::{ConsoleSink, RingBuffer, TraceEntry, TraceFilter, TraceSink, Tracer};
+use crate::utils::set_panic_hook;
+
+// When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
+// allocator.
+#[cfg(feature = "wee_alloc")]
+#[global_allocator]
+static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
+
+// Instructions per frame until the first `run_frame` sets it.
+const DEFAULT_CYCLES_PER_FRAME: usize = 10;
+
+/// JavaScript facing wrapper around [`processor::Processor`].
+#[wasm_bindgen]
+pub struct Processor {
+    debugger: Debugger,
+    wave: SquareWave,
+    rewind: Rewind,
+    trace: Option<Tracer<WebTrace>>,
+    renderer: Renderer
+}
+
+// Keeps the trace for `trace_log` and optionally logs it to the console.
+struct WebTrace {
+    buffer: RingBuffer,
+    console: bool
+}
+
+impl TraceSink for WebTrace {
+    fn record(&mut self, entry: &TraceEntry) {
+        self.buffer.record(entry);
+        if self.console {
+            ConsoleSink.record(entry);
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Processor {
+
+    pub fn new(rom: Vec<u8>) -> Self {
+        Processor::wrap(processor::Processor::new(&rom, Quirks::default()))
+    }
+
+    /// Creates a processor for the named platform (`chip8`, `schip` or `xochip`)
+    /// with the platform's default quirks.
+    pub fn with_platform(rom: Vec<u8>, platform: &str) -> Result<Processor, JsValue> {
+        let platform: Platform = platform.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        Ok(Processor::wrap(processor::Processor::with_platform(&rom, platform, platform.default_quirks())))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn halt(&self) -> bool {
+        self.debugger.processor().is_halted()
+    }
+
+    /// Why the processor halted, e.g. `unknown opcode 0x00ff at 0x200`.
+    /// Undefined if it is running or halted normally.
+    #[wasm_bindgen(getter)]
+    pub fn halt_reason(&self) -> Option<String> {
+        self.debugger.processor().error().map(|error| error.to_string())
+    }
+
+    /// Presses a key of the hex keypad, 0 to 15. Other keys are ignored.
+    pub fn key_pressed(&mut self, key: usize) {
+        self.debugger.processor_mut().key_pressed(key);
+    }
+
+    pub fn key_released(&mut self, key: usize) {
+        self.debugger.processor_mut().key_released(key);
+    }
+
+    /// Pointer to the display, one byte per pixel, `screen_width * screen_height` bytes.
+    /// Each pixel holds the bit-planes it is set in, 0 to 3.
+    pub fn screen(&self) -> *const u8 {
+        self.debugger.processor().framebuffer().as_ptr()
+    }
+
+    pub fn screen_width(&self) -> usize {
+        self.debugger.processor().screen_width()
+    }
+
+    pub fn screen_height(&self) -> usize {
+        self.debugger.processor().screen_height()
+    }
+
+    /// Returns whether the display changed since `take_dirty_rects` was last called.
+    pub fn frame_changed(&self) -> bool {
+        self.debugger.processor().display().frame_changed()
+    }
+
+    /// The display rectangles changed since the last call, four numbers per
+    /// rectangle: x, y, width and height in display pixels.
+    pub fn take_dirty_rects(&mut self) -> Vec<u32> {
+        self.debugger.processor_mut().display_mut().take_dirty_rects().iter()
+            .flat_map(|rect| [rect.x, rect.y, rect.width, rect.height])
+            .map(|n| n as u32)
+            .collect()
+    }
+
+    /// Renders the display as RGBA8 with the palette and scale and returns a
+    /// pointer to the image, `image_width * image_height * 4` bytes in the
+    /// layout of `ImageData`. Valid until the next call.
+    pub fn render(&mut self) -> *const u8 {
+        self.renderer.render(self.debugger.processor().display()).as_ptr()
+    }
+
+    pub fn image_width(&self) -> usize {
+        self.renderer.width()
+    }
+
+    pub fn image_height(&self) -> usize {
+        self.renderer.height()
+    }
+
+    /// Sets the colours from two or four comma separated `#rrggbb` colours:
+    /// unset pixels, plane 1, plane 2 and both planes.
+    pub fn set_palette(&mut self, palette: &str) -> Result<(), JsValue> {
+        let palette: Palette = palette.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        self.renderer.set_palette(palette);
+        Ok(())
+    }
+
+    /// Sets how many image pixels wide and high a display pixel is rendered.
+    pub fn set_scale(&mut self, scale: usize) {
+        self.renderer.set_scale(scale);
+    }
+
+    /// Sets how long cleared pixels stay visible to reduce flicker: `off`,
+    /// `decay:<share>` or `max:<frames>`. Assumes `render` is called once per frame.
+    pub fn set_persistence(&mut self, persistence: &str) -> Result<(), JsValue> {
+        let persistence: Persistence = persistence.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        self.renderer.set_persistence(persistence);
+        Ok(())
+    }
+
+    /// Emulates one 60 Hz frame, see [`processor::Processor::run_frame`], and
+    /// records it for rewinding. A failing instruction halts the processor,
+    /// see `halt_reason`.
+    ///
+    /// While breakpoints, watchpoints or conditions are set, or after stopping
+    /// in the middle of a frame, the frame runs in the debugger instead and the
+    /// rewind history is cleared. The same goes for tracing, see `start_trace`,
+    /// which does not trace frames run in the debugger. Returns why it stopped early, e.g. `breakpoint at 0x20a`.
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> Option<String> {
+        self.debugger.set_cycles_per_frame(cycles_per_frame);
+        if self.debugger.has_stops() || self.debugger.is_mid_frame() {
+            self.rewind.clear();
+            return self.debugger.run_frame().map(|reason| reason.to_string());
+        }
+        if let Some(tracer) = self.trace.as_mut() {
+            self.rewind.clear();
+            let _ = tracer.run_frame(self.debugger.processor_mut(), cycles_per_frame);
+            return None;
+        }
+        let _ = self.rewind.run_frame(self.debugger.processor_mut(), cycles_per_frame);
+        None
+    }
+
+    /// Selects how instructions are timed: `instructions` runs the requested
+    /// number per frame, `vip` as many as the COSMAC VIP would.
+    pub fn set_timing(&mut self, timing: &str) -> Result<(), JsValue> {
+        let timing: Timing = timing.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        self.debugger.processor_mut().set_timing(timing);
+        Ok(())
+    }
+
+    /// Goes back to the start of the previous frame. Returns false once the
+    /// recorded history is used up.
+    pub fn rewind_frame(&mut self) -> bool {
+        self.rewind.rewind_frame(self.debugger.processor_mut())
+    }
+
+    /// Undoes the previous instruction by replaying the frame up to it.
+    /// Returns false once the recorded history is used up.
+    pub fn step_back(&mut self) -> bool {
+        self.rewind.step_back(self.debugger.processor_mut())
+    }
+
+    /// Serializes the complete machine state, e.g. to keep it in localStorage.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.debugger.processor().save_state()
+    }
+
+    /// Restores a state written by `save_state`. Throws if the state cannot be
+    /// loaded, leaving the processor unchanged.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
+        self.debugger.processor_mut().load_state(state).map_err(|error| JsValue::from_str(&error.to_string()))?;
+        self.debugger.reset_frame();
+        self.rewind.clear();
+        Ok(())
+    }
+
+    pub fn is_sound_active(&self) -> bool {
+        self.debugger.processor().is_sound_active()
+    }
+
+    /// Fills `out` with buzzer samples at the given sample rate, e.g. for a WebAudio buffer.
+    pub fn fill_audio(&mut self, sample_rate: u32, out: &mut [f32]) {
+        self.wave.set_sample_rate(sample_rate);
+        self.wave.fill_sound(&self.debugger.processor().sound_state(), out);
+    }
+
+    /// Stops before executing the instruction at `pc`.
+    pub fn add_breakpoint(&mut self, pc: usize) -> bool {
+        self.debugger.add_breakpoint(pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.debugger.remove_breakpoint(pc)
+    }
+
+    /// Stops before an instruction accesses memory from `start` up to `end`,
+    /// exclusive. `access` is `read`, `write` or `readwrite`.
+    pub fn add_watchpoint(&mut self, start: usize, end: usize, access: &str) -> Result<(), JsValue> {
+        let access = access.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        self.debugger.add_watchpoint(start..end, access);
+        Ok(())
+    }
+
+    pub fn remove_watchpoint(&mut self, start: usize, end: usize) -> bool {
+        self.debugger.remove_watchpoint(start..end)
+    }
+
+    /// Stops after an instruction makes a condition like `V3 == 0x10` true.
+    pub fn add_condition(&mut self, condition: &str) -> Result<(), JsValue> {
+        let condition = condition.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        self.debugger.add_condition(condition);
+        Ok(())
+    }
+
+    pub fn remove_condition(&mut self, condition: &str) -> Result<bool, JsValue> {
+        let condition = condition.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        Ok(self.debugger.remove_condition(&condition))
+    }
+
+    /// Removes all breakpoints, watchpoints and conditions.
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.clear();
+    }
+
+    /// Executes one instruction. Returns why it stopped, `step` if nothing
+    /// else happened.
+    pub fn step_into(&mut self) -> String {
+        self.stepped(|debugger| debugger.step_into())
+    }
+
+    /// Executes one instruction, or a whole subroutine if it is a call.
+    pub fn step_over(&mut self) -> String {
+        self.stepped(|debugger| debugger.step_over())
+    }
+
+    /// Runs until the current subroutine returns.
+    pub fn step_out(&mut self) -> String {
+        self.stepped(|debugger| debugger.step_out())
+    }
+
+    /// Runs until the given frame count, see `frames`.
+    pub fn run_until_frame(&mut self, frame: u32) -> String {
+        self.stepped(|debugger| debugger.run_until_frame(frame as u64))
+    }
+
+    /// The 60 Hz frames emulated so far.
+    #[wasm_bindgen(getter)]
+    pub fn frames(&self) -> u32 {
+        self.debugger.processor().frames() as u32
+    }
+
+    /// The registers V0 to VF.
+    pub fn registers(&self) -> Vec<u8> {
+        self.debugger.processor().registers().to_vec()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pc(&self) -> usize {
+        self.debugger.processor().pc()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn i(&self) -> usize {
+        self.debugger.processor().i()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sp(&self) -> usize {
+        self.debugger.processor().sp()
+    }
+
+    /// The instruction at the program counter in Cowgod syntax.
+    pub fn current_instruction(&self) -> Option<String> {
+        self.debugger.processor().current_instruction().ok().map(|instruction| instruction.to_string())
+    }
+
+    /// Traces every executed instruction into a buffer of the latest `capacity`
+    /// lines, see `trace_log`, and to the browser console if `console` is set.
+    pub fn start_trace(&mut self, capacity: usize, console: bool) {
+        let sink = WebTrace{ buffer: RingBuffer::new(capacity), console };
+        self.trace = Some(Tracer::new(sink, TraceFilter::ALL));
+    }
+
+    /// Traces only instructions from `start` up to `end`, exclusive, whose
+    /// opcodes start with a hex digit `n` with bit `n` set in `classes`.
+    pub fn set_trace_filter(&mut self, start: usize, end: usize, classes: u16) {
+        if let Some(tracer) = self.trace.as_mut() {
+            tracer.set_filter(TraceFilter{ addresses: start..end, classes });
+        }
+    }
+
+    pub fn stop_trace(&mut self) {
+        self.trace = None;
+    }
+
+    /// The buffered trace, a line per instruction, oldest first.
+    pub fn trace_log(&self) -> String {
+        self.trace.as_ref().map_or_else(String::new, |tracer| {
+            tracer.sink().buffer.entries().map(|entry| format!("{}\n", entry)).collect()
+        })
+    }
+}
+
+// Only frame snapshots are recorded, as a delta per instruction would copy
+// the processor on every step.
+fn frame_rewind() -> Rewind {
+    let mut rewind = Rewind::default();
+    rewind.set_record_steps(false);
+    rewind
+}
+
+impl Processor {
+
+    fn wrap(processor: processor::Processor) -> Self {
+        set_panic_hook();
+        Processor{
+            debugger: Debugger::new(processor, DEFAULT_CYCLES_PER_FRAME),
+            wave: SquareWave::default(),
+            rewind: frame_rewind(),
+            trace: None,
+            renderer: Renderer::new(Palette::WEB, 1)
+        }
+    }
+
+    // Stepping is not recorded, so rewinding starts over from here.
+    fn stepped(&mut self, step: impl FnOnce(&mut Debugger) -> StopReason) -> String {
+        self.rewind.clear();
+        step(&mut self.debugger).to_string()
+    }
+}
@@ -0,0 +1,47 @@
+//! Assembler tests against whole ROMs in `tests/roms`.
+
+use std::{env, fs};
+
+use chip_8_wasm::asm;
+use chip_8_wasm::disasm::{self, Syntax};
+use chip_8_wasm::platform::Platform;
+
+fn rom_path(name: &str) -> String {
+    format!("{}/tests/roms/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn br8kout_listing_assembles_byte_for_byte() {
+    let rom = fs::read(rom_path("br8kout.ch8")).unwrap();
+
+    let assembled = asm::assemble_file(rom_path("br8kout.asm")).unwrap();
+
+    assert_eq!(assembled, rom);
+}
+
+#[test]
+fn br8kout_disassembly_round_trips() {
+    let rom = fs::read(rom_path("br8kout.ch8")).unwrap();
+    let listing = disasm::disassemble(&rom, Platform::Chip8).listing(Syntax::Cowgod);
+
+    let assembled = asm::assemble(&listing).unwrap();
+
+    assert_eq!(assembled, rom);
+}
+
+#[test]
+fn include_is_relative_to_including_file() {
+    let directory = env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+    fs::create_dir_all(directory.join("lib")).unwrap();
+    fs::write(directory.join("main.asm"), "CALL draw\nJP 0x202\ninclude \"lib/draw.asm\"\n").unwrap();
+    fs::write(directory.join("lib/draw.asm"), "draw: DRW V0, V1, 5\nRET\nFOO\n").unwrap();
+
+    let error = asm::assemble_file(directory.join("main.asm")).unwrap_err();
+    fs::write(directory.join("lib/draw.asm"), "draw: DRW V0, V1, 5\nRET\n").unwrap();
+    let rom = asm::assemble_file(directory.join("main.asm")).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(rom, [0x22, 0x04, 0x12, 0x02, 0xd0, 0x15, 0x00, 0xee]);
+    assert!(error.file.unwrap().ends_with("draw.asm"));
+    assert_eq!((error.line, error.column), (3, 1));
+}
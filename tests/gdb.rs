@@ -0,0 +1,121 @@
+//! Scripted GDB remote serial protocol sessions against `gdb::GdbStub`.
+#![cfg(feature = "gdb")]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use chip_8_wasm::debugger::Debugger;
+use chip_8_wasm::gdb::GdbStub;
+use chip_8_wasm::{Processor, Quirks};
+
+// 200: 6005 V0 = 5, 202: 2208 call, 204: 7001 V0 += 1, 206: 1206 exit,
+// 208: A300 I = 0x300, 20A: F033 BCD, 20C: 00EE
+const CALL: [u8; 14] = [0x60, 0x05, 0x22, 0x08, 0x70, 0x01, 0x12, 0x06, 0xa3, 0x00, 0xf0, 0x33, 0x00, 0xee];
+
+struct Client {
+    stream: TcpStream
+}
+
+impl Client {
+
+    // Starts a stub for `rom` and connects to it. The server thread returns
+    // the registers at the end of the session.
+    fn connect(rom: &[u8]) -> (Client, JoinHandle<[u8; 16]>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let rom = rom.to_vec();
+        let server = thread::spawn(move || {
+            let mut stub = GdbStub::new(Debugger::new(Processor::new(&rom, Quirks::default()), 10));
+            let (stream, _) = listener.accept().unwrap();
+            stub.serve(stream).unwrap();
+            *stub.debugger().processor().registers()
+        });
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        (Client{ stream }, server)
+    }
+
+    fn send(&mut self, packet: &str) {
+        let sum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", packet, sum).unwrap();
+        assert_eq!(self.read_byte(), b'+', "no acknowledgement for {}", packet);
+    }
+
+    fn receive(&mut self) -> String {
+        while self.read_byte() != b'$' {}
+        let mut packet = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => packet.push(byte)
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let sum = packet.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        assert_eq!(String::from_utf8_lossy(&checksum), format!("{:02x}", sum));
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(packet).unwrap()
+    }
+
+    fn request(&mut self, packet: &str) -> String {
+        self.send(packet);
+        self.receive()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+#[test]
+fn session_reads_registers_and_memory() {
+    let (mut client, server) = Client::connect(&CALL);
+
+    assert!(client.request("qSupported:swbreak+;xmlRegisters=i386").contains("qXfer:features:read+"));
+    assert!(client.request("qXfer:features:read:target.xml:0,ffff").starts_with("l<?xml"));
+    assert_eq!(client.request("?"), "S05");
+    assert_eq!(client.request("s"), "S05");
+    // V0 = 5, I = 0, PC = 0x202, SP, DT and ST = 0
+    assert_eq!(client.request("g"), format!("05{}00000202000000", "00".repeat(15)));
+    assert_eq!(client.request("p11"), "0202");
+    assert_eq!(client.request("m200,4"), "60052208");
+    assert_eq!(client.request("mfffe,4"), "E01");
+    assert_eq!(client.request("D"), "OK");
+
+    server.join().unwrap();
+}
+
+#[test]
+fn session_stops_at_breakpoints_and_watchpoints() {
+    let (mut client, server) = Client::connect(&CALL);
+
+    assert_eq!(client.request("Z0,20c,2"), "OK");
+    assert_eq!(client.request("Z2,302,1"), "OK");
+    assert_eq!(client.request("c"), "T05watch:302;");
+    assert_eq!(client.request("p11"), "0a02");
+    assert_eq!(client.request("c"), "T05swbreak:;");
+    assert_eq!(client.request("m300,3"), "000005");
+    assert_eq!(client.request("z0,20c,2"), "OK");
+    assert_eq!(client.request("c"), "W00");
+    client.send("k");
+
+    let registers = server.join().unwrap();
+    assert_eq!(registers[0], 6);
+}
+
+#[test]
+fn session_interrupts_running_target() {
+    // 200: 7001 V0 += 1, 202: 1200 loop
+    let (mut client, server) = Client::connect(&[0x70, 0x01, 0x12, 0x00]);
+
+    assert_eq!(client.request("QStartNoAckMode"), "OK");
+    write!(client.stream, "$c#63").unwrap();
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(client.receive(), "S02");
+    write!(client.stream, "$k#6b").unwrap();
+
+    server.join().unwrap();
+}
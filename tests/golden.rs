@@ -0,0 +1,36 @@
+//! Golden tests: run ROMs headlessly and compare the final screen against
+//! the snapshots in `tests/snapshots`. Set `UPDATE_SNAPSHOTS=1` to rewrite them.
+
+use std::{env, fs};
+
+use chip_8_wasm::headless::{self, ScreenFormat, ScriptedKey};
+use chip_8_wasm::processor::Processor;
+use chip_8_wasm::quirks::Quirks;
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = format!("{}/tests/snapshots/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {}", path, e));
+    assert!(expected == actual, "screen differs from {}:\n{}", path, actual);
+}
+
+#[test]
+fn draw_pressed_key() {
+    // F30A: wait for key into V3, F329: I = font(V3), 6000: V0 = 0,
+    // D005: draw at (V0, V0), 1208: halt
+    let rom = [0xf3, 0x0a, 0xf3, 0x29, 0x60, 0x00, 0xd0, 0x05, 0x12, 0x08];
+    let script = [
+        ScriptedKey{ frame: 2, key: 0xa, pressed: true },
+        ScriptedKey{ frame: 3, key: 0xa, pressed: false }
+    ];
+
+    let mut processor = Processor::new(&rom, Quirks::default());
+    headless::run(&mut processor, 5, 10, &script).unwrap();
+
+    assert!(processor.is_halted());
+    assert_snapshot("draw_pressed_key", &headless::dump_screen(&processor, ScreenFormat::Ascii));
+}
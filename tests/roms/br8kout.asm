@@ -0,0 +1,115 @@
+; Br8kout as bundled with the web frontend, listed by `chip8 disasm`.
+
+    JP L29F                  ; 200: 129f
+    db 0xfc, 0xfc, 0x80      ; 202: fcfc80
+L205:
+    LD I, 0x202              ; 205: a202
+    DRW VD, VC, 1            ; 207: ddc1
+    RET                      ; 209: 00ee
+L20B:
+    LD I, 0x204              ; 20b: a204
+    DRW VB, VA, 1            ; 20d: dba1
+    RET                      ; 20f: 00ee
+L211:
+    LD I, 0x203              ; 211: a203
+    LD V0, 0x02              ; 213: 6002
+L215:
+    LD V1, 0x05              ; 215: 6105
+L217:
+    LD V7, V0                ; 217: 8700
+    LD V6, V1                ; 219: 8610
+    DRW V6, V7, 1            ; 21b: d671
+    ADD V1, 0x08             ; 21d: 7108
+    LD VF, 0x38              ; 21f: 6f38
+    SUBN VF, V1              ; 221: 8f17
+    SNE VF, 0x00             ; 223: 4f00
+    JP L217                  ; 225: 1217
+    ADD V0, 0x02             ; 227: 7002
+    LD VF, 0x10              ; 229: 6f10
+    SUBN VF, V0              ; 22b: 8f07
+    SNE VF, 0x00             ; 22d: 4f00
+    JP L215                  ; 22f: 1215
+    RET                      ; 231: 00ee
+L233:
+    CALL L205                ; 233: 2205
+    ADD VD, 0x04             ; 235: 7d04
+    CALL L205                ; 237: 2205
+    RET                      ; 239: 00ee
+L23B:
+    CALL L205                ; 23b: 2205
+    ADD VD, 0xfc             ; 23d: 7dfc
+    CALL L205                ; 23f: 2205
+    RET                      ; 241: 00ee
+L243:
+    LD V0, V8                ; 243: 8080
+    SNE V0, 0x01             ; 245: 4001
+    LD V8, 0xff              ; 247: 68ff
+    SNE V0, 0xff             ; 249: 40ff
+    LD V8, 0x01              ; 24b: 6801
+    SE VA, VC                ; 24d: 5ac0
+    CALL L253                ; 24f: 2253
+    RET                      ; 251: 00ee
+L253:
+    LD V0, VB                ; 253: 80b0
+    ADD V0, 0xfb             ; 255: 70fb
+    LD V1, 0xf8              ; 257: 61f8
+    AND V0, V1               ; 259: 8012
+    ADD V0, 0x05             ; 25b: 7005
+    LD I, 0x203              ; 25d: a203
+    DRW V0, VA, 1            ; 25f: d0a1
+    RET                      ; 261: 00ee
+L263:
+    CALL L20B                ; 263: 220b
+    ADD VB, V9               ; 265: 8b94
+    ADD VA, V8               ; 267: 8a84
+    CALL L20B                ; 269: 220b
+    SNE VB, 0x00             ; 26b: 4b00
+    LD V9, 0x01              ; 26d: 6901
+    SNE VB, 0x3f             ; 26f: 4b3f
+    LD V9, 0xff              ; 271: 69ff
+    SNE VA, 0x00             ; 273: 4a00
+    LD V8, 0x01              ; 275: 6801
+    SNE VA, 0x1f             ; 277: 4a1f
+    LD V8, 0xff              ; 279: 68ff
+    SNE VF, 0x01             ; 27b: 4f01
+    CALL L243                ; 27d: 2243
+    SNE VA, 0x1f             ; 27f: 4a1f
+    CALL L285                ; 281: 2285
+    RET                      ; 283: 00ee
+L285:
+    CLS                      ; 285: 00e0
+    LD VB, 0x1e              ; 287: 6b1e
+    LD VA, 0x14              ; 289: 6a14
+    CALL L205                ; 28b: 2205
+    CALL L20B                ; 28d: 220b
+    CALL L211                ; 28f: 2211
+    RET                      ; 291: 00ee
+L293:
+    LD VE, DT                ; 293: fe07
+    SE VE, 0x00              ; 295: 3e00
+    JP L293                  ; 297: 1293
+    LD VE, 0x04              ; 299: 6e04
+    LD DT, VE                ; 29b: fe15
+    RET                      ; 29d: 00ee
+L29F:
+    LD VD, 0x1e              ; 29f: 6d1e
+    LD VC, 0x1e              ; 2a1: 6c1e
+    LD VB, 0x40              ; 2a3: 6b40
+    LD VA, 0x1d              ; 2a5: 6a1d
+    RND V9, 0x01             ; 2a7: c901
+    SNE V9, 0x00             ; 2a9: 4900
+    LD V9, 0xff              ; 2ab: 69ff
+    LD V8, 0xff              ; 2ad: 68ff
+    CALL L205                ; 2af: 2205
+    CALL L20B                ; 2b1: 220b
+    CALL L211                ; 2b3: 2211
+L2B5:
+    LD V0, 0x07              ; 2b5: 6007
+    SKNP V0                  ; 2b7: e0a1
+    CALL L23B                ; 2b9: 223b
+    LD V0, 0x09              ; 2bb: 6009
+    SKNP V0                  ; 2bd: e0a1
+    CALL L233                ; 2bf: 2233
+    CALL L263                ; 2c1: 2263
+    CALL L293                ; 2c3: 2293
+    JP L2B5                  ; 2c5: 12b5
@@ -1,9 +1,7 @@
 import { Processor } from "chip-8-wasm";
 import { memory } from "chip-8-wasm/chip_8_wasm_bg";
 
-const PIXEL_SIZE = 15;
-const WHITE_COLOR = "#2a9fd6";
-const BLACK_COLOR = "#0b2633";
+const PIXEL_SIZE = 16;
 const roms = {
   breakout: "Ep/8/ICiAt3BAO6iBNuhAO6iA2ACYQWHAIYQ1nFxCG84jxdPABIXcAJvEI8HTwASFQDuIgV9BCIFAO4iBX38IgUA7oCAQAFo/0D/aAFawCJTAO6AsHD7YfiAEnAFogPQoQDuIguLlIqEIgtLAGkBSz9p/0oAaAFKH2j/TwEiQ0ofIoUA7gDgax5qFCIFIgsiEQDu/gc+ABKTbgT+FQDubR5sHmtAah3JAUkAaf9o/yIFIgsiEWAH4KEiO2AJ4KEiMyJjIpMStQ==",
   snake: "FtaAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAYJCQ8JDgkOCQ4GCAgIBg4JCQkODggOCA4OCA4ICAcICwkHCQkPCQkOBAQEDgMBAQkGCQoMCgkICAgIDgiNioiIiQ0LCQkGCQkJBg4JCQ4IBgkJCwYOCQkOCQcIBgEODgQEBAQJCQkJBgkJCgoECIqKioUJCQYJCQkJBwEGDgIECA4ABwkJBwgOCQkOAAYICAYBBwkJBwAGCwwGAgQOBAQHCQ8BBggOCQkJBAAEBAQEAAQECAgJCg4JBAQEBAQADwqKioAOCQkJAAYJCQYOCQ4ICAcJDwEBAAoMCAgABwwDDgQOBAQCAAkJCQcACQkKBAAKioUFAAoEBAoACQcBBgAPAgQPAAAAAAgICAgACA4BBgAEAAAOAAAACAAIAAUPhQ+FBAgICAQIBAQECAAKBAoAAAQOBAAAAAAAAADz5y+BwEAgJBfvwAQEF4fG5jYXAwMDgAwMDh4WNjZsdHRo4CQcHh8TE5GemJCAgIDA4MjLiw8NjOz+YAP0dCQGJ+ZGBxPzYADyseLCw+FgASAy0oPiwtGistBhomHj4oLx4rNRIcKCseOBEeHCgrHTgAAKW88VWBUIJgYwAAAPMe8GWFAKQEbgUwACXo0SVxBUUWcQFFJnEBRStx/0Utcf9zAVNAFbwA7v4ecP8wABXoAO55AaIE+R75HoCggbDxVQDugpCC1aIE8h7yHvFlAO59Acc/yB8A7mEAYgBjAEAAFjpw/3EBMQoWNmEAcgEyChY2YgBzATAAFiLxKWo32rXyKWox2rXzKWor2rUA7gDgffvwhYrQiwBKABZqSwAWZnr/e/8WWIDQ8HWMAGClYZlkCmUIZgMlsmClYaNkBmUFZg4lsoDQaw4mGGClYalkB2UFZhYlsoDAaxYmGP8KAOBgAGEAYgBjAGQAZQBmAGcAb0CiBPdVf/8/ABa0FtZBAdeBJhAXmGwEF1hsAxdYbAIXWGwBF1hsAG0EaiBrEGkAJhBgBGEGYgxjBKU/0wxx/3MI8h4xABbsYKVhh2QKZQRmEiWyYKVhkWQIZRxmGCWy/wpPBWwETwdsA08IbAJPCWwBYAAA4EACFzAmAqIC0BFvBUwCFzrvoRbGbwdMARdE76EWym8ITAQXTu+hFs5vCUwDF1jvoRbSTAF6AUwCewFMA3r/TAR7/0pAagBLIGsASv9qP0v/ax8l8mEAbwCiAtqxTwFhAWAAmnBwAZuAcAFAAha+QQEmTqIC14EXJg==",
@@ -15,6 +13,11 @@ let loadedRom = null;
 let processor = null;
 let speed = 15;
 let paused = false;
+let rewinding = false;
+let audioContext = null;
+let audioTime = 0;
+// Set when the canvas no longer shows the display, e.g. after fading.
+let redrawAll = true;
 
 const canvas = document.getElementById("chip-8-canvas");
 canvas.height = 32 * PIXEL_SIZE;
@@ -74,6 +77,59 @@ loadRom.onchange = function(event) {
   insertRom(Uint8Array.from(atob(roms[event.target.value]), c => c.charCodeAt(0)));
 }
 
+// Save states are kept in localStorage as base64, one entry per slot.
+const stateSlot = document.getElementById("stateSlot");
+const stateKey = () => `chip-8-state-${stateSlot.value}`;
+
+const saveState = document.getElementById("saveState");
+saveState.onclick = function() {
+  if (processor != null) {
+    let binary = "";
+    for (const byte of processor.save_state()) {
+      binary += String.fromCharCode(byte);
+    }
+    localStorage.setItem(stateKey(), btoa(binary));
+  }
+  return false;
+}
+
+const loadState = document.getElementById("loadState");
+loadState.onclick = function() {
+  const saved = localStorage.getItem(stateKey());
+  if (processor != null && saved != null) {
+    try {
+      processor.load_state(Uint8Array.from(atob(saved), c => c.charCodeAt(0)));
+    } catch (error) {
+      console.error(`Could not load state: ${error}`);
+    }
+  }
+  return false;
+}
+
+const selectPlatform = document.getElementById("selectPlatform");
+selectPlatform.onchange = function() {
+  if (loadedRom != null) {
+    startProcessor();
+  }
+}
+
+// With COSMAC VIP timing the processor speed slider has no effect.
+const selectTiming = document.getElementById("selectTiming");
+selectTiming.onchange = function() {
+  if (processor != null) {
+    processor.set_timing(selectTiming.value);
+  }
+  processorSpeed.disabled = selectTiming.value == "vip";
+}
+
+const selectPersistence = document.getElementById("selectPersistence");
+selectPersistence.onchange = function() {
+  if (processor != null) {
+    processor.set_persistence(selectPersistence.value);
+  }
+  redrawAll = true;
+}
+
 const processorSpeed = document.getElementById("processorSpeed");
 processorSpeed.value = 15;
 processorSpeed.oninput = function() {
@@ -100,43 +156,68 @@ const key_map = new Map([
 ]);
 
 const renderLoop = () => {
-  if (processor != null && !processor.halt && !paused) {
-    for(var i = 0; i < speed; i++) {
-      processor.tick();
-    }
+  if (processor != null && rewinding && !paused) {
+    processor.rewind_frame();
+    drawScreen();
+  } else if (processor != null && !processor.halt && !paused) {
+    const stop = processor.run_frame(speed);
     drawScreen();
+    playAudio();
+    if(stop && !processor.halt) {
+      pauseButton.onclick();
+      stateButton.innerHTML = `Paused: ${stop}`;
+    }
     if(processor.halt) {
       stateButton.classList.remove("btn-success");
       stateButton.classList.add("btn-light");
-      stateButton.innerHTML = "Halted";
+      stateButton.innerHTML = processor.halt_reason ? `Halted: ${processor.halt_reason}` : "Halted";
       processor = null;
     }
   }
   requestAnimationFrame(renderLoop);
 };
 
+// The core renders the display scaled to the canvas, which is blitted as is.
+// Without persistence only the changed rectangles are copied to the canvas.
 const drawScreen = () => {
-    const screenPtr = processor.screen();
-    const screen = new Uint8Array(memory.buffer, screenPtr, 256);
-  
-    ctx.beginPath();
-
-    for (let y = 0; y < 32; y++) {
-      for (let x = 0; x < 8; x++) {
-        let byte = screen[y*8+x];
-        for (let bit = 0; bit < 8; bit++) {
-          let color = (byte & Math.pow(2, 7-bit)) >= 1;
-          ctx.fillStyle = color ? WHITE_COLOR : BLACK_COLOR;
-          ctx.fillRect(
-            (x*8+bit) * PIXEL_SIZE, 
-            y * PIXEL_SIZE, 
-            PIXEL_SIZE, 
-            PIXEL_SIZE);
-        }
-      }
+    const persistent = selectPersistence.value != "off";
+    if (!persistent && !redrawAll && !processor.frame_changed()) {
+      return;
+    }
+    const rects = processor.take_dirty_rects();
+    const scale = canvas.width / processor.screen_width();
+    processor.set_scale(scale);
+    const imagePtr = processor.render();
+    const width = processor.image_width();
+    const height = processor.image_height();
+    const image = new ImageData(new Uint8ClampedArray(memory.buffer, imagePtr, width * height * 4), width, height);
+    if (persistent || redrawAll) {
+      ctx.putImageData(image, 0, 0);
+      redrawAll = false;
+      return;
+    }
+    for (let n = 0; n < rects.length; n += 4) {
+      ctx.putImageData(image, 0, 0, rects[n] * scale, rects[n + 1] * scale, rects[n + 2] * scale, rects[n + 3] * scale);
     }
-  
-    ctx.stroke();
+};
+
+// Queues one frame of buzzer samples behind the previously queued ones.
+const playAudio = () => {
+  if (audioContext == null) {
+    return;
+  }
+  const frameSamples = Math.round(audioContext.sampleRate / 60);
+  const samples = new Float32Array(frameSamples);
+  processor.fill_audio(audioContext.sampleRate, samples);
+
+  const buffer = audioContext.createBuffer(1, frameSamples, audioContext.sampleRate);
+  buffer.copyToChannel(samples, 0);
+  const source = audioContext.createBufferSource();
+  source.buffer = buffer;
+  source.connect(audioContext.destination);
+  audioTime = Math.max(audioTime, audioContext.currentTime);
+  source.start(audioTime);
+  audioTime += buffer.duration;
 };
 
 const insertRom = (rom) => {
@@ -150,12 +231,23 @@ const startProcessor = () => {
   stateButton.classList.remove("btn-light");
   stateButton.classList.add("btn", "btn-success", "disabled");
   stateButton.innerHTML = "Running";
-  processor = Processor.new(loadedRom);
+  processor = Processor.with_platform(loadedRom, selectPlatform.value);
+  processor.set_timing(selectTiming.value);
+  processor.set_persistence(selectPersistence.value);
 }
 
 window.addEventListener(
   "keydown",
   (event) => {
+    // Browsers only allow audio after a user gesture.
+    if (audioContext == null) {
+      audioContext = new AudioContext();
+    }
+    // Holding backspace rewinds one frame per frame.
+    if (event.key == "Backspace") {
+      rewinding = true;
+      event.preventDefault();
+    }
     if(processor) {
       let key_id = key_map.get(event.key);
       if (key_id != null) {
@@ -169,6 +261,9 @@ window.addEventListener(
 window.addEventListener(
   "keyup",
   (event) => {
+    if (event.key == "Backspace") {
+      rewinding = false;
+    }
     if(processor) {
       let key_id = key_map.get(event.key);
       if (key_id != null) {